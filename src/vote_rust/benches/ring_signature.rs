@@ -0,0 +1,136 @@
+//! Compute units of ring signature ballots by ring size
+//!
+//! `cargo build-sbf && SBF_OUT_DIR=target/deploy cargo bench --features prover --bench ring_signature`
+//!
+//! Compute units are only metered when the program runs as SBF, so this
+//! loads the SBF build into a local bank and simulates a `CastRingVote` for
+//! each ring size. It reports the units the transaction consumed, the units
+//! spent verifying the signature, from the compute units `CastRingVote`
+//! logs before and after, and the size of the transaction, which has to fit
+//! a packet.
+
+use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+use solana_bpf_simplest::{
+    instruction::cast_ring_vote,
+    ring::{secret_scalar, sign, MAX_RING_SIZE},
+    state::{find_voter_record_address, ElectionState, Eligibility, VoterRecord},
+};
+use solana_program_test::{find_file, tokio::runtime::Runtime, ProgramTest};
+use solana_sdk::{
+    account::Account, compute_budget::ComputeBudgetInstruction, packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey, signature::Signer, transaction::Transaction,
+};
+
+const RING_SIZES: [usize; 5] = [1, 2, 4, 8, MAX_RING_SIZE];
+
+// the most a transaction may request
+const COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+fn ring(size: usize) -> (Vec<Scalar>, Vec<Pubkey>) {
+    let secrets: Vec<Scalar> = (0..size)
+        .map(|index| secret_scalar(&[index as u8 + 1; 32]))
+        .collect();
+    let keys = secrets
+        .iter()
+        .map(|secret| {
+            Pubkey::new_from_array((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+        })
+        .collect();
+    (secrets, keys)
+}
+
+fn account(data: Vec<u8>, owner: Pubkey) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        ..Account::default()
+    }
+}
+
+// compute units left at each `sol_log_compute_units`
+fn units_remaining(logs: &[String]) -> Vec<u64> {
+    logs.iter()
+        .filter_map(|line| {
+            line.strip_prefix("Program consumption: ")?
+                .strip_suffix(" units remaining")?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+fn main() {
+    // the native build would run, but without metering a single unit
+    assert!(
+        find_file("solana_bpf_simplest.so").is_some(),
+        "solana_bpf_simplest.so not found, build it with `cargo build-sbf` and point SBF_OUT_DIR at it"
+    );
+
+    let program_id = Pubkey::new_unique();
+    let election = Pubkey::new_unique();
+    let largest = RING_SIZES[RING_SIZES.len() - 1];
+    let (secrets, keys) = ring(largest);
+
+    let mut program_test = ProgramTest::new("solana_bpf_simplest", program_id, None);
+    program_test.prefer_bpf(true);
+    let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+    state.eligibility = Eligibility::AnonymousRegistry;
+    let mut data = vec![0; ElectionState::space(3)];
+    state.pack(&mut data).unwrap();
+    program_test.add_account(election, account(data, program_id));
+    for voter in keys.iter() {
+        let (address, bump_seed) = find_voter_record_address(&program_id, &election, voter);
+        let mut data = vec![0; VoterRecord::LEN];
+        VoterRecord::new(election, *voter, 1, bump_seed)
+            .pack(&mut data)
+            .unwrap();
+        program_test.add_account(address, account(data, program_id));
+    }
+
+    let runtime = Runtime::new().unwrap();
+    let (mut client, payer, blockhash) = runtime.block_on(program_test.start());
+
+    println!(
+        "{:>9} {:>14} {:>14} {:>9}",
+        "ring size", "total units", "verify units", "tx bytes"
+    );
+    for size in RING_SIZES.iter() {
+        let ring = &keys[..*size];
+        let signature = sign(ring, &secrets[0], election.as_ref(), &[0]).unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(COMPUTE_UNIT_LIMIT),
+                cast_ring_vote(&program_id, &election, &payer.pubkey(), ring, 0, signature),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer],
+            blockhash,
+        );
+        // a signature count and the signatures precede the message
+        let bytes = 1 + 64 + transaction.message_data().len();
+
+        let simulation = runtime
+            .block_on(client.simulate_transaction(transaction))
+            .unwrap();
+        let details = simulation.simulation_details.unwrap();
+        let verify_units = match units_remaining(&details.logs)[..] {
+            [before, after] => (before - after).to_string(),
+            _ => "-".to_string(),
+        };
+        let result = match simulation.result {
+            Some(Ok(())) => String::new(),
+            Some(Err(error)) => format!(" ({})", error),
+            None => " (not run)".to_string(),
+        };
+        let oversized = if bytes > PACKET_DATA_SIZE {
+            " (too large)"
+        } else {
+            ""
+        };
+        println!(
+            "{:>9} {:>14} {:>14} {:>9}{}{}",
+            size, details.units_consumed, verify_units, bytes, oversized, result
+        );
+    }
+}
@@ -0,0 +1,762 @@
+//! Anonymous voting by proof of group membership, in the style of Semaphore
+//!
+//! Each member of the group holds two secrets, an identity nullifier and a
+//! trapdoor, and publishes its identity commitment, their Poseidon hash over
+//! the BN254 scalar field. The election stores the root of a Merkle tree of
+//! the commitments, hashed with Poseidon as well, with a zero leaf for every
+//! free slot. To vote, a member proves with a Groth16 proof that it knows
+//! the secrets behind one of the leaves, without revealing which, and
+//! publishes the nullifier hash `Poseidon(identity_nullifier,
+//! external_nullifier)`. The external nullifier is derived from the election
+//! address, so a member has exactly one nullifier hash per election and
+//! nullifier hashes can't be linked across elections. The program records
+//! spent nullifier hashes and rejects a second ballot with the same one.
+//!
+//! The public inputs of the circuit are, in order, the group root, the
+//! nullifier hash, the candidate voted for and the external nullifier, so a
+//! proof can't be replayed for another candidate or election. Field elements
+//! are encoded as 32 big endian bytes, curve points uncompressed in the big
+//! endian encoding of the `alt_bn128` syscalls (EIP-197), `x` then `y` and
+//! the imaginary part of each G2 coordinate first.
+//!
+//! The proof is checked with the `alt_bn128` syscalls rather than in program
+//! code: 4 multiplications and 4 additions to fold the public inputs into
+//! the verifying key, at 3,840 and 334 compute units each, then a pairing
+//! check of 4 pairs at 72,844, about 89,540 compute units in total, well
+//! within the default budget of an instruction. Checking the points of a
+//! verifying key when initializing an election takes another 62,727. The
+//! circuit and the prover are only built for tests and with the `prover`
+//! feature.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    alt_bn128::prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing},
+    hash::hashv,
+    pubkey::Pubkey,
+};
+
+const EXTERNAL_NULLIFIER_DOMAIN: &[u8] = b"vote/anonymous/external-nullifier";
+
+const G1_LEN: usize = 64;
+const G2_LEN: usize = 128;
+
+// modulus of the BN254 base field, big endian
+const BASE_FIELD_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d, 0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// Number of public inputs of the membership circuit
+pub const PUBLIC_INPUTS: usize = 4;
+
+/// Size of a verifying key of the membership circuit: `alpha` in G1,
+/// `beta`, `gamma` and `delta` in G2, then a G1 point per public input and
+/// one more
+pub const VERIFYING_KEY_LEN: usize = G1_LEN + 3 * G2_LEN + (PUBLIC_INPUTS + 1) * G1_LEN;
+
+/// Groth16 proof of membership
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct MembershipProof {
+    /// The `A` point in G1
+    pub a: [u8; G1_LEN],
+    /// The `B` point in G2
+    pub b: [u8; G2_LEN],
+    /// The `C` point in G1
+    pub c: [u8; G1_LEN],
+}
+
+impl MembershipProof {
+    /// Serialized size
+    pub const LEN: usize = G1_LEN + G2_LEN + G1_LEN;
+}
+
+/// Encodes a field element as 32 big endian bytes
+pub fn field_to_bytes(element: &Fr) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(&element.into_bigint().to_bytes_be());
+    bytes
+}
+
+/// Decodes a field element from 32 big endian bytes, rejecting values not
+/// below the field modulus so every element has a single encoding
+pub fn field_from_bytes(bytes: &[u8; 32]) -> Option<Fr> {
+    let element = Fr::from_be_bytes_mod_order(bytes);
+    if field_to_bytes(&element) == *bytes {
+        Some(element)
+    } else {
+        None
+    }
+}
+
+/// External nullifier of an election, a hash of its address truncated to
+/// fit the field
+pub fn external_nullifier(election: &Pubkey) -> Fr {
+    let hash = hashv(&[EXTERNAL_NULLIFIER_DOMAIN, election.as_ref()]);
+    Fr::from_be_bytes_mod_order(&hash.as_ref()[..31])
+}
+
+// Points of a verifying key, in the order they are stored
+struct VerifyingKey<'a> {
+    alpha: &'a [u8],
+    beta: &'a [u8],
+    gamma: &'a [u8],
+    delta: &'a [u8],
+    inputs: Vec<&'a [u8]>,
+}
+
+fn parse_verifying_key(bytes: &[u8]) -> Option<VerifyingKey<'_>> {
+    if bytes.len() != VERIFYING_KEY_LEN {
+        return None;
+    }
+    let (alpha, rest) = bytes.split_at(G1_LEN);
+    let (beta, rest) = rest.split_at(G2_LEN);
+    let (gamma, rest) = rest.split_at(G2_LEN);
+    let (delta, inputs) = rest.split_at(G2_LEN);
+    Some(VerifyingKey {
+        alpha,
+        beta,
+        gamma,
+        delta,
+        inputs: inputs.chunks(G1_LEN).collect(),
+    })
+}
+
+// Negates a G1 point by replacing `y` with `q - y`, leaving the point at
+// infinity as it is. A `y` past the modulus stays out of range and is
+// rejected by the syscalls.
+fn negate_g1(point: &[u8]) -> [u8; G1_LEN] {
+    let mut negated = [0; G1_LEN];
+    negated.copy_from_slice(point);
+    if point[32..].iter().all(|byte| *byte == 0) {
+        return negated;
+    }
+    let mut borrow = false;
+    for i in (0..32).rev() {
+        let (difference, first) = BASE_FIELD_MODULUS[i].overflowing_sub(point[32 + i]);
+        let (difference, second) = difference.overflowing_sub(borrow as u8);
+        negated[32 + i] = difference;
+        borrow = first || second;
+    }
+    negated
+}
+
+/// Checks that `bytes` hold a verifying key with the public inputs of the
+/// membership circuit and points on the curve
+pub fn is_valid_verifying_key(bytes: &[u8]) -> bool {
+    let key = match parse_verifying_key(bytes) {
+        Some(key) => key,
+        None => return false,
+    };
+    // the syscalls reject points off the curve or, in G2, outside the
+    // subgroup, pairing each G2 point with the point at infinity checks
+    // them all in a single call
+    let g1_valid = std::iter::once(key.alpha)
+        .chain(key.inputs.iter().copied())
+        .all(|point| alt_bn128_addition(&[point, &[0; G1_LEN]].concat()).is_ok());
+    let g2_pairs: Vec<u8> = [key.beta, key.gamma, key.delta]
+        .iter()
+        .flat_map(|point| [&[0; G1_LEN][..], point].concat())
+        .collect();
+    g1_valid && alt_bn128_pairing(&g2_pairs).is_ok()
+}
+
+/// Checks a proof that a member of the group with `root` voted for
+/// `candidate` in `election` under `nullifier_hash`
+pub fn verify_membership(
+    verifying_key: &[u8],
+    root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    candidate: u8,
+    election: &Pubkey,
+    proof: &MembershipProof,
+) -> bool {
+    if field_from_bytes(root).is_none() || field_from_bytes(nullifier_hash).is_none() {
+        return false;
+    }
+    let key = match parse_verifying_key(verifying_key) {
+        Some(key) => key,
+        None => return false,
+    };
+    let inputs = [
+        *root,
+        *nullifier_hash,
+        field_to_bytes(&Fr::from(candidate)),
+        field_to_bytes(&external_nullifier(election)),
+    ];
+
+    // the inputs folded into the key, `IC_0 + sum(input_i * IC_i+1)`
+    let mut folded = key.inputs[0].to_vec();
+    for (input, point) in inputs.iter().zip(&key.inputs[1..]) {
+        let product = match alt_bn128_multiplication(&[point, &input[..]].concat()) {
+            Ok(product) => product,
+            Err(_) => return false,
+        };
+        folded = match alt_bn128_addition(&[folded, product].concat()) {
+            Ok(sum) => sum,
+            Err(_) => return false,
+        };
+    }
+
+    // e(-A, B) * e(alpha, beta) * e(folded, gamma) * e(C, delta) == 1
+    let pairs = [
+        &negate_g1(&proof.a)[..],
+        &proof.b,
+        key.alpha,
+        key.beta,
+        &folded[..],
+        key.gamma,
+        &proof.c,
+        key.delta,
+    ]
+    .concat();
+    match alt_bn128_pairing(&pairs) {
+        Ok(result) => result.last() == Some(&1),
+        Err(_) => false,
+    }
+}
+
+#[cfg(any(test, feature = "prover"))]
+pub use prover::*;
+
+#[cfg(any(test, feature = "prover"))]
+mod prover {
+    use super::*;
+    use ark_bn254::{Bn254, Fq, G1Affine, G2Affine};
+    use ark_ff::{UniformRand, Zero};
+    use ark_groth16::{Groth16, ProvingKey};
+    use ark_r1cs_std::{fields::fp::FpVar, prelude::*};
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+    use ark_snark::SNARK;
+    use ark_std::rand::{CryptoRng, RngCore};
+    use light_poseidon::{
+        parameters::bn254_x5::get_poseidon_parameters, Poseidon, PoseidonHasher, PoseidonParameters,
+    };
+
+    /// Poseidon hash of two field elements, as in circom
+    pub fn poseidon(left: &Fr, right: &Fr) -> Fr {
+        Poseidon::<Fr>::new_circom(2)
+            .unwrap()
+            .hash(&[*left, *right])
+            .unwrap()
+    }
+
+    /// Secrets of a group member
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Identity {
+        /// Secret behind every nullifier hash of the member
+        pub nullifier: Fr,
+        /// Second secret, keeps the commitment from being linked to
+        /// nullifier hashes
+        pub trapdoor: Fr,
+    }
+
+    impl Identity {
+        /// Generates a new random identity
+        pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+            Self {
+                nullifier: Fr::rand(rng),
+                trapdoor: Fr::rand(rng),
+            }
+        }
+
+        /// Leaf of the member in the group tree
+        pub fn commitment(&self) -> Fr {
+            poseidon(&self.nullifier, &self.trapdoor)
+        }
+
+        /// Nullifier hash of the member's ballot in an election
+        pub fn nullifier_hash(&self, election: &Pubkey) -> Fr {
+            poseidon(&self.nullifier, &external_nullifier(election))
+        }
+    }
+
+    /// Merkle tree of `depth` levels over the identity commitments of a
+    /// group, padded with zero leaves
+    #[derive(Clone, Debug)]
+    pub struct Group {
+        depth: usize,
+        // nodes of each level that have a non-zero leaf below them, from
+        // the leaves up, followed by the hash of an empty subtree
+        levels: Vec<(Vec<Fr>, Fr)>,
+    }
+
+    impl Group {
+        /// Builds the tree of `commitments`, `None` if they don't fit
+        pub fn new(depth: usize, commitments: Vec<Fr>) -> Option<Self> {
+            if depth > 32 || commitments.len() > 1 << depth {
+                return None;
+            }
+
+            let mut levels = Vec::with_capacity(depth + 1);
+            let mut nodes = commitments;
+            let mut zero = Fr::zero();
+            for _ in 0..depth {
+                let parents = nodes
+                    .chunks(2)
+                    .map(|pair| poseidon(&pair[0], pair.get(1).unwrap_or(&zero)))
+                    .collect();
+                let parent_zero = poseidon(&zero, &zero);
+                levels.push((nodes, zero));
+                nodes = parents;
+                zero = parent_zero;
+            }
+            levels.push((nodes, zero));
+            Some(Self { depth, levels })
+        }
+
+        /// Number of levels below the root
+        pub fn depth(&self) -> usize {
+            self.depth
+        }
+
+        /// The group root stored in the election
+        pub fn root(&self) -> Fr {
+            let (nodes, zero) = &self.levels[self.depth];
+            nodes.first().copied().unwrap_or(*zero)
+        }
+
+        /// Sibling of each node from the leaf at `index` up to the root,
+        /// paired with whether the node is a right child
+        pub fn path(&self, index: usize) -> Vec<(Fr, bool)> {
+            self.levels[..self.depth]
+                .iter()
+                .enumerate()
+                .map(|(level, (nodes, zero))| {
+                    let node = index >> level;
+                    (*nodes.get(node ^ 1).unwrap_or(zero), node & 1 == 1)
+                })
+                .collect()
+        }
+    }
+
+    /// Circuit proving that the prover knows the identity behind a leaf of
+    /// the group and computed the nullifier hash from it
+    #[derive(Clone, Debug)]
+    pub struct MembershipCircuit {
+        root: Fr,
+        nullifier_hash: Fr,
+        candidate: Fr,
+        external_nullifier: Fr,
+        identity: Identity,
+        path: Vec<(Fr, bool)>,
+    }
+
+    impl MembershipCircuit {
+        /// Circuit of a vote for `candidate` in `election` by the member at
+        /// `index` of the group
+        pub fn new(
+            identity: &Identity,
+            group: &Group,
+            index: usize,
+            election: &Pubkey,
+            candidate: u8,
+        ) -> Self {
+            Self {
+                root: group.root(),
+                nullifier_hash: identity.nullifier_hash(election),
+                candidate: Fr::from(candidate),
+                external_nullifier: external_nullifier(election),
+                identity: identity.clone(),
+                path: group.path(index),
+            }
+        }
+
+        /// Circuit with placeholder values, enough to generate the keys for
+        /// groups of `depth` levels
+        pub fn blank(depth: usize) -> Self {
+            Self {
+                root: Fr::zero(),
+                nullifier_hash: Fr::zero(),
+                candidate: Fr::zero(),
+                external_nullifier: Fr::zero(),
+                identity: Identity {
+                    nullifier: Fr::zero(),
+                    trapdoor: Fr::zero(),
+                },
+                path: vec![(Fr::zero(), false); depth],
+            }
+        }
+    }
+
+    // Poseidon permutation of `[0, left, right]` in constraints, matching
+    // `poseidon`
+    fn poseidon_gadget(
+        params: &PoseidonParameters<Fr>,
+        left: &FpVar<Fr>,
+        right: &FpVar<Fr>,
+    ) -> Result<FpVar<Fr>, SynthesisError> {
+        let width = params.width;
+        let half_rounds = params.full_rounds / 2;
+        let mut state = vec![FpVar::zero(), left.clone(), right.clone()];
+
+        for round in 0..params.full_rounds + params.partial_rounds {
+            for (i, element) in state.iter_mut().enumerate() {
+                *element += params.ark[round * width + i];
+            }
+            let is_full = round < half_rounds || round >= half_rounds + params.partial_rounds;
+            let sboxes = if is_full { width } else { 1 };
+            for element in state.iter_mut().take(sboxes) {
+                let square = element.square()?;
+                *element = square.square()? * &*element;
+            }
+            state = params
+                .mds
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .zip(&state)
+                        .fold(FpVar::zero(), |sum, (m, element)| sum + element * *m)
+                })
+                .collect();
+        }
+
+        Ok(state.swap_remove(0))
+    }
+
+    impl ConstraintSynthesizer<Fr> for MembershipCircuit {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+            let params =
+                get_poseidon_parameters::<Fr>(3).map_err(|_| SynthesisError::Unsatisfiable)?;
+
+            let root = FpVar::new_input(cs.clone(), || Ok(self.root))?;
+            let nullifier_hash = FpVar::new_input(cs.clone(), || Ok(self.nullifier_hash))?;
+            let candidate = FpVar::new_input(cs.clone(), || Ok(self.candidate))?;
+            let external_nullifier = FpVar::new_input(cs.clone(), || Ok(self.external_nullifier))?;
+
+            let identity_nullifier =
+                FpVar::new_witness(cs.clone(), || Ok(self.identity.nullifier))?;
+            let trapdoor = FpVar::new_witness(cs.clone(), || Ok(self.identity.trapdoor))?;
+
+            let mut node = poseidon_gadget(&params, &identity_nullifier, &trapdoor)?;
+            for (sibling, is_right) in self.path {
+                let sibling = FpVar::new_witness(cs.clone(), || Ok(sibling))?;
+                let is_right = Boolean::new_witness(cs.clone(), || Ok(is_right))?;
+                let left = is_right.select(&sibling, &node)?;
+                let right = is_right.select(&node, &sibling)?;
+                node = poseidon_gadget(&params, &left, &right)?;
+            }
+            node.enforce_equal(&root)?;
+
+            poseidon_gadget(&params, &identity_nullifier, &external_nullifier)?
+                .enforce_equal(&nullifier_hash)?;
+
+            // an input outside every constraint would not be bound by the
+            // proof, letting anyone swap the candidate
+            let _ = candidate.square()?;
+
+            Ok(())
+        }
+    }
+
+    fn base_field_to_bytes(element: &Fq) -> Vec<u8> {
+        element.into_bigint().to_bytes_be()
+    }
+
+    /// Encodes a G1 point for the `alt_bn128` syscalls, the point at
+    /// infinity as zeroes
+    pub fn g1_to_bytes(point: &G1Affine) -> [u8; G1_LEN] {
+        let mut bytes = [0; G1_LEN];
+        if !point.infinity {
+            bytes.copy_from_slice(
+                &[base_field_to_bytes(&point.x), base_field_to_bytes(&point.y)].concat(),
+            );
+        }
+        bytes
+    }
+
+    /// Encodes a G2 point for the `alt_bn128` syscalls, the point at
+    /// infinity as zeroes
+    pub fn g2_to_bytes(point: &G2Affine) -> [u8; G2_LEN] {
+        let mut bytes = [0; G2_LEN];
+        if !point.infinity {
+            bytes.copy_from_slice(
+                &[
+                    base_field_to_bytes(&point.x.c1),
+                    base_field_to_bytes(&point.x.c0),
+                    base_field_to_bytes(&point.y.c1),
+                    base_field_to_bytes(&point.y.c0),
+                ]
+                .concat(),
+            );
+        }
+        bytes
+    }
+
+    /// Generates the proving key and the verifying key of the circuit for
+    /// groups of `depth` levels, the latter as stored in the election
+    pub fn setup<R: RngCore + CryptoRng>(
+        depth: usize,
+        rng: &mut R,
+    ) -> Result<(ProvingKey<Bn254>, Vec<u8>), SynthesisError> {
+        let (proving_key, verifying_key) =
+            Groth16::<Bn254>::circuit_specific_setup(MembershipCircuit::blank(depth), rng)?;
+        let mut bytes = g1_to_bytes(&verifying_key.alpha_g1).to_vec();
+        for point in [
+            verifying_key.beta_g2,
+            verifying_key.gamma_g2,
+            verifying_key.delta_g2,
+        ]
+        .iter()
+        {
+            bytes.extend_from_slice(&g2_to_bytes(point));
+        }
+        for point in verifying_key.gamma_abc_g1.iter() {
+            bytes.extend_from_slice(&g1_to_bytes(point));
+        }
+        Ok((proving_key, bytes))
+    }
+
+    /// Proves a vote for `candidate` in `election` by the member at `index`
+    /// of the group
+    pub fn prove_membership<R: RngCore + CryptoRng>(
+        proving_key: &ProvingKey<Bn254>,
+        identity: &Identity,
+        group: &Group,
+        index: usize,
+        election: &Pubkey,
+        candidate: u8,
+        rng: &mut R,
+    ) -> Result<MembershipProof, SynthesisError> {
+        let circuit = MembershipCircuit::new(identity, group, index, election, candidate);
+        let proof = Groth16::<Bn254>::prove(proving_key, circuit, rng)?;
+        Ok(MembershipProof {
+            a: g1_to_bytes(&proof.a),
+            b: g2_to_bytes(&proof.b),
+            c: g1_to_bytes(&proof.c),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ark_ff::Zero;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    // keep the circuit small, proving time grows with the depth
+    const DEPTH: usize = 3;
+
+    #[test]
+    fn test_field_bytes() {
+        let element = Fr::from(0x0102u64);
+        let bytes = field_to_bytes(&element);
+        assert_eq!(&bytes[30..], &[1, 2]);
+        assert_eq!(field_from_bytes(&bytes), Some(element));
+
+        // the modulus and anything above has no canonical encoding
+        let mut modulus = [0; 32];
+        modulus.copy_from_slice(&Fr::MODULUS.to_bytes_be());
+        assert_eq!(field_from_bytes(&modulus), None);
+        assert_eq!(field_from_bytes(&[0xff; 32]), None);
+    }
+
+    #[test]
+    fn test_external_nullifier() {
+        let election = Pubkey::new_unique();
+        assert_eq!(external_nullifier(&election), external_nullifier(&election));
+        assert_ne!(
+            external_nullifier(&election),
+            external_nullifier(&Pubkey::new_unique())
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let members: Vec<Identity> = (0..3).map(|_| Identity::random(rng)).collect();
+        let group = Group::new(DEPTH, members.iter().map(Identity::commitment).collect()).unwrap();
+
+        // every path leads from the member's leaf to the root, including
+        // the free slots
+        for index in 0..1 << DEPTH {
+            let leaf = members
+                .get(index)
+                .map(Identity::commitment)
+                .unwrap_or_else(Fr::zero);
+            let root = group
+                .path(index)
+                .iter()
+                .fold(leaf, |node, (sibling, is_right)| {
+                    if *is_right {
+                        poseidon(sibling, &node)
+                    } else {
+                        poseidon(&node, sibling)
+                    }
+                });
+            assert_eq!(root, group.root());
+        }
+
+        // an empty group hashes zero leaves only
+        let empty = Group::new(1, vec![]).unwrap();
+        assert_eq!(empty.root(), poseidon(&Fr::zero(), &Fr::zero()));
+
+        assert!(Group::new(1, vec![Fr::zero(); 3]).is_none());
+    }
+
+    #[test]
+    fn test_circuit() {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let members: Vec<Identity> = (0..3).map(|_| Identity::random(rng)).collect();
+        let group = Group::new(DEPTH, members.iter().map(Identity::commitment).collect()).unwrap();
+        let election = Pubkey::new_unique();
+
+        let is_satisfied = |circuit: MembershipCircuit| {
+            let cs = ConstraintSystem::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+
+        for (index, member) in members.iter().enumerate() {
+            assert!(is_satisfied(MembershipCircuit::new(
+                member, &group, index, &election, 1
+            )));
+        }
+
+        // an outsider has no leaf
+        let outsider = Identity::random(rng);
+        assert!(!is_satisfied(MembershipCircuit::new(
+            &outsider, &group, 0, &election, 1
+        )));
+
+        // a member can't claim another member's leaf
+        assert!(!is_satisfied(MembershipCircuit::new(
+            &members[0],
+            &group,
+            1,
+            &election,
+            1
+        )));
+    }
+
+    #[test]
+    fn test_membership_proof() {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let members: Vec<Identity> = (0..3).map(|_| Identity::random(rng)).collect();
+        let group = Group::new(DEPTH, members.iter().map(Identity::commitment).collect()).unwrap();
+        let root = field_to_bytes(&group.root());
+        let election = Pubkey::new_unique();
+
+        let (proving_key, verifying_key) = setup(DEPTH, rng).unwrap();
+        assert_eq!(verifying_key.len(), VERIFYING_KEY_LEN);
+        assert!(is_valid_verifying_key(&verifying_key));
+        assert!(!is_valid_verifying_key(&verifying_key[1..]));
+
+        // alpha, beta and the first input point moved off the curve
+        for offset in [0, G1_LEN, G1_LEN + 3 * G2_LEN].iter() {
+            let mut off_curve = verifying_key.clone();
+            off_curve[offset + 31] ^= 1;
+            assert!(!is_valid_verifying_key(&off_curve));
+        }
+
+        let member = &members[1];
+        let nullifier_hash = field_to_bytes(&member.nullifier_hash(&election));
+        let proof = prove_membership(&proving_key, member, &group, 1, &election, 2, rng).unwrap();
+        assert_eq!(proof.try_to_vec().unwrap().len(), MembershipProof::LEN);
+        assert!(verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &election,
+            &proof
+        ));
+
+        // the proof is bound to each of its public inputs
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            1,
+            &election,
+            &proof
+        ));
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &Pubkey::new_unique(),
+            &proof
+        ));
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &field_to_bytes(&members[0].nullifier_hash(&election)),
+            2,
+            &election,
+            &proof
+        ));
+        assert!(!verify_membership(
+            &verifying_key,
+            &field_to_bytes(&Fr::from(7u64)),
+            &nullifier_hash,
+            2,
+            &election,
+            &proof
+        ));
+
+        // a nullifier hash past the modulus would dodge the spent record
+        let mut aliased = Fr::MODULUS;
+        aliased.add_with_carry(&member.nullifier_hash(&election).into_bigint());
+        let mut aliased_bytes = [0; 32];
+        aliased_bytes.copy_from_slice(&aliased.to_bytes_be());
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &aliased_bytes,
+            2,
+            &election,
+            &proof
+        ));
+
+        // keys of another setup don't verify it
+        let (_, other_key) = setup(DEPTH, rng).unwrap();
+        assert!(!verify_membership(
+            &other_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &election,
+            &proof
+        ));
+
+        // negating the proof's A is what the pairing check relies on
+        assert_eq!(
+            alt_bn128_addition(&[&proof.a[..], &negate_g1(&proof.a)].concat()).unwrap(),
+            vec![0; G1_LEN]
+        );
+        assert_eq!(negate_g1(&[0; G1_LEN]), [0; G1_LEN]);
+
+        let mut tampered = proof;
+        tampered.a = negate_g1(&proof.a);
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &election,
+            &tampered
+        ));
+        tampered.a = proof.c;
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &election,
+            &tampered
+        ));
+        tampered.a = [0xff; 64];
+        assert!(!verify_membership(
+            &verifying_key,
+            &root,
+            &nullifier_hash,
+            2,
+            &election,
+            &tampered
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+//! Builds the eligibility tree of a Merkle-root election
+//!
+//! Reads a CSV file with the base58 pubkey of one eligible voter and,
+//! optionally, the weight of its vote on each line, then prints the root to
+//! pass in `ElectionConfig` and the weight and proof each voter sends with
+//! `CastVoteWithProof`. Missing weights default to 1. Empty lines, lines
+//! starting with `#` and a leading `pubkey` header are skipped.
+//!
+//! Usage: merkle_tree <voters.csv>
+
+use std::{collections::BTreeMap, env, fs, process, str::FromStr};
+
+use solana_bpf_simplest::merkle::{leaf_hash, MerkleTree};
+use solana_program::pubkey::Pubkey;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read_voters(path: &str) -> Result<BTreeMap<Pubkey, u64>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut voters = BTreeMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let mut fields = line.split(',').map(str::trim);
+        let field = fields.next().unwrap_or("");
+        if field.is_empty() || field.starts_with('#') || (number == 0 && field == "pubkey") {
+            continue;
+        }
+        let error = |what| format!("{}:{}: invalid {}", path, number + 1, what);
+
+        let voter = Pubkey::from_str(field).map_err(|_| error("pubkey"))?;
+        let weight = match fields.next() {
+            Some(weight) if !weight.is_empty() => weight.parse().map_err(|_| error("weight"))?,
+            _ => 1,
+        };
+        if weight == 0 {
+            return Err(error("weight"));
+        }
+        if voters.insert(voter, weight).is_some() {
+            return Err(format!(
+                "{}:{}: duplicate voter {}",
+                path,
+                number + 1,
+                voter
+            ));
+        }
+    }
+
+    if voters.is_empty() {
+        return Err(format!("{}: no voters found", path));
+    }
+    Ok(voters)
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: merkle_tree <voters.csv>");
+            process::exit(2);
+        }
+    };
+
+    let voters = read_voters(&path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+
+    let tree = MerkleTree::new(
+        voters
+            .iter()
+            .map(|(voter, weight)| leaf_hash(voter, *weight))
+            .collect(),
+    );
+    println!("root,{}", hex(&tree.root()));
+    for (index, (voter, weight)) in voters.iter().enumerate() {
+        let proof: Vec<String> = tree.proof(index).iter().map(|node| hex(node)).collect();
+        println!("{},{},{}", voter, weight, proof.join(":"));
+    }
+}
@@ -0,0 +1,176 @@
+//! Relays signed ballots to the vote program and pays their fees
+//!
+//! Listens for `POST /ballots` with a JSON ballot request, see `relayer`,
+//! and answers 202 once the ballot decodes and its signature verifies, 400
+//! with the reason otherwise. Only ballots of the listed elections are
+//! relayed, others get 403, and each election gets up to
+//! `max-ballots-per-election` ballots, 429 after that. Accepted ballots are
+//! batched for a second and submitted with the sponsor keypair, retrying
+//! failures, and the outcome of each ballot is printed as
+//! `cast,<voter>,<nonce>,<transaction signature>` or
+//! `failed,<voter>,<nonce>,<error>`.
+//!
+//! Usage: relayer <program-id> <sponsor-keypair.json> <election,...> [max-ballots-per-election] [rpc-url] [listen-address]
+
+use std::{
+    env,
+    io::Read,
+    iter, process,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use serde_json::json;
+use solana_bpf_simplest::relayer::{BallotQuota, Refusal, RelayedBallot, Relayer};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, pubkey::Pubkey, signature::read_keypair_file,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8080";
+const DEFAULT_MAX_BALLOTS_PER_ELECTION: usize = 10_000;
+
+// how long accepted ballots wait for others to share their transactions
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// ballot requests are a few hundred bytes
+const MAX_BODY_LEN: u64 = 4096;
+
+fn respond(request: Request, status: u16, body: serde_json::Value) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        eprintln!("error: {}", e);
+    }
+}
+
+fn serve(server: Server, mut quota: BallotQuota, ballots: Sender<RelayedBallot>) {
+    for mut request in server.incoming_requests() {
+        if *request.method() != Method::Post || request.url() != "/ballots" {
+            respond(request, 404, json!({ "error": "not found" }));
+            continue;
+        }
+
+        let mut body = String::new();
+        if request
+            .as_reader()
+            .take(MAX_BODY_LEN)
+            .read_to_string(&mut body)
+            .is_err()
+        {
+            respond(request, 400, json!({ "error": "invalid body" }));
+            continue;
+        }
+
+        match RelayedBallot::from_json(&body) {
+            Ok(ballot) => {
+                match quota.admit(&ballot) {
+                    Ok(()) => {}
+                    Err(Refusal::UnknownElection) => {
+                        respond(request, 403, json!({ "error": "election not relayed" }));
+                        continue;
+                    }
+                    Err(Refusal::QuotaExceeded) => {
+                        respond(request, 429, json!({ "error": "election quota exceeded" }));
+                        continue;
+                    }
+                }
+
+                let accepted = json!({
+                    "voter": ballot.voter.to_string(),
+                    "election": ballot.ballot.election.to_string(),
+                    "nonce": ballot.ballot.nonce,
+                });
+                if ballots.send(ballot).is_err() {
+                    respond(request, 503, json!({ "error": "relayer stopped" }));
+                    return;
+                }
+                respond(request, 202, accepted);
+            }
+            Err(error) => respond(request, 400, json!({ "error": error })),
+        }
+    }
+}
+
+fn relay(mut relayer: Relayer<RpcClient>, ballots: Receiver<RelayedBallot>) {
+    // wait for a first ballot, then take whatever else arrived meanwhile
+    while let Ok(first) = ballots.recv() {
+        thread::sleep(BATCH_INTERVAL);
+        let batch: Vec<_> = iter::once(first).chain(ballots.try_iter()).collect();
+
+        println!("Relaying {} ballots", batch.len());
+        let report = relayer.relay(batch);
+        for (ballot, signature) in report.cast.iter() {
+            println!(
+                "cast,{},{},{}",
+                ballot.voter, ballot.ballot.nonce, signature
+            );
+        }
+        for (ballot, error) in report.failed.iter() {
+            println!("failed,{},{},{}", ballot.voter, ballot.ballot.nonce, error);
+        }
+        println!(
+            "Completed: {} Failed: {}",
+            report.cast.len(),
+            report.failed.len()
+        );
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 3 || args.len() > 6 {
+        eprintln!("Usage: relayer <program-id> <sponsor-keypair.json> <election,...> [max-ballots-per-election] [rpc-url] [listen-address]");
+        process::exit(2);
+    }
+
+    let program_id = Pubkey::from_str(&args[0]).unwrap_or_else(|_| {
+        eprintln!("error: invalid program id {}", args[0]);
+        process::exit(1);
+    });
+    let sponsor = read_keypair_file(&args[1]).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", args[1], e);
+        process::exit(1);
+    });
+    let elections: Vec<Pubkey> = args[2]
+        .split(',')
+        .map(|election| {
+            Pubkey::from_str(election).unwrap_or_else(|_| {
+                eprintln!("error: invalid election {}", election);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let max_ballots = args.get(3).map_or(DEFAULT_MAX_BALLOTS_PER_ELECTION, |max| {
+        max.parse().unwrap_or_else(|_| {
+            eprintln!("error: invalid ballot count {}", max);
+            process::exit(1);
+        })
+    });
+    let rpc_url = args.get(4).map_or(DEFAULT_RPC_URL, String::as_str);
+    let listen_address = args.get(5).map_or(DEFAULT_LISTEN_ADDRESS, String::as_str);
+
+    let server = Server::http(listen_address).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", listen_address, e);
+        process::exit(1);
+    });
+
+    let client = RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+    let relayer = Relayer::new(client, program_id, sponsor);
+    println!(
+        "Relaying ballots from {} to {} paid by {}",
+        listen_address,
+        rpc_url,
+        relayer.sponsor()
+    );
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || relay(relayer, receiver));
+    serve(server, BallotQuota::new(elections, max_ballots), sender);
+}
@@ -0,0 +1,268 @@
+//! Curve25519 arithmetic for verifying proofs in the program
+//!
+//! Point arithmetic goes through the curve25519 syscalls, which take
+//! compressed points and canonical scalars and charge a fixed number of
+//! compute units per operation, whatever the operands. The little scalar
+//! arithmetic verification needs besides, subtraction modulo the group
+//! order, works on the encoded bytes, so no field or scalar multiplication
+//! runs in program code.
+//!
+//! Off the chain the same functions run on `curve25519_dalek`, so provers
+//! and tests get the results the program computes.
+
+use curve25519_dalek::{
+    constants::{ED25519_BASEPOINT_COMPRESSED, RISTRETTO_BASEPOINT_COMPRESSED},
+    scalar::Scalar,
+};
+use solana_program::hash::hashv;
+use solana_zk_token_sdk::curve25519::{
+    edwards::{self, PodEdwardsPoint},
+    ristretto::{self, PodRistrettoPoint},
+    scalar::PodScalar,
+};
+
+/// Compressed Ristretto basepoint `G`
+pub const RISTRETTO_BASEPOINT: [u8; 32] = RISTRETTO_BASEPOINT_COMPRESSED.0;
+
+/// Compressed ed25519 basepoint
+pub const ED25519_BASEPOINT: [u8; 32] = ED25519_BASEPOINT_COMPRESSED.0;
+
+/// Compressed Ristretto identity
+pub const RISTRETTO_IDENTITY: [u8; 32] = [0; 32];
+
+/// Compressed ed25519 identity, `y = 1`
+pub const ED25519_IDENTITY: [u8; 32] = [
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+// l, the order of the Ristretto group and of the ed25519 basepoint, little
+// endian
+const ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// `l - 1`, which takes a point of the prime order subgroup to its negation
+pub const ORDER_MINUS_ONE: [u8; 32] = [
+    0xec, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Scalar from a domain separated SHA-256 digest of `parts`. The top four
+/// bits are cleared, which leaves it below l without a reduction and
+/// negligibly far from uniform.
+pub fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut bytes = hashv(parts).to_bytes();
+    bytes[31] &= 0x0f;
+    Scalar::from_bits(bytes)
+}
+
+/// Checks that `scalar` is a canonical scalar encoding, below l
+pub fn is_canonical_scalar(scalar: &[u8; 32]) -> bool {
+    for (byte, order) in scalar.iter().rev().zip(ORDER.iter().rev()) {
+        if byte != order {
+            return byte < order;
+        }
+    }
+    false
+}
+
+/// `left - right` modulo l, both must be canonical
+pub fn subtract_scalars(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut difference = [0; 32];
+    let mut borrow = 0;
+    for (byte, (left, right)) in difference.iter_mut().zip(left.iter().zip(right.iter())) {
+        let value = *left as i16 - *right as i16 - borrow;
+        *byte = value as u8;
+        borrow = (value < 0) as i16;
+    }
+    // wrapped below zero, l brings it back into range
+    if borrow != 0 {
+        let mut carry = 0;
+        for (byte, order) in difference.iter_mut().zip(ORDER.iter()) {
+            let value = *byte as u16 + *order as u16 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+    }
+    difference
+}
+
+/// `-scalar` modulo l, `scalar` must be canonical
+pub fn negate_scalar(scalar: &[u8; 32]) -> [u8; 32] {
+    subtract_scalars(&[0; 32], scalar)
+}
+
+/// Checks that `point` is a valid compressed Ristretto point
+pub fn is_valid_ristretto(point: &[u8; 32]) -> bool {
+    ristretto::validate_ristretto(&PodRistrettoPoint(*point))
+}
+
+/// `left + right`, `None` if either is not a valid point
+pub fn add_ristretto(left: &[u8; 32], right: &[u8; 32]) -> Option<[u8; 32]> {
+    ristretto::add_ristretto(&PodRistrettoPoint(*left), &PodRistrettoPoint(*right))
+        .map(|point| point.0)
+}
+
+/// `left - right`, `None` if either is not a valid point
+pub fn subtract_ristretto(left: &[u8; 32], right: &[u8; 32]) -> Option<[u8; 32]> {
+    ristretto::subtract_ristretto(&PodRistrettoPoint(*left), &PodRistrettoPoint(*right))
+        .map(|point| point.0)
+}
+
+/// `scalar·point`, `None` if the scalar is not canonical or the point not
+/// valid
+pub fn multiply_ristretto(scalar: &[u8; 32], point: &[u8; 32]) -> Option<[u8; 32]> {
+    ristretto::multiply_ristretto(&PodScalar(*scalar), &PodRistrettoPoint(*point))
+        .map(|point| point.0)
+}
+
+/// Sum of `scalars[i]·points[i]`, `None` if a scalar is not canonical or a
+/// point not valid
+pub fn multiscalar_multiply_ristretto(
+    scalars: &[[u8; 32]],
+    points: &[[u8; 32]],
+) -> Option<[u8; 32]> {
+    if scalars.len() != points.len() {
+        return None;
+    }
+    let scalars: Vec<PodScalar> = scalars.iter().map(|scalar| PodScalar(*scalar)).collect();
+    let points: Vec<PodRistrettoPoint> = points
+        .iter()
+        .map(|point| PodRistrettoPoint(*point))
+        .collect();
+    ristretto::multiscalar_multiply_ristretto(&scalars, &points).map(|point| point.0)
+}
+
+/// `left + right` on ed25519, `None` if either is not a valid point
+pub fn add_edwards(left: &[u8; 32], right: &[u8; 32]) -> Option<[u8; 32]> {
+    edwards::add_edwards(&PodEdwardsPoint(*left), &PodEdwardsPoint(*right)).map(|point| point.0)
+}
+
+/// `scalar·point` on ed25519, `None` if the scalar is not canonical or the
+/// point not valid
+pub fn multiply_edwards(scalar: &[u8; 32], point: &[u8; 32]) -> Option<[u8; 32]> {
+    edwards::multiply_edwards(&PodScalar(*scalar), &PodEdwardsPoint(*point)).map(|point| point.0)
+}
+
+/// Sum of `scalars[i]·points[i]` on ed25519, `None` if a scalar is not
+/// canonical or a point not valid
+pub fn multiscalar_multiply_edwards(scalars: &[[u8; 32]], points: &[[u8; 32]]) -> Option<[u8; 32]> {
+    if scalars.len() != points.len() {
+        return None;
+    }
+    let scalars: Vec<PodScalar> = scalars.iter().map(|scalar| PodScalar(*scalar)).collect();
+    let points: Vec<PodEdwardsPoint> = points.iter().map(|point| PodEdwardsPoint(*point)).collect();
+    edwards::multiscalar_multiply_edwards(&scalars, &points).map(|point| point.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::{
+        constants::{BASEPOINT_ORDER, ED25519_BASEPOINT_POINT, RISTRETTO_BASEPOINT_POINT},
+        edwards::EdwardsPoint,
+        ristretto::RistrettoPoint,
+        traits::Identity,
+    };
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(ORDER, BASEPOINT_ORDER.to_bytes());
+        assert_eq!(ORDER_MINUS_ONE, (-Scalar::one()).to_bytes());
+        assert_eq!(
+            RISTRETTO_IDENTITY,
+            RistrettoPoint::identity().compress().to_bytes()
+        );
+        assert_eq!(
+            ED25519_IDENTITY,
+            EdwardsPoint::identity().compress().to_bytes()
+        );
+        assert_eq!(
+            RISTRETTO_BASEPOINT,
+            RISTRETTO_BASEPOINT_POINT.compress().to_bytes()
+        );
+        assert_eq!(
+            ED25519_BASEPOINT,
+            ED25519_BASEPOINT_POINT.compress().to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_scalar_arithmetic() {
+        let scalars = [
+            Scalar::zero(),
+            Scalar::one(),
+            -Scalar::one(),
+            Scalar::from(0x1234_5678u64),
+            hash_to_scalar(&[b"first"]),
+            hash_to_scalar(&[b"second"]),
+        ];
+        for left in scalars.iter() {
+            assert!(is_canonical_scalar(left.as_bytes()));
+            assert_eq!(negate_scalar(left.as_bytes()), (-left).to_bytes());
+            for right in scalars.iter() {
+                assert_eq!(
+                    subtract_scalars(left.as_bytes(), right.as_bytes()),
+                    (left - right).to_bytes()
+                );
+            }
+        }
+
+        // l and anything above has a smaller canonical encoding
+        assert!(!is_canonical_scalar(&ORDER));
+        let mut above = ORDER;
+        above[0] += 1;
+        assert!(!is_canonical_scalar(&above));
+        assert!(!is_canonical_scalar(&[0xff; 32]));
+    }
+
+    #[test]
+    fn test_point_arithmetic() {
+        let two = Scalar::from(2u64).to_bytes();
+        let doubled = (RISTRETTO_BASEPOINT_POINT + RISTRETTO_BASEPOINT_POINT)
+            .compress()
+            .to_bytes();
+        assert_eq!(
+            add_ristretto(&RISTRETTO_BASEPOINT, &RISTRETTO_BASEPOINT),
+            Some(doubled)
+        );
+        assert_eq!(
+            subtract_ristretto(&doubled, &RISTRETTO_BASEPOINT),
+            Some(RISTRETTO_BASEPOINT)
+        );
+        assert_eq!(
+            multiply_ristretto(&two, &RISTRETTO_BASEPOINT),
+            Some(doubled)
+        );
+        assert_eq!(
+            multiscalar_multiply_ristretto(
+                &[two, negate_scalar(&Scalar::one().to_bytes())],
+                &[RISTRETTO_BASEPOINT, doubled]
+            ),
+            Some(RISTRETTO_IDENTITY)
+        );
+        assert_eq!(
+            add_edwards(
+                &multiply_edwards(&ORDER_MINUS_ONE, &ED25519_BASEPOINT).unwrap(),
+                &ED25519_BASEPOINT
+            ),
+            Some(ED25519_IDENTITY)
+        );
+        assert_eq!(
+            multiscalar_multiply_edwards(&[two], &[ED25519_BASEPOINT]),
+            add_edwards(&ED25519_BASEPOINT, &ED25519_BASEPOINT)
+        );
+
+        // invalid points and non-canonical scalars fail
+        assert!(is_valid_ristretto(&RISTRETTO_BASEPOINT));
+        assert!(!is_valid_ristretto(&[0xff; 32]));
+        assert_eq!(add_ristretto(&[0xff; 32], &RISTRETTO_BASEPOINT), None);
+        assert_eq!(multiply_ristretto(&ORDER, &RISTRETTO_BASEPOINT), None);
+        assert_eq!(
+            multiscalar_multiply_ristretto(&[two], &[RISTRETTO_BASEPOINT, doubled]),
+            None
+        );
+        assert_eq!(multiply_edwards(&[0xff; 32], &ED25519_BASEPOINT), None);
+    }
+}
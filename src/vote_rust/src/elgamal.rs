@@ -0,0 +1,1059 @@
+//! Exponential ElGamal encryption over Ristretto, for encrypted tallies
+//!
+//! A value `m` is encrypted under the trustee's public key `H = x·G` as the
+//! pair `(r·G, m·G + r·H)` for a random scalar `r`. Adding two ciphertexts
+//! adds the values they encrypt, so the program sums ballots into the
+//! tallies without learning any of them. The trustee decrypts a tally to
+//! `m·G`, finds the small `m` by search, and proves the result with a
+//! Chaum–Pedersen proof that the same secret `x` links `G` to `H` and `r·G`
+//! to `m·G + r·H - m·G`.
+//!
+//! Voters prove their ballots valid without revealing them: a disjunctive
+//! Chaum–Pedersen proof per candidate shows the ciphertext encrypts 0 or 1,
+//! and a Chaum–Pedersen proof on the sum of the ciphertexts shows exactly
+//! one candidate was chosen. Ballot proofs are bound to a context, the
+//! election and the voter, so a ballot can't be copied by another voter.
+//!
+//! Verifying only takes the curve25519 syscalls, see `curve`, so its cost
+//! follows from their prices. Each candidate's 0 or 1 proof takes four
+//! multiscalar multiplications of two points, a subtraction and a hash, and
+//! adding its ciphertext to the ballot sum and to the tally four point
+//! additions, 15,206 compute units in all. The proof on the sum takes 6,918
+//! more.
+//! Each candidate also takes 192 bytes of instruction data, which is what
+//! bounds encrypted elections to `MAX_ENCRYPTED_CANDIDATES`:
+//!
+//! | candidates | syscall units | transaction bytes |
+//! |-----------:|--------------:|------------------:|
+//! |          2 |        37,330 |               761 |
+//! |          3 |        52,536 |               953 |
+//! |          4 |        67,742 |             1,145 |
+//! |          5 |        82,948 |   1,337, too large |
+//!
+//! Units add up the syscall prices of the 1.18 runtime, the program's own
+//! instruction decoding and account handling come on top. Bytes are those of
+//! a signed `cast_encrypted_vote` transaction, which has to fit the 1,232
+//! bytes of a packet. Checking a decryption takes up to 9,095 units per tally.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    curve::{self, hash_to_scalar, RISTRETTO_BASEPOINT, RISTRETTO_IDENTITY},
+    error::VoteError,
+};
+
+/// Most candidates an encrypted election can have, the most whose ballot
+/// and proofs fit a transaction
+pub const MAX_ENCRYPTED_CANDIDATES: u8 = 4;
+
+const CHALLENGE_DOMAIN: &[u8] = b"vote/elgamal/decryption";
+const NONCE_DOMAIN: &[u8] = b"vote/elgamal/nonce";
+const ZERO_ONE_DOMAIN: &[u8] = b"vote/elgamal/zero-one";
+const VALUE_DOMAIN: &[u8] = b"vote/elgamal/value";
+const BALLOT_NONCE_DOMAIN: &[u8] = b"vote/elgamal/ballot-nonce";
+
+fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, ProgramError> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or_else(|| VoteError::InvalidCiphertext.into())
+}
+
+// Secret scalar derived from the encryption randomness of a ciphertext,
+// distinct for every `label`
+fn ballot_nonce(randomness: &Scalar, context: &[u8], ciphertext: &Ciphertext, label: u8) -> Scalar {
+    hash_to_scalar(&[
+        BALLOT_NONCE_DOMAIN,
+        randomness.as_bytes(),
+        context,
+        &ciphertext.ephemeral,
+        &ciphertext.masked,
+        &[label],
+    ])
+}
+
+/// Context ballot proofs are bound to, the election and the voter casting
+/// the ballot
+pub fn ballot_context(election: &Pubkey, voter: &Pubkey) -> [u8; 64] {
+    let mut context = [0; 64];
+    context[..32].copy_from_slice(election.as_ref());
+    context[32..].copy_from_slice(voter.as_ref());
+    context
+}
+
+/// Checks that `public_key` is a usable trustee key, a valid point other
+/// than the identity
+pub fn is_valid_public_key(public_key: &[u8; 32]) -> bool {
+    *public_key != RISTRETTO_IDENTITY && curve::is_valid_ristretto(public_key)
+}
+
+// `B - v·G` for a ciphertext `(A, B)` of `v`, what is left of the masking
+// point once the value is taken out
+fn unmasked(masked: &[u8; 32], value: u64) -> Option<[u8; 32]> {
+    let value_point = match value {
+        0 => return Some(*masked),
+        1 => RISTRETTO_BASEPOINT,
+        _ => curve::multiply_ristretto(Scalar::from(value).as_bytes(), &RISTRETTO_BASEPOINT)?,
+    };
+    curve::subtract_ristretto(masked, &value_point)
+}
+
+/// Encryption of a value, both points compressed. The default ciphertext
+/// encrypts 0 without randomness and is the starting point of every tally.
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Ciphertext {
+    /// `r·G`
+    pub ephemeral: [u8; 32],
+    /// `m·G + r·H`
+    pub masked: [u8; 32],
+}
+
+impl Ciphertext {
+    /// Serialized size of a ciphertext
+    pub const LEN: usize = 32 + 32;
+
+    /// Encrypts `value` under `public_key`, `randomness` must be secret and
+    /// drawn uniformly at random for every ciphertext
+    pub fn encrypt(
+        public_key: &[u8; 32],
+        value: u64,
+        randomness: &Scalar,
+    ) -> Result<Self, ProgramError> {
+        let public_key = decompress(public_key)?;
+        Ok(Self::from_points(
+            randomness * RISTRETTO_BASEPOINT_POINT,
+            Scalar::from(value) * RISTRETTO_BASEPOINT_POINT + randomness * public_key,
+        ))
+    }
+
+    fn from_points(ephemeral: RistrettoPoint, masked: RistrettoPoint) -> Self {
+        Self {
+            ephemeral: ephemeral.compress().to_bytes(),
+            masked: masked.compress().to_bytes(),
+        }
+    }
+
+    fn to_points(self) -> Result<(RistrettoPoint, RistrettoPoint), ProgramError> {
+        Ok((decompress(&self.ephemeral)?, decompress(&self.masked)?))
+    }
+
+    /// Encryption of the sum of both values, fails if either ciphertext
+    /// does not hold valid points
+    pub fn checked_add(&self, other: &Self) -> Result<Self, ProgramError> {
+        Ok(Self {
+            ephemeral: curve::add_ristretto(&self.ephemeral, &other.ephemeral)
+                .ok_or(VoteError::InvalidCiphertext)?,
+            masked: curve::add_ristretto(&self.masked, &other.masked)
+                .ok_or(VoteError::InvalidCiphertext)?,
+        })
+    }
+
+    /// Encryption of `factor` times the value
+    pub fn scale(&self, factor: u64) -> Result<Self, ProgramError> {
+        let (ephemeral, masked) = self.to_points()?;
+        let factor = Scalar::from(factor);
+        Ok(Self::from_points(factor * ephemeral, factor * masked))
+    }
+}
+
+/// Proof that a ciphertext decrypts to a given value under a trustee key
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct DecryptionProof {
+    /// `k·G` for the prover's nonce `k`
+    pub base_commitment: [u8; 32],
+    /// `k·r·G`
+    pub ephemeral_commitment: [u8; 32],
+    /// `k + e·x` for the challenge `e`
+    pub response: [u8; 32],
+}
+
+impl DecryptionProof {
+    /// Serialized size of a decryption proof
+    pub const LEN: usize = 32 + 32 + 32;
+
+    fn challenge(&self, public_key: &[u8; 32], ciphertext: &Ciphertext, value: u64) -> Scalar {
+        hash_to_scalar(&[
+            CHALLENGE_DOMAIN,
+            public_key,
+            &ciphertext.ephemeral,
+            &ciphertext.masked,
+            &value.to_le_bytes(),
+            &self.base_commitment,
+            &self.ephemeral_commitment,
+        ])
+    }
+
+    /// Checks that `ciphertext` decrypts to `value` under `public_key`
+    pub fn verify(&self, public_key: &[u8; 32], ciphertext: &Ciphertext, value: u64) -> bool {
+        self.check(public_key, ciphertext, value).unwrap_or(false)
+    }
+
+    fn check(&self, public_key: &[u8; 32], ciphertext: &Ciphertext, value: u64) -> Option<bool> {
+        let negated =
+            curve::negate_scalar(self.challenge(public_key, ciphertext, value).as_bytes());
+        // x·r·G, what the masking point becomes once the value is taken out
+        let shared = unmasked(&ciphertext.masked, value)?;
+
+        // z·G - e·H and z·r·G - e·x·r·G give back the commitments
+        let base_commitment = curve::multiscalar_multiply_ristretto(
+            &[self.response, negated],
+            &[RISTRETTO_BASEPOINT, *public_key],
+        )?;
+        let ephemeral_commitment = curve::multiscalar_multiply_ristretto(
+            &[self.response, negated],
+            &[ciphertext.ephemeral, shared],
+        )?;
+        Some(
+            base_commitment == self.base_commitment
+                && ephemeral_commitment == self.ephemeral_commitment,
+        )
+    }
+}
+
+/// Proof by the encrypting voter that a ciphertext encrypts 0 or 1, made of
+/// a Chaum–Pedersen proof for each value where the one not encrypted is
+/// simulated. The two challenges must add up to the Fiat–Shamir challenge,
+/// so at most one of them could be chosen freely.
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ZeroOneProof {
+    /// Challenge of the branch claiming the value is 0
+    pub challenge_zero: [u8; 32],
+    /// Challenge of the branch claiming the value is 1
+    pub challenge_one: [u8; 32],
+    /// Response of the branch claiming the value is 0
+    pub response_zero: [u8; 32],
+    /// Response of the branch claiming the value is 1
+    pub response_one: [u8; 32],
+}
+
+// Commitments `(z·G - c·A, z·H - c·(B - v·G))` a Chaum–Pedersen proof that
+// `(A, B)` encrypts `v` must have been made with, `None` if a point or the
+// response is invalid. The challenge must be canonical.
+fn value_commitments(
+    public_key: &[u8; 32],
+    ciphertext: &Ciphertext,
+    value: u64,
+    challenge: &[u8; 32],
+    response: &[u8; 32],
+) -> Option<[[u8; 32]; 2]> {
+    let negated = curve::negate_scalar(challenge);
+    let unmasked = unmasked(&ciphertext.masked, value)?;
+    Some([
+        curve::multiscalar_multiply_ristretto(
+            &[*response, negated],
+            &[RISTRETTO_BASEPOINT, ciphertext.ephemeral],
+        )?,
+        curve::multiscalar_multiply_ristretto(&[*response, negated], &[*public_key, unmasked])?,
+    ])
+}
+
+impl ZeroOneProof {
+    /// Serialized size of a 0 or 1 proof
+    pub const LEN: usize = 4 * 32;
+
+    fn challenge(
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        context: &[u8],
+        commitments: &[[u8; 32]; 4],
+    ) -> Scalar {
+        hash_to_scalar(&[
+            ZERO_ONE_DOMAIN,
+            context,
+            public_key,
+            &ciphertext.ephemeral,
+            &ciphertext.masked,
+            &commitments[0],
+            &commitments[1],
+            &commitments[2],
+            &commitments[3],
+        ])
+    }
+
+    /// Proves that `ciphertext`, encrypted with `randomness`, encrypts
+    /// `is_one as u64`. The proof only verifies if it actually does.
+    pub fn prove(
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        is_one: bool,
+        randomness: &Scalar,
+        context: &[u8],
+    ) -> Result<Self, ProgramError> {
+        let key = decompress(public_key)?;
+
+        // the branch not taken is simulated from a chosen challenge
+        let simulated_value = !is_one as u64;
+        let simulated_challenge = ballot_nonce(randomness, context, ciphertext, 0);
+        let simulated_response = ballot_nonce(randomness, context, ciphertext, 1);
+        let simulated = value_commitments(
+            public_key,
+            ciphertext,
+            simulated_value,
+            simulated_challenge.as_bytes(),
+            simulated_response.as_bytes(),
+        )
+        .ok_or(VoteError::InvalidCiphertext)?;
+
+        let nonce = ballot_nonce(randomness, context, ciphertext, 2);
+        let real = [
+            (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes(),
+            (nonce * key).compress().to_bytes(),
+        ];
+
+        let (zero, one) = if is_one {
+            (simulated, real)
+        } else {
+            (real, simulated)
+        };
+        let challenge = Self::challenge(
+            public_key,
+            ciphertext,
+            context,
+            &[zero[0], zero[1], one[0], one[1]],
+        );
+        let real_challenge = challenge - simulated_challenge;
+        let real_response = nonce + real_challenge * randomness;
+
+        let (challenges, responses) = if is_one {
+            (
+                [simulated_challenge, real_challenge],
+                [simulated_response, real_response],
+            )
+        } else {
+            (
+                [real_challenge, simulated_challenge],
+                [real_response, simulated_response],
+            )
+        };
+        Ok(Self {
+            challenge_zero: challenges[0].to_bytes(),
+            challenge_one: challenges[1].to_bytes(),
+            response_zero: responses[0].to_bytes(),
+            response_one: responses[1].to_bytes(),
+        })
+    }
+
+    /// Checks that `ciphertext` encrypts 0 or 1 under `public_key`
+    pub fn verify(&self, public_key: &[u8; 32], ciphertext: &Ciphertext, context: &[u8]) -> bool {
+        self.check(public_key, ciphertext, context).unwrap_or(false)
+    }
+
+    fn check(
+        &self,
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        context: &[u8],
+    ) -> Option<bool> {
+        let scalars = [
+            self.challenge_zero,
+            self.challenge_one,
+            self.response_zero,
+            self.response_one,
+        ];
+        if !scalars.iter().all(curve::is_canonical_scalar) {
+            return None;
+        }
+
+        let zero = value_commitments(
+            public_key,
+            ciphertext,
+            0,
+            &self.challenge_zero,
+            &self.response_zero,
+        )?;
+        let one = value_commitments(
+            public_key,
+            ciphertext,
+            1,
+            &self.challenge_one,
+            &self.response_one,
+        )?;
+
+        let challenge = Self::challenge(
+            public_key,
+            ciphertext,
+            context,
+            &[zero[0], zero[1], one[0], one[1]],
+        );
+        Some(
+            curve::subtract_scalars(challenge.as_bytes(), &self.challenge_one)
+                == self.challenge_zero,
+        )
+    }
+}
+
+/// Proof by the encrypting voter that a ciphertext encrypts a public value,
+/// a Chaum–Pedersen proof that the same randomness links `G` to `r·G` and
+/// `H` to `r·H`
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ValueProof {
+    /// Fiat–Shamir challenge
+    pub challenge: [u8; 32],
+    /// `k + c·r` for the prover's nonce `k`
+    pub response: [u8; 32],
+}
+
+impl ValueProof {
+    /// Serialized size of a value proof
+    pub const LEN: usize = 2 * 32;
+
+    fn challenge(
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        value: u64,
+        context: &[u8],
+        commitments: &[[u8; 32]; 2],
+    ) -> Scalar {
+        hash_to_scalar(&[
+            VALUE_DOMAIN,
+            context,
+            public_key,
+            &ciphertext.ephemeral,
+            &ciphertext.masked,
+            &value.to_le_bytes(),
+            &commitments[0],
+            &commitments[1],
+        ])
+    }
+
+    /// Proves that `ciphertext`, encrypted with `randomness`, encrypts
+    /// `value`. The proof only verifies if it actually does.
+    pub fn prove(
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        value: u64,
+        randomness: &Scalar,
+        context: &[u8],
+    ) -> Result<Self, ProgramError> {
+        let key = decompress(public_key)?;
+
+        let nonce = ballot_nonce(randomness, context, ciphertext, 3);
+        let commitments = [
+            (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes(),
+            (nonce * key).compress().to_bytes(),
+        ];
+        let challenge = Self::challenge(public_key, ciphertext, value, context, &commitments);
+        Ok(Self {
+            challenge: challenge.to_bytes(),
+            response: (nonce + challenge * randomness).to_bytes(),
+        })
+    }
+
+    /// Checks that `ciphertext` encrypts `value` under `public_key`
+    pub fn verify(
+        &self,
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        value: u64,
+        context: &[u8],
+    ) -> bool {
+        self.check(public_key, ciphertext, value, context)
+            .unwrap_or(false)
+    }
+
+    fn check(
+        &self,
+        public_key: &[u8; 32],
+        ciphertext: &Ciphertext,
+        value: u64,
+        context: &[u8],
+    ) -> Option<bool> {
+        if !curve::is_canonical_scalar(&self.challenge) {
+            return None;
+        }
+
+        let commitments = value_commitments(
+            public_key,
+            ciphertext,
+            value,
+            &self.challenge,
+            &self.response,
+        )?;
+        Some(
+            Self::challenge(public_key, ciphertext, value, context, &commitments).as_bytes()
+                == &self.challenge,
+        )
+    }
+}
+
+/// Proof that an encrypted ballot votes for exactly one candidate
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct BallotProof {
+    /// Proof per candidate that its ciphertext encrypts 0 or 1
+    pub choices: Vec<ZeroOneProof>,
+    /// Proof that the sum of the ciphertexts encrypts 1
+    pub total: ValueProof,
+}
+
+impl BallotProof {
+    /// Checks that `ballot` holds one ciphertext per proof, at most
+    /// `MAX_ENCRYPTED_CANDIDATES`, each encrypting 0 or 1 under
+    /// `public_key`, and that they add up to 1
+    pub fn verify(&self, public_key: &[u8; 32], ballot: &[Ciphertext], context: &[u8]) -> bool {
+        if ballot.len() != self.choices.len() || ballot.len() > MAX_ENCRYPTED_CANDIDATES as usize {
+            return false;
+        }
+        let choices_valid = ballot
+            .iter()
+            .zip(self.choices.iter())
+            .all(|(ciphertext, proof)| proof.verify(public_key, ciphertext, context));
+
+        choices_valid
+            && ballot
+                .iter()
+                .try_fold(Ciphertext::default(), |sum, ciphertext| {
+                    sum.checked_add(ciphertext)
+                })
+                .map(|sum| self.total.verify(public_key, &sum, 1, context))
+                .unwrap_or(false)
+    }
+}
+
+/// Encrypts a ballot for `choice` under `public_key` and proves it valid
+/// within `context`, see `ballot_context`. `randomness` holds one secret,
+/// uniformly random scalar per candidate, of at most
+/// `MAX_ENCRYPTED_CANDIDATES`.
+pub fn encrypt_ballot(
+    public_key: &[u8; 32],
+    choice: u8,
+    randomness: &[Scalar],
+    context: &[u8],
+) -> Result<(Vec<Ciphertext>, BallotProof), ProgramError> {
+    if randomness.len() > MAX_ENCRYPTED_CANDIDATES as usize {
+        return Err(VoteError::InvalidCandidateCount.into());
+    }
+    if choice as usize >= randomness.len() {
+        return Err(VoteError::InvalidCandidate.into());
+    }
+
+    let mut ballot = vec![];
+    let mut choices = vec![];
+    for (candidate, randomness) in randomness.iter().enumerate() {
+        let is_one = candidate == choice as usize;
+        let ciphertext = Ciphertext::encrypt(public_key, is_one as u64, randomness)?;
+        choices.push(ZeroOneProof::prove(
+            public_key,
+            &ciphertext,
+            is_one,
+            randomness,
+            context,
+        )?);
+        ballot.push(ciphertext);
+    }
+
+    let sum = ballot
+        .iter()
+        .try_fold(Ciphertext::default(), |sum, ciphertext| {
+            sum.checked_add(ciphertext)
+        })?;
+    let total_randomness = randomness.iter().sum();
+    let total = ValueProof::prove(public_key, &sum, 1, &total_randomness, context)?;
+
+    Ok((ballot, BallotProof { choices, total }))
+}
+
+/// Secret key of an election trustee, never leaves the trustee's machine
+pub struct ElGamalSecretKey(Scalar);
+
+impl ElGamalSecretKey {
+    /// Wraps a secret scalar, which must be drawn uniformly at random
+    pub fn new(secret: Scalar) -> Self {
+        Self(secret)
+    }
+
+    /// Public key to store in the election
+    pub fn public_key(&self) -> [u8; 32] {
+        (self.0 * RISTRETTO_BASEPOINT_POINT).compress().to_bytes()
+    }
+
+    /// Recovers the value encrypted in `ciphertext` by trying every value up
+    /// to `max`, returns `None` if it is larger or the ciphertext is invalid
+    pub fn decrypt(&self, ciphertext: &Ciphertext, max: u64) -> Option<u64> {
+        let (ephemeral, masked) = ciphertext.to_points().ok()?;
+        let target = masked - self.0 * ephemeral;
+
+        let mut candidate = RistrettoPoint::identity();
+        for value in 0..=max {
+            if candidate == target {
+                return Some(value);
+            }
+            candidate += RISTRETTO_BASEPOINT_POINT;
+        }
+        None
+    }
+
+    /// Proves that `ciphertext` decrypts to `value`. The nonce is derived
+    /// from the secret key and the statement, so proving needs no randomness
+    /// and never reuses a nonce across statements.
+    pub fn prove_decryption(
+        &self,
+        ciphertext: &Ciphertext,
+        value: u64,
+    ) -> Result<DecryptionProof, ProgramError> {
+        let (ephemeral, _) = ciphertext.to_points()?;
+        let public_key = self.public_key();
+
+        let nonce = hash_to_scalar(&[
+            NONCE_DOMAIN,
+            self.0.as_bytes(),
+            &ciphertext.ephemeral,
+            &ciphertext.masked,
+            &value.to_le_bytes(),
+        ]);
+        let mut proof = DecryptionProof {
+            base_commitment: (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes(),
+            ephemeral_commitment: (nonce * ephemeral).compress().to_bytes(),
+            response: [0; 32],
+        };
+        let challenge = proof.challenge(&public_key, ciphertext, value);
+        proof.response = (nonce + challenge * self.0).to_bytes();
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trustee() -> ElGamalSecretKey {
+        ElGamalSecretKey::new(hash_to_scalar(&[b"test trustee"]))
+    }
+
+    fn randomness(seed: u64) -> Scalar {
+        hash_to_scalar(&[b"test randomness", &seed.to_le_bytes()])
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let trustee = trustee();
+        let public_key = trustee.public_key();
+
+        for value in [0, 1, 2, 17, 1_000].iter() {
+            let ciphertext = Ciphertext::encrypt(&public_key, *value, &randomness(*value)).unwrap();
+            assert_eq!(trustee.decrypt(&ciphertext, 1_000), Some(*value));
+        }
+
+        // the same value encrypts differently under fresh randomness
+        let first = Ciphertext::encrypt(&public_key, 1, &randomness(1)).unwrap();
+        let second = Ciphertext::encrypt(&public_key, 1, &randomness(2)).unwrap();
+        assert_ne!(first, second);
+
+        // values past the search bound are not found
+        let large = Ciphertext::encrypt(&public_key, 11, &randomness(3)).unwrap();
+        assert_eq!(trustee.decrypt(&large, 10), None);
+
+        // another key decrypts to garbage
+        let other = ElGamalSecretKey::new(randomness(4));
+        assert_eq!(other.decrypt(&first, 1_000), None);
+    }
+
+    #[test]
+    fn test_default_ciphertext_is_zero() {
+        let trustee = trustee();
+        assert_eq!(trustee.decrypt(&Ciphertext::default(), 0), Some(0));
+
+        let ciphertext = Ciphertext::encrypt(&trustee.public_key(), 5, &randomness(0)).unwrap();
+        assert_eq!(
+            Ciphertext::default().checked_add(&ciphertext).unwrap(),
+            ciphertext
+        );
+    }
+
+    #[test]
+    fn test_homomorphic_sum() {
+        let trustee = trustee();
+        let public_key = trustee.public_key();
+
+        let mut tally = Ciphertext::default();
+        let mut expected = 0;
+        for (seed, value) in [1, 0, 1, 1, 0, 1].iter().enumerate() {
+            let ballot =
+                Ciphertext::encrypt(&public_key, *value, &randomness(seed as u64)).unwrap();
+            tally = tally.checked_add(&ballot).unwrap();
+            expected += value;
+        }
+        assert_eq!(trustee.decrypt(&tally, 100), Some(expected));
+
+        // scaling weighs a ballot
+        let ballot = Ciphertext::encrypt(&public_key, 1, &randomness(7)).unwrap();
+        assert_eq!(trustee.decrypt(&ballot.scale(25).unwrap(), 100), Some(25));
+        assert_eq!(trustee.decrypt(&ballot.scale(0).unwrap(), 100), Some(0));
+        let weighted = tally.checked_add(&ballot.scale(3).unwrap()).unwrap();
+        assert_eq!(trustee.decrypt(&weighted, 100), Some(expected + 3));
+    }
+
+    #[test]
+    fn test_invalid_points_are_rejected() {
+        let trustee = trustee();
+        let valid = Ciphertext::encrypt(&trustee.public_key(), 1, &randomness(0)).unwrap();
+
+        // not the canonical encoding of any point
+        let invalid = Ciphertext {
+            ephemeral: [0xff; 32],
+            masked: valid.masked,
+        };
+        assert_eq!(
+            valid.checked_add(&invalid),
+            Err(VoteError::InvalidCiphertext.into())
+        );
+        assert_eq!(
+            invalid.checked_add(&valid),
+            Err(VoteError::InvalidCiphertext.into())
+        );
+        assert_eq!(invalid.scale(2), Err(VoteError::InvalidCiphertext.into()));
+        assert_eq!(
+            Ciphertext::encrypt(&[0xff; 32], 1, &randomness(0)),
+            Err(VoteError::InvalidCiphertext.into())
+        );
+        assert_eq!(trustee.decrypt(&invalid, 10), None);
+        assert!(trustee.prove_decryption(&invalid, 1).is_err());
+
+        assert!(is_valid_public_key(&trustee.public_key()));
+        assert!(!is_valid_public_key(&[0xff; 32]));
+        // the identity would leave every ballot in the clear
+        assert!(!is_valid_public_key(&[0; 32]));
+    }
+
+    #[test]
+    fn test_decryption_proof() {
+        let trustee = trustee();
+        let public_key = trustee.public_key();
+
+        let mut tally = Ciphertext::default();
+        for seed in 0..4 {
+            let ballot = Ciphertext::encrypt(&public_key, 1, &randomness(seed)).unwrap();
+            tally = tally.checked_add(&ballot).unwrap();
+        }
+        let proof = trustee.prove_decryption(&tally, 4).unwrap();
+        assert!(proof.verify(&public_key, &tally, 4));
+
+        // proving is deterministic
+        assert_eq!(trustee.prove_decryption(&tally, 4).unwrap(), proof);
+
+        // the proof is bound to the value, the ciphertext and the key
+        assert!(!proof.verify(&public_key, &tally, 3));
+        assert!(!proof.verify(&public_key, &tally, 5));
+        let other_tally = tally
+            .checked_add(&Ciphertext::encrypt(&public_key, 0, &randomness(9)).unwrap())
+            .unwrap();
+        assert!(!proof.verify(&public_key, &other_tally, 4));
+        let other = ElGamalSecretKey::new(randomness(10));
+        assert!(!proof.verify(&other.public_key(), &tally, 4));
+
+        // a proof of a wrong value can't be made to verify
+        let forged = trustee.prove_decryption(&tally, 5).unwrap();
+        assert!(!forged.verify(&public_key, &tally, 5));
+
+        // the untouched tally decrypts to zero, provably
+        let zero = Ciphertext::default();
+        let proof = trustee.prove_decryption(&zero, 0).unwrap();
+        assert!(proof.verify(&public_key, &zero, 0));
+        assert!(!proof.verify(&public_key, &zero, 1));
+    }
+
+    #[test]
+    fn test_tampered_decryption_proofs_fail() {
+        let trustee = trustee();
+        let public_key = trustee.public_key();
+        let ciphertext = Ciphertext::encrypt(&public_key, 2, &randomness(0)).unwrap();
+        let proof = trustee.prove_decryption(&ciphertext, 2).unwrap();
+
+        let mut tampered = proof;
+        tampered.response[0] ^= 1;
+        assert!(!tampered.verify(&public_key, &ciphertext, 2));
+
+        let mut tampered = proof;
+        tampered.base_commitment = proof.ephemeral_commitment;
+        assert!(!tampered.verify(&public_key, &ciphertext, 2));
+
+        let mut tampered = proof;
+        tampered.ephemeral_commitment = [0xff; 32];
+        assert!(!tampered.verify(&public_key, &ciphertext, 2));
+
+        // responses must be canonical scalars
+        let mut tampered = proof;
+        tampered.response = [0xff; 32];
+        assert!(!tampered.verify(&public_key, &ciphertext, 2));
+
+        assert!(!DecryptionProof::default().verify(&public_key, &ciphertext, 2));
+    }
+
+    #[test]
+    fn test_serialized_sizes() {
+        let trustee = trustee();
+        let ciphertext = Ciphertext::encrypt(&trustee.public_key(), 1, &randomness(0)).unwrap();
+        assert_eq!(ciphertext.try_to_vec().unwrap().len(), Ciphertext::LEN);
+
+        let proof = trustee.prove_decryption(&ciphertext, 1).unwrap();
+        assert_eq!(proof.try_to_vec().unwrap().len(), DecryptionProof::LEN);
+        assert_eq!(
+            DecryptionProof::try_from_slice(&proof.try_to_vec().unwrap()).unwrap(),
+            proof
+        );
+
+        let (_, proof) = encrypt_ballot(
+            &trustee.public_key(),
+            0,
+            &[randomness(1), randomness(2)],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            proof.choices[0].try_to_vec().unwrap().len(),
+            ZeroOneProof::LEN
+        );
+        assert_eq!(proof.total.try_to_vec().unwrap().len(), ValueProof::LEN);
+        assert_eq!(
+            proof.try_to_vec().unwrap().len(),
+            4 + 2 * ZeroOneProof::LEN + ValueProof::LEN
+        );
+    }
+
+    #[test]
+    fn test_zero_one_proof() {
+        let public_key = trustee().public_key();
+        let context = b"election and voter";
+
+        for (seed, is_one) in [false, true].iter().enumerate() {
+            let scalar = randomness(seed as u64);
+            let ciphertext = Ciphertext::encrypt(&public_key, *is_one as u64, &scalar).unwrap();
+            let proof =
+                ZeroOneProof::prove(&public_key, &ciphertext, *is_one, &scalar, context).unwrap();
+            assert!(proof.verify(&public_key, &ciphertext, context));
+
+            // the proof is bound to its context and ciphertext
+            assert!(!proof.verify(&public_key, &ciphertext, b"another voter"));
+            let other = Ciphertext::encrypt(&public_key, *is_one as u64, &randomness(9)).unwrap();
+            assert!(!proof.verify(&public_key, &other, context));
+
+            // claiming the other value does not verify
+            let wrong =
+                ZeroOneProof::prove(&public_key, &ciphertext, !is_one, &scalar, context).unwrap();
+            assert!(!wrong.verify(&public_key, &ciphertext, context));
+        }
+
+        // neither branch can be proven for other values
+        for value in [2, 1_000].iter() {
+            let randomness = randomness(*value);
+            let ciphertext = Ciphertext::encrypt(&public_key, *value, &randomness).unwrap();
+            for is_one in [false, true].iter() {
+                let proof =
+                    ZeroOneProof::prove(&public_key, &ciphertext, *is_one, &randomness, context)
+                        .unwrap();
+                assert!(!proof.verify(&public_key, &ciphertext, context));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_zero_one_proofs_fail() {
+        let public_key = trustee().public_key();
+        let randomness = randomness(0);
+        let ciphertext = Ciphertext::encrypt(&public_key, 1, &randomness).unwrap();
+        let proof = ZeroOneProof::prove(&public_key, &ciphertext, true, &randomness, &[]).unwrap();
+        assert!(proof.verify(&public_key, &ciphertext, &[]));
+
+        let swapped = ZeroOneProof {
+            challenge_zero: proof.challenge_one,
+            challenge_one: proof.challenge_zero,
+            response_zero: proof.response_one,
+            response_one: proof.response_zero,
+        };
+        assert!(!swapped.verify(&public_key, &ciphertext, &[]));
+
+        for field in 0..4 {
+            for (index, value) in [(0, 1), (31, 0xff)].iter() {
+                // flipped bits, and scalars that aren't canonical
+                let mut tampered = proof;
+                let bytes = match field {
+                    0 => &mut tampered.challenge_zero,
+                    1 => &mut tampered.challenge_one,
+                    2 => &mut tampered.response_zero,
+                    _ => &mut tampered.response_one,
+                };
+                bytes[*index] ^= value;
+                assert!(!tampered.verify(&public_key, &ciphertext, &[]));
+            }
+        }
+
+        assert!(!ZeroOneProof::default().verify(&public_key, &ciphertext, &[]));
+    }
+
+    #[test]
+    fn test_value_proof() {
+        let public_key = trustee().public_key();
+        let randomness = randomness(0);
+        let ciphertext = Ciphertext::encrypt(&public_key, 7, &randomness).unwrap();
+
+        let proof = ValueProof::prove(&public_key, &ciphertext, 7, &randomness, &[1]).unwrap();
+        assert!(proof.verify(&public_key, &ciphertext, 7, &[1]));
+        assert!(!proof.verify(&public_key, &ciphertext, 6, &[1]));
+        assert!(!proof.verify(&public_key, &ciphertext, 7, &[2]));
+
+        let wrong = ValueProof::prove(&public_key, &ciphertext, 6, &randomness, &[1]).unwrap();
+        assert!(!wrong.verify(&public_key, &ciphertext, 6, &[1]));
+
+        let mut tampered = proof;
+        tampered.response[0] ^= 1;
+        assert!(!tampered.verify(&public_key, &ciphertext, 7, &[1]));
+    }
+
+    #[test]
+    fn test_ballot_proof() {
+        let trustee = trustee();
+        let public_key = trustee.public_key();
+        let context = ballot_context(&Pubkey::new_unique(), &Pubkey::new_unique());
+        let scalars: Vec<Scalar> = (0..4).map(randomness).collect();
+
+        let mut tallies = vec![Ciphertext::default(); 4];
+        for choice in 0..4 {
+            let (ballot, proof) = encrypt_ballot(&public_key, choice, &scalars, &context).unwrap();
+            assert!(proof.verify(&public_key, &ballot, &context));
+            assert!(!proof.verify(&public_key, &ballot, &[0; 64]));
+
+            for (tally, ciphertext) in tallies.iter_mut().zip(ballot.iter()) {
+                *tally = tally.checked_add(ciphertext).unwrap();
+            }
+        }
+        for tally in tallies.iter() {
+            assert_eq!(trustee.decrypt(tally, 10), Some(1));
+        }
+
+        assert_eq!(
+            encrypt_ballot(&public_key, 4, &scalars, &context),
+            Err(VoteError::InvalidCandidate.into())
+        );
+
+        // every ciphertext needs its proof
+        let (ballot, mut proof) = encrypt_ballot(&public_key, 1, &scalars, &context).unwrap();
+        assert!(!proof.verify(&public_key, &ballot[..3], &context));
+        proof.choices.pop();
+        assert!(!proof.verify(&public_key, &ballot[..3], &context));
+        assert!(!proof.verify(&public_key, &ballot, &context));
+
+        // a ballot must fit a transaction, even if each of its proofs holds
+        let scalars: Vec<Scalar> = (0..=MAX_ENCRYPTED_CANDIDATES as u64)
+            .map(randomness)
+            .collect();
+        assert_eq!(
+            encrypt_ballot(&public_key, 0, &scalars, &context),
+            Err(VoteError::InvalidCandidateCount.into())
+        );
+        let ballot: Vec<Ciphertext> = scalars
+            .iter()
+            .enumerate()
+            .map(|(candidate, randomness)| {
+                Ciphertext::encrypt(&public_key, (candidate == 0) as u64, randomness).unwrap()
+            })
+            .collect();
+        let choices: Vec<ZeroOneProof> = ballot
+            .iter()
+            .zip(scalars.iter())
+            .enumerate()
+            .map(|(candidate, (ciphertext, randomness))| {
+                ZeroOneProof::prove(
+                    &public_key,
+                    ciphertext,
+                    candidate == 0,
+                    randomness,
+                    &context,
+                )
+                .unwrap()
+            })
+            .collect();
+        let sum = ballot
+            .iter()
+            .try_fold(Ciphertext::default(), |sum, ciphertext| {
+                sum.checked_add(ciphertext)
+            })
+            .unwrap();
+        let total =
+            ValueProof::prove(&public_key, &sum, 1, &scalars.iter().sum(), &context).unwrap();
+        assert!(total.verify(&public_key, &sum, 1, &context));
+        let proof = BallotProof { choices, total };
+        assert!(!proof.verify(&public_key, &ballot, &context));
+    }
+
+    #[test]
+    fn test_invalid_ballots_are_rejected() {
+        let public_key = trustee().public_key();
+        let scalars: Vec<Scalar> = (0..3).map(randomness).collect();
+
+        // a ballot with a valid proof per candidate
+        let encrypt = |values: &[u64]| -> (Vec<Ciphertext>, Vec<ZeroOneProof>) {
+            values
+                .iter()
+                .zip(scalars.iter())
+                .map(|(value, randomness)| {
+                    let ciphertext = Ciphertext::encrypt(&public_key, *value, randomness).unwrap();
+                    let proof =
+                        ZeroOneProof::prove(&public_key, &ciphertext, *value == 1, randomness, &[])
+                            .unwrap();
+                    (ciphertext, proof)
+                })
+                .unzip()
+        };
+        let total = |ballot: &[Ciphertext], value| {
+            let sum = ballot
+                .iter()
+                .try_fold(Ciphertext::default(), |sum, ciphertext| {
+                    sum.checked_add(ciphertext)
+                })
+                .unwrap();
+            let randomness: Scalar = scalars.iter().sum();
+            ValueProof::prove(&public_key, &sum, value, &randomness, &[]).unwrap()
+        };
+
+        let (ballot, choices) = encrypt(&[0, 1, 0]);
+        let proof = BallotProof {
+            choices,
+            total: total(&ballot, 1),
+        };
+        assert!(proof.verify(&public_key, &ballot, &[]));
+
+        // piling 1000 votes on a candidate
+        let (ballot, choices) = encrypt(&[1_000, 0, 0]);
+        let proof = BallotProof {
+            choices,
+            total: total(&ballot, 1),
+        };
+        assert!(!proof.verify(&public_key, &ballot, &[]));
+
+        // voting for several candidates, or none
+        for values in [[1, 1, 0], [0, 0, 0]].iter() {
+            let (ballot, choices) = encrypt(values);
+            let sum = values.iter().sum();
+            let proof = BallotProof {
+                choices,
+                total: total(&ballot, sum),
+            };
+            assert!(!proof.verify(&public_key, &ballot, &[]));
+            let proof = BallotProof {
+                total: total(&ballot, 1),
+                ..proof
+            };
+            assert!(!proof.verify(&public_key, &ballot, &[]));
+        }
+
+        // a negative vote offsetting a double vote
+        let (mut ballot, choices) = encrypt(&[1, 1, 0]);
+        ballot[2] = Ciphertext::encrypt(&public_key, 0, &scalars[2])
+            .unwrap()
+            .checked_add(&Ciphertext {
+                ephemeral: [0; 32],
+                masked: (-RISTRETTO_BASEPOINT_POINT).compress().to_bytes(),
+            })
+            .unwrap();
+        let proof = BallotProof {
+            choices,
+            total: total(&ballot, 1),
+        };
+        assert!(!proof.verify(&public_key, &ballot, &[]));
+    }
+}
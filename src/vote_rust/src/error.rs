@@ -0,0 +1,288 @@
+//! Error types
+
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+/// Errors that may be returned by the vote program.
+///
+/// The numeric value of each variant is surfaced as `ProgramError::Custom(code)`
+/// in transaction logs and must never change once released.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum VoteError {
+    /// The instruction data could not be decoded
+    #[error("Invalid instruction")]
+    InvalidInstruction = 0,
+    /// The candidate voted for does not exist
+    #[error("Candidate does not exist")]
+    InvalidCandidate = 1,
+    /// The voting window of the election has ended
+    #[error("Election is closed")]
+    ElectionClosed = 2,
+    /// The voter already cast a ballot in this election
+    #[error("Voter has already voted")]
+    AlreadyVoted = 3,
+    /// The vote account data is too small or malformed
+    #[error("Invalid election account")]
+    InvalidElectionAccount = 4,
+    /// The election account was already initialized
+    #[error("Election already initialized")]
+    ElectionAlreadyInitialized = 5,
+    /// The election account has not been initialized
+    #[error("Election not initialized")]
+    ElectionNotInitialized = 6,
+    /// The signer is not the election authority
+    #[error("Invalid election authority")]
+    InvalidAuthority = 7,
+    /// The requested number of candidates is out of range
+    #[error("Invalid candidate count")]
+    InvalidCandidateCount = 8,
+    /// A candidate tally would overflow
+    #[error("Tally overflow")]
+    TallyOverflow = 9,
+    /// The vote receipt account is not the one derived for the voter
+    #[error("Invalid vote receipt account")]
+    InvalidReceiptAccount = 10,
+    /// The voting window ends before it starts
+    #[error("Invalid voting window")]
+    InvalidVotingWindow = 11,
+    /// Voting has not opened yet
+    #[error("Voting is not open yet")]
+    VotingNotOpen = 12,
+    /// The election was finalized and its tallies are frozen
+    #[error("Election is finalized")]
+    ElectionFinalized = 13,
+    /// The election must be finalized first
+    #[error("Election is not finalized")]
+    ElectionNotFinalized = 14,
+    /// The voter is not registered as eligible for the election
+    #[error("Voter is not eligible")]
+    NotEligible = 15,
+    /// The operation does not apply to the eligibility mode of the election
+    #[error("Wrong eligibility mode")]
+    WrongEligibilityMode = 16,
+    /// A voter must carry a weight of at least one
+    #[error("Invalid vote weight")]
+    InvalidWeight = 17,
+    /// The token account is not the voter's account of the election mint,
+    /// or not the escrow derived for the voter
+    #[error("Invalid token account")]
+    InvalidTokenAccount = 18,
+    /// Escrowed tokens can't be withdrawn before the election closes
+    #[error("Tokens are locked until the election closes")]
+    TokensLocked = 19,
+    /// The ballot does not fit the voting method of the election
+    #[error("Wrong voting method")]
+    WrongVotingMethod = 20,
+    /// The voter has too few voice credits left for the ballot
+    #[error("Insufficient voice credits")]
+    InsufficientCredits = 21,
+    /// A ranking is empty or lists a candidate twice
+    #[error("Invalid ranking")]
+    InvalidRanking = 22,
+    /// Results can't be computed before voting ends
+    #[error("Voting is still in progress")]
+    VotingInProgress = 23,
+    /// The winner of the election was already computed
+    #[error("Election already tabulated")]
+    AlreadyTabulated = 24,
+    /// The revealed candidate and salt do not hash to the commitment
+    #[error("Revealed ballot does not match the commitment")]
+    InvalidReveal = 25,
+    /// A ciphertext or encryption key is not a valid Ristretto point
+    #[error("Invalid ciphertext")]
+    InvalidCiphertext = 26,
+    /// A zero-knowledge proof does not verify
+    #[error("Invalid proof")]
+    InvalidProof = 27,
+    /// The verifying key is not one of the anonymous membership circuit
+    #[error("Invalid verifying key")]
+    InvalidVerifyingKey = 28,
+    /// The ring signature does not verify against the ring of registered
+    /// voters
+    #[error("Invalid ring signature")]
+    InvalidRingSignature = 29,
+    /// The transaction does not verify the voter's signature of the ballot
+    /// with the Ed25519 signature program
+    #[error("Missing ballot signature")]
+    MissingBallotSignature = 30,
+    /// The ballot nonce is not above the last one the voter used
+    #[error("Stale ballot nonce")]
+    StaleNonce = 31,
+    /// Taking a ballot back would bring a tally or count below zero
+    #[error("Tally underflow")]
+    TallyUnderflow = 32,
+}
+
+impl From<VoteError> for ProgramError {
+    fn from(e: VoteError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for VoteError {
+    fn type_of() -> &'static str {
+        "VoteError"
+    }
+}
+
+impl PrintProgramError for VoteError {
+    fn print<E>(&self)
+    where
+        E: 'static
+            + std::error::Error
+            + DecodeError<E>
+            + PrintProgramError
+            + num_traits::FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidInstruction),
+            ProgramError::Custom(0)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidCandidate),
+            ProgramError::Custom(1)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::ElectionClosed),
+            ProgramError::Custom(2)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::AlreadyVoted),
+            ProgramError::Custom(3)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidElectionAccount),
+            ProgramError::Custom(4)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::ElectionAlreadyInitialized),
+            ProgramError::Custom(5)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::ElectionNotInitialized),
+            ProgramError::Custom(6)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidAuthority),
+            ProgramError::Custom(7)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidCandidateCount),
+            ProgramError::Custom(8)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::TallyOverflow),
+            ProgramError::Custom(9)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidReceiptAccount),
+            ProgramError::Custom(10)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidVotingWindow),
+            ProgramError::Custom(11)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::VotingNotOpen),
+            ProgramError::Custom(12)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::ElectionFinalized),
+            ProgramError::Custom(13)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::ElectionNotFinalized),
+            ProgramError::Custom(14)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::NotEligible),
+            ProgramError::Custom(15)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::WrongEligibilityMode),
+            ProgramError::Custom(16)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidWeight),
+            ProgramError::Custom(17)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidTokenAccount),
+            ProgramError::Custom(18)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::TokensLocked),
+            ProgramError::Custom(19)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::WrongVotingMethod),
+            ProgramError::Custom(20)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InsufficientCredits),
+            ProgramError::Custom(21)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidRanking),
+            ProgramError::Custom(22)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::VotingInProgress),
+            ProgramError::Custom(23)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::AlreadyTabulated),
+            ProgramError::Custom(24)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidReveal),
+            ProgramError::Custom(25)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidCiphertext),
+            ProgramError::Custom(26)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidProof),
+            ProgramError::Custom(27)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidVerifyingKey),
+            ProgramError::Custom(28)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::InvalidRingSignature),
+            ProgramError::Custom(29)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::MissingBallotSignature),
+            ProgramError::Custom(30)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::StaleNonce),
+            ProgramError::Custom(31)
+        );
+        assert_eq!(
+            ProgramError::from(VoteError::TallyUnderflow),
+            ProgramError::Custom(32)
+        );
+
+        assert_eq!(VoteError::from_u32(3), Some(VoteError::AlreadyVoted));
+    }
+}
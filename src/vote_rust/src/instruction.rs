@@ -0,0 +1,1051 @@
+//! Instruction types
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    clock::UnixTimestamp,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+
+use crate::{
+    anonymous::MembershipProof,
+    elgamal::{BallotProof, Ciphertext, DecryptionProof},
+    error::VoteError,
+    ring::RingSignature,
+    signed_ballot::SignedBallot,
+    state::{
+        find_escrow_address, find_nonce_address, find_nullifier_address, find_receipt_address,
+        find_voter_record_address, Eligibility, VotingMethod,
+    },
+};
+
+/// Layout version written as the first byte of every instruction,
+/// bumped whenever the encoding of `VoteInstruction` changes
+pub const INSTRUCTION_VERSION: u8 = 1;
+
+/// Parameters of a new election
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ElectionConfig {
+    /// Hash of the off-chain title and description
+    pub metadata_hash: [u8; 32],
+    /// Hash of each candidate label, one per candidate, between
+    /// `MIN_CANDIDATES` and `MAX_CANDIDATES` entries
+    pub candidate_labels: Vec<[u8; 32]>,
+    /// Unix timestamp voting opens at
+    pub start_time: UnixTimestamp,
+    /// Unix timestamp voting closes at, after `start_time`
+    pub end_time: UnixTimestamp,
+    /// Who may vote
+    pub eligibility: Eligibility,
+    /// How ballots are cast and counted
+    pub voting_method: VotingMethod,
+    /// Groth16 verifying key of the membership circuit, encoded as in
+    /// `anonymous`, when using `Eligibility::AnonymousGroup`, empty otherwise
+    pub verifying_key: Vec<u8>,
+}
+
+/// Instructions supported by the vote program
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum VoteInstruction {
+    /// Initializes a new election account with zeroed tallies.
+    ///
+    /// The election account must be created and assigned to the program
+    /// beforehand, with at least `ElectionState::space` bytes of data.
+    /// Fails if the account already holds an election.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The election authority
+    InitializeElection(ElectionConfig),
+
+    /// Casts the single vote of a voter for a candidate, recording it in a
+    /// vote receipt account derived from the election and the voter.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The voter, paying for the receipt account
+    ///   2. `[writable]` The vote receipt, see `find_receipt_address`
+    ///   3. `[]` The system program
+    ///   4. `[]` The voter record, see `find_voter_record_address`, only
+    ///      checked if the election uses `Eligibility::Registry`
+    ///
+    /// Elections using `Eligibility::TokenBalance` lock the whole balance of
+    /// the voter's token account in escrow until `WithdrawTokens` or
+    /// `RevokeVote`, and expect instead of the voter record:
+    ///
+    ///   4. `[writable]` The voter's token account of the election mint
+    ///   5. `[writable]` The escrow token account, see `find_escrow_address`
+    ///   6. `[]` The election mint
+    ///   7. `[]` The SPL Token program
+    ///   8. `[]` The rent sysvar
+    CastVote {
+        /// Index of the candidate voted for, starting at 0
+        candidate: u8,
+    },
+
+    /// Closes a finalized election and moves its lamports to a destination
+    /// account.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The election authority
+    ///   2. `[writable]` The destination for the reclaimed lamports
+    CloseElection,
+
+    /// Moves the counts of a legacy 8 byte vote account into a new two
+    /// candidate election, then empties the legacy account.
+    ///
+    /// The new election account must be created and assigned to the program
+    /// beforehand, with at least `ElectionState::space(2)` bytes of data.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable, signer]` The legacy vote account
+    ///   1. `[writable]` The new election account
+    ///   2. `[signer]` The authority of the new election
+    MigrateLegacyElection,
+
+    /// Freezes the tallies of an election, rejecting any further votes.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The election authority
+    FinalizeElection,
+
+    /// Adds a voter to the registry of an election using
+    /// `Eligibility::Registry`, creating its voter record.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[]` The election account
+    ///   1. `[writable, signer]` The election authority, paying for the record
+    ///   2. `[writable]` The voter record, see `find_voter_record_address`
+    ///   3. `[]` The system program
+    RegisterVoter {
+        /// The eligible voter
+        voter: Pubkey,
+        /// Weight of the voter's ballot, at least 1
+        weight: u64,
+    },
+
+    /// Removes a voter from the registry, returning the rent of its voter
+    /// record to the authority.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[]` The election account
+    ///   1. `[writable, signer]` The election authority
+    ///   2. `[writable]` The voter record to remove
+    UnregisterVoter,
+
+    /// Casts a vote in an election using `Eligibility::MerkleRoot`, proving
+    /// the voter is a leaf of the tree.
+    ///
+    /// Accounts expected: same as `CastVote`, without the voter record
+    CastVoteWithProof {
+        /// Index of the candidate voted for, starting at 0
+        candidate: u8,
+        /// Weight committed to in the voter's leaf
+        weight: u64,
+        /// Sibling hashes from the voter's leaf up to the root, see
+        /// `merkle::MerkleTree::proof`
+        proof: Vec<[u8; 32]>,
+    },
+
+    /// Returns the tokens a voter locked in an `Eligibility::TokenBalance`
+    /// election once it is finalized, its voting window ended or it was
+    /// closed, and closes the escrow account.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[]` The election account
+    ///   1. `[writable, signer]` The voter, receiving the escrow rent
+    ///   2. `[writable]` The escrow token account, see `find_escrow_address`
+    ///   3. `[writable]` The token account receiving the tokens
+    ///   4. `[]` The SPL Token program
+    WithdrawTokens,
+
+    /// Spends voice credits on votes in an election using
+    /// `VotingMethod::Quadratic`. The first ballot of a voter checks its
+    /// eligibility and grants it the election's credits times its weight,
+    /// later ballots spend what is left.
+    ///
+    /// Accounts expected: same as `CastVote`, the mode specific accounts
+    /// are only read by the first ballot
+    CastQuadraticVote {
+        /// Votes to add per candidate index, buying `k` votes for a
+        /// candidate costs `k²` credits in total
+        allocations: Vec<(u8, u32)>,
+    },
+
+    /// Casts the ranked ballot of a voter in an election using
+    /// `VotingMethod::RankedChoice`, adding its weight to the tally of its
+    /// first preference.
+    ///
+    /// Accounts expected: same as `CastVote`
+    CastRankedVote {
+        /// Distinct candidate indices, most preferred first
+        ranking: Vec<u8>,
+    },
+
+    /// Advances the instant runoff count of a ranked choice election whose
+    /// voting ended, counting the given ballots in the current round. Anyone
+    /// can call it, repeatedly and with any batch of receipts, until the
+    /// winner is recorded in the election. Receipts already counted in the
+    /// current round are skipped.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. ..1+N `[writable]` Vote receipts of the election
+    Tabulate,
+
+    /// Casts the single ballot of a voter in an election using
+    /// `VotingMethod::Approval`, adding its weight to the tally of each
+    /// approved candidate.
+    ///
+    /// Accounts expected: same as `CastVote`
+    CastApprovalVote {
+        /// Bit `i` set approves candidate `i`, no bit may be set past the
+        /// last candidate
+        approvals: u64,
+    },
+
+    /// Moves the vote of a voter to another candidate while voting is open,
+    /// in an election using `VotingMethod::Plurality`. Ballots of other
+    /// voting methods are changed by revoking them and voting again.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The voter
+    ///   2. `[writable]` The vote receipt, see `find_receipt_address`
+    ChangeVote {
+        /// Index of the candidate now voted for
+        candidate: u8,
+    },
+
+    /// Takes a ballot back out of the tallies while voting is open and
+    /// closes its receipt, so the voter may vote again. Tokens escrowed by a
+    /// token weighted vote are returned, the empty escrow account stays
+    /// until `WithdrawTokens`. Encrypted ballots can't be revoked.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The voter, receiving the receipt rent
+    ///   2. `[writable]` The vote receipt, see `find_receipt_address`
+    ///
+    ///   With `Eligibility::TokenBalance`:
+    ///
+    ///   3. `[writable]` The escrow token account, see `find_escrow_address`
+    ///   4. `[writable]` The token account receiving the tokens
+    ///   5. `[]` The SPL Token program
+    RevokeVote,
+
+    /// Commits the hidden ballot of a voter in an election using
+    /// `VotingMethod::CommitReveal` during its voting window. Nothing is
+    /// counted until the ballot is revealed.
+    ///
+    /// Accounts expected: same as `CastVote`
+    CommitVote {
+        /// `vote_commitment` of the candidate and a secret salt
+        commitment: [u8; 32],
+    },
+
+    /// Reveals a committed ballot between the end of voting and the end of
+    /// the reveal phase, adding the voter's weight to the candidate if it
+    /// matches the commitment.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The voter
+    ///   2. `[writable]` The vote receipt, see `find_receipt_address`
+    RevealVote {
+        /// Index of the committed candidate
+        candidate: u8,
+        /// Salt the commitment was made with
+        salt: [u8; 32],
+    },
+
+    /// Casts the encrypted ballot of a voter in an election using
+    /// `VotingMethod::Encrypted`, adding it to the encrypted tallies.
+    /// Ballots without a valid proof are rejected.
+    ///
+    /// Accounts expected: same as `CastVote`
+    CastEncryptedVote {
+        /// One ciphertext per candidate under the trustee's key, encrypting
+        /// 1 for the chosen candidate and 0 for the others
+        ballot: Vec<Ciphertext>,
+        /// Proof that the ballot encrypts a single vote, made for
+        /// `elgamal::ballot_context` of the election and the voter
+        proof: BallotProof,
+    },
+
+    /// Publishes the decrypted tallies of an encrypted election once voting
+    /// ended, and finalizes it. Each tally comes with a proof that it is the
+    /// decryption of the encrypted tally, see `elgamal`.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[signer]` The trustee of the election
+    DecryptTally {
+        /// Plaintext vote count per candidate
+        tallies: Vec<u64>,
+        /// Decryption proof per candidate
+        proofs: Vec<DecryptionProof>,
+    },
+
+    /// Casts an anonymous ballot in an election using
+    /// `Eligibility::AnonymousGroup`, proving membership in the group
+    /// without revealing the member, see `anonymous`. The nullifier hash is
+    /// recorded as spent, a second ballot with it is rejected. Anyone may
+    /// submit the ballot and pay for the record, e.g. a throwaway keypair.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The payer of the nullifier record
+    ///   2. `[writable]` The nullifier record, see `find_nullifier_address`
+    ///   3. `[]` The system program
+    CastAnonymousVote {
+        /// Index of the candidate voted for, starting at 0
+        candidate: u8,
+        /// Nullifier hash of the member in this election
+        nullifier_hash: [u8; 32],
+        /// Proof of membership bound to the candidate and nullifier hash
+        proof: MembershipProof,
+    },
+
+    /// Casts an anonymous ballot in an election using
+    /// `Eligibility::AnonymousRegistry`, signed by a ring of registered
+    /// voters who all carry the weight the ballot counts with. The key image
+    /// of the signature is recorded as spent, a second ballot with it is
+    /// rejected. Anyone may submit the ballot and pay for the record.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The payer of the key image record
+    ///   2. `[writable]` The key image record, see `find_nullifier_address`
+    ///   3. `[]` The system program
+    ///   4. ..4+N `[]` The voter records of the ring members, in ring order
+    CastRingVote {
+        /// Index of the candidate voted for, starting at 0
+        candidate: u8,
+        /// Signature of `[candidate]` with the election address as scope,
+        /// see `ring::sign`
+        signature: RingSignature,
+    },
+
+    /// Casts a single choice ballot the voter signed off-chain, see
+    /// `signed_ballot`, in an election using `Eligibility::Open` or
+    /// `Eligibility::Registry` and `VotingMethod::Plurality`. The transaction
+    /// must carry an Ed25519 signature program instruction verifying the
+    /// voter's signature of the `SignedBallot` message, the payer submitting
+    /// it needs no authority from the voter.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The payer of the receipt and nonce record
+    ///   2. `[writable]` The vote receipt, see `find_receipt_address`
+    ///   3. `[writable]` The nonce record, see `find_nonce_address`
+    ///   4. `[]` The system program
+    ///   5. `[]` The Instructions sysvar
+    ///   6. `[]` The voter record, see `find_voter_record_address`, only
+    ///      checked if the election uses `Eligibility::Registry`
+    CastSignedVote {
+        /// Voter who signed the ballot
+        voter: Pubkey,
+        /// Index of the candidate voted for, starting at 0
+        candidate: u8,
+        /// Nonce of the signed ballot
+        nonce: u64,
+    },
+
+    /// Casts a batch of signed ballots as `CastSignedVote` would, in one
+    /// instruction. The transaction must carry Ed25519 signature program
+    /// instructions verifying every ballot's signature. Each ballot is
+    /// checked in full before any of its accounts is written, an invalid
+    /// one either fails the whole instruction or is skipped, as `policy`
+    /// says. The transaction still fails as a whole if the payer can't fund
+    /// a receipt or nonce record.
+    ///
+    /// Logs the outcome of each ballot and sets the return data to one byte
+    /// per ballot, in order: 1 if it was cast, 0 if it was skipped.
+    ///
+    /// Accounts expected:
+    ///
+    ///   0. `[writable]` The election account
+    ///   1. `[writable, signer]` The payer of the receipts and nonce records
+    ///   2. `[]` The system program
+    ///   3. `[]` The Instructions sysvar
+    ///
+    ///   Then for each ballot, in order:
+    ///
+    ///   0. `[writable]` The vote receipt, see `find_receipt_address`
+    ///   1. `[writable]` The nonce record, see `find_nonce_address`
+    ///   2. `[]` The voter record, see `find_voter_record_address`, only
+    ///      checked if the election uses `Eligibility::Registry`
+    CastSignedVotes {
+        /// What happens to the batch when a ballot is invalid
+        policy: BatchPolicy,
+        /// Ballots of the batch, at least one
+        votes: Vec<SignedVote>,
+    },
+}
+
+/// Ballot of a `CastSignedVotes` batch
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct SignedVote {
+    /// Voter who signed the ballot
+    pub voter: Pubkey,
+    /// Index of the candidate voted for, starting at 0
+    pub candidate: u8,
+    /// Nonce of the signed ballot
+    pub nonce: u64,
+}
+
+/// What a batch of ballots does about an invalid one
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum BatchPolicy {
+    /// The instruction fails and no ballot of the batch is cast
+    AllOrNothing,
+    /// The ballot is skipped and the valid ones are cast
+    BestEffort,
+}
+
+impl VoteInstruction {
+    /// Unpacks a version-prefixed, Borsh encoded byte buffer into a `VoteInstruction`
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&version, rest) = input.split_first().ok_or(VoteError::InvalidInstruction)?;
+
+        if version != INSTRUCTION_VERSION {
+            return Err(VoteError::InvalidInstruction.into());
+        }
+
+        Self::try_from_slice(rest).map_err(|_| VoteError::InvalidInstruction.into())
+    }
+
+    /// Packs a `VoteInstruction` into a version-prefixed byte buffer
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![INSTRUCTION_VERSION];
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+}
+
+/// Creates an `InitializeElection` instruction
+pub fn initialize_election(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+    config: ElectionConfig,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: VoteInstruction::InitializeElection(config).pack(),
+    }
+}
+
+/// Creates a `CastVote` instruction
+pub fn cast_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    candidate: u8,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastVote { candidate }.pack(),
+    }
+}
+
+/// Creates a `CastQuadraticVote` instruction
+pub fn cast_quadratic_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    allocations: Vec<(u8, u32)>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastQuadraticVote { allocations }.pack(),
+    }
+}
+
+/// Creates a `CastRankedVote` instruction
+pub fn cast_ranked_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    ranking: Vec<u8>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastRankedVote { ranking }.pack(),
+    }
+}
+
+/// Creates a `CastApprovalVote` instruction
+pub fn cast_approval_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    approvals: u64,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastApprovalVote { approvals }.pack(),
+    }
+}
+
+/// Creates a `CommitVote` instruction
+pub fn commit_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    commitment: [u8; 32],
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CommitVote { commitment }.pack(),
+    }
+}
+
+/// Creates a `RevealVote` instruction
+pub fn reveal_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    candidate: u8,
+    salt: [u8; 32],
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*voter, true),
+            AccountMeta::new(receipt, false),
+        ],
+        data: VoteInstruction::RevealVote { candidate, salt }.pack(),
+    }
+}
+
+/// Creates a `CastEncryptedVote` instruction
+pub fn cast_encrypted_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    ballot: Vec<Ciphertext>,
+    proof: BallotProof,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastEncryptedVote { ballot, proof }.pack(),
+    }
+}
+
+/// Creates a `DecryptTally` instruction
+pub fn decrypt_tally(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    trustee: &Pubkey,
+    tallies: Vec<u64>,
+    proofs: Vec<DecryptionProof>,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*trustee, true),
+        ],
+        data: VoteInstruction::DecryptTally { tallies, proofs }.pack(),
+    }
+}
+
+/// Creates a `CastAnonymousVote` instruction
+pub fn cast_anonymous_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    payer: &Pubkey,
+    candidate: u8,
+    nullifier_hash: [u8; 32],
+    proof: MembershipProof,
+) -> Instruction {
+    let (nullifier, _) = find_nullifier_address(program_id, election, &nullifier_hash);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(nullifier, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: VoteInstruction::CastAnonymousVote {
+            candidate,
+            nullifier_hash,
+            proof,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CastRingVote` instruction for a ring of registered voters
+pub fn cast_ring_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    payer: &Pubkey,
+    ring: &[Pubkey],
+    candidate: u8,
+    signature: RingSignature,
+) -> Instruction {
+    let (key_image, _) = find_nullifier_address(program_id, election, &signature.key_image);
+    let mut accounts = vec![
+        AccountMeta::new(*election, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(key_image, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(ring.iter().map(|voter| {
+        let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+        AccountMeta::new_readonly(voter_record, false)
+    }));
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VoteInstruction::CastRingVote {
+            candidate,
+            signature,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CastSignedVote` instruction for a ballot signed by `voter`,
+/// to follow the Ed25519 signature program instruction verifying it, see
+/// `signed_ballot::new_ed25519_instruction`
+pub fn cast_signed_vote(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    voter: &Pubkey,
+    ballot: &SignedBallot,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, &ballot.election, voter);
+    let (nonce_record, _) = find_nonce_address(program_id, &ballot.election, voter);
+    let (voter_record, _) = find_voter_record_address(program_id, &ballot.election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(ballot.election, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new(nonce_record, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+            AccountMeta::new_readonly(voter_record, false),
+        ],
+        data: VoteInstruction::CastSignedVote {
+            voter: *voter,
+            candidate: ballot.candidate,
+            nonce: ballot.nonce,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CastSignedVotes` instruction for ballots signed by their
+/// voters, to follow the Ed25519 signature program instructions verifying
+/// them
+pub fn cast_signed_votes(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    payer: &Pubkey,
+    votes: &[SignedVote],
+    policy: BatchPolicy,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*election, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+    ];
+    for vote in votes {
+        let (receipt, _) = find_receipt_address(program_id, election, &vote.voter);
+        let (nonce_record, _) = find_nonce_address(program_id, election, &vote.voter);
+        let (voter_record, _) = find_voter_record_address(program_id, election, &vote.voter);
+        accounts.extend([
+            AccountMeta::new(receipt, false),
+            AccountMeta::new(nonce_record, false),
+            AccountMeta::new_readonly(voter_record, false),
+        ]);
+    }
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VoteInstruction::CastSignedVotes {
+            policy,
+            votes: votes.to_vec(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ChangeVote` instruction
+pub fn change_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    candidate: u8,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*voter, true),
+            AccountMeta::new(receipt, false),
+        ],
+        data: VoteInstruction::ChangeVote { candidate }.pack(),
+    }
+}
+
+/// Creates a `RevokeVote` instruction
+pub fn revoke_vote(program_id: &Pubkey, election: &Pubkey, voter: &Pubkey) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+        ],
+        data: VoteInstruction::RevokeVote.pack(),
+    }
+}
+
+/// Creates a `RevokeVote` instruction for a token weighted vote, returning
+/// the escrowed tokens to `destination`
+pub fn revoke_token_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    let mut instruction = revoke_vote(program_id, election, voter);
+    let (escrow, _) = find_escrow_address(program_id, election, voter);
+    instruction.accounts.extend([
+        AccountMeta::new(escrow, false),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ]);
+    instruction
+}
+
+/// Creates a `Tabulate` instruction counting the ballots at `receipts`
+pub fn tabulate(program_id: &Pubkey, election: &Pubkey, receipts: &[Pubkey]) -> Instruction {
+    let mut accounts = vec![AccountMeta::new(*election, false)];
+    accounts.extend(
+        receipts
+            .iter()
+            .map(|receipt| AccountMeta::new(*receipt, false)),
+    );
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: VoteInstruction::Tabulate.pack(),
+    }
+}
+
+/// Creates a `CastVote` instruction for an election using
+/// `Eligibility::TokenBalance`
+pub fn cast_token_vote(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    voter_token_account: &Pubkey,
+    mint: &Pubkey,
+    candidate: u8,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    let (escrow, _) = find_escrow_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*voter_token_account, false),
+            AccountMeta::new(escrow, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: VoteInstruction::CastVote { candidate }.pack(),
+    }
+}
+
+/// Creates a `CloseElection` instruction
+pub fn close_election(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*destination, false),
+        ],
+        data: VoteInstruction::CloseElection.pack(),
+    }
+}
+
+/// Creates a `FinalizeElection` instruction
+pub fn finalize_election(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: VoteInstruction::FinalizeElection.pack(),
+    }
+}
+
+/// Creates a `CastVoteWithProof` instruction
+pub fn cast_vote_with_proof(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    candidate: u8,
+    weight: u64,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: VoteInstruction::CastVoteWithProof {
+            candidate,
+            weight,
+            proof,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `RegisterVoter` instruction
+pub fn register_voter(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+    voter: &Pubkey,
+    weight: u64,
+) -> Instruction {
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*election, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(voter_record, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: VoteInstruction::RegisterVoter {
+            voter: *voter,
+            weight,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `UnregisterVoter` instruction
+pub fn unregister_voter(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+    voter: &Pubkey,
+) -> Instruction {
+    let (voter_record, _) = find_voter_record_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*election, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(voter_record, false),
+        ],
+        data: VoteInstruction::UnregisterVoter.pack(),
+    }
+}
+
+/// Creates a `MigrateLegacyElection` instruction
+pub fn migrate_legacy_election(
+    program_id: &Pubkey,
+    legacy: &Pubkey,
+    election: &Pubkey,
+    authority: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*legacy, true),
+            AccountMeta::new(*election, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: VoteInstruction::MigrateLegacyElection.pack(),
+    }
+}
+
+/// Creates a `WithdrawTokens` instruction
+pub fn withdraw_tokens(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    let (escrow, _) = find_escrow_address(program_id, election, voter);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*election, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(escrow, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: VoteInstruction::WithdrawTokens.pack(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pack_unpack() {
+        let instruction = VoteInstruction::CastVote { candidate: 2 };
+        let packed = instruction.pack();
+        assert_eq!(packed, vec![INSTRUCTION_VERSION, 1, 2]);
+        assert_eq!(VoteInstruction::unpack(&packed).unwrap(), instruction);
+
+        let instruction = VoteInstruction::CastVoteWithProof {
+            candidate: 1,
+            weight: 5,
+            proof: vec![[3; 32], [4; 32]],
+        };
+        let packed = instruction.pack();
+        assert_eq!(VoteInstruction::unpack(&packed).unwrap(), instruction);
+    }
+
+    #[test]
+    fn test_unpack_rejects_malformed() {
+        // empty payload
+        assert_eq!(
+            VoteInstruction::unpack(&[]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+
+        // wrong version
+        assert_eq!(
+            VoteInstruction::unpack(&[INSTRUCTION_VERSION + 1, 1, 2]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+
+        // unknown variant
+        assert_eq!(
+            VoteInstruction::unpack(&[INSTRUCTION_VERSION, 99]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+
+        // truncated payload and trailing bytes
+        assert_eq!(
+            VoteInstruction::unpack(&[INSTRUCTION_VERSION, 1]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            VoteInstruction::unpack(&[INSTRUCTION_VERSION, 1, 2, 3]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+    }
+}
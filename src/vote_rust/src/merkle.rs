@@ -0,0 +1,142 @@
+//! Merkle tree of eligible voters
+//!
+//! Each leaf commits to a voter and the weight of its vote. Leaves and
+//! inner nodes are domain separated SHA-256 hashes, and the two children of
+//! a node are hashed in sorted order so a proof is just the list of sibling
+//! hashes from the leaf up to the root. A node without a sibling is carried
+//! up to the next level unchanged.
+
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+/// Deepest proof accepted on-chain, enough for 2^32 voters
+pub const MAX_PROOF_LEN: usize = 32;
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Hash of the leaf committing to an eligible voter and its vote weight
+pub fn leaf_hash(voter: &Pubkey, weight: u64) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, voter.as_ref(), &weight.to_le_bytes()]).to_bytes()
+}
+
+/// Hash of the parent of two nodes
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let (first, second) = if left <= right {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    hashv(&[NODE_PREFIX, first, second]).to_bytes()
+}
+
+/// Checks that `proof` links `leaf` to `root`
+pub fn verify_proof(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    if proof.len() > MAX_PROOF_LEN {
+        return false;
+    }
+    let computed = proof
+        .iter()
+        .fold(leaf, |node, sibling| node_hash(&node, sibling));
+    computed == *root
+}
+
+/// Complete tree over a list of leaves, for building roots and proofs off-chain
+pub struct MerkleTree {
+    // levels[0] holds the leaves, the last level holds the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree, `leaves` must not be empty
+    pub fn new(leaves: Vec<[u8; 32]>) -> Self {
+        assert!(!leaves.is_empty(), "a Merkle tree needs at least one leaf");
+
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    /// Root to store in the election
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().unwrap()[0]
+    }
+
+    /// Number of leaves in the tree
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Always `false`, trees are never empty
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Sibling hashes linking the leaf at `index` to the root
+    pub fn proof(&self, mut index: usize) -> Vec<[u8; 32]> {
+        assert!(index < self.len(), "leaf index out of range");
+
+        let mut proof = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proofs_verify() {
+        for size in [1, 2, 3, 5, 8, 13].iter() {
+            let voters: Vec<Pubkey> = (0..*size).map(|_| Pubkey::new_unique()).collect();
+            let tree = MerkleTree::new(voters.iter().map(|voter| leaf_hash(voter, 1)).collect());
+            let root = tree.root();
+
+            for (index, voter) in voters.iter().enumerate() {
+                let proof = tree.proof(index);
+                assert!(verify_proof(&root, leaf_hash(voter, 1), &proof));
+
+                // the proof is bound to its voter and weight
+                assert!(!verify_proof(
+                    &root,
+                    leaf_hash(&Pubkey::new_unique(), 1),
+                    &proof
+                ));
+                assert!(!verify_proof(&root, leaf_hash(voter, 2), &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_proofs_fail() {
+        let voters: Vec<Pubkey> = (0..6).map(|_| Pubkey::new_unique()).collect();
+        let tree = MerkleTree::new(voters.iter().map(|voter| leaf_hash(voter, 1)).collect());
+        let root = tree.root();
+
+        let mut proof = tree.proof(4);
+        proof[0][0] ^= 1;
+        assert!(!verify_proof(&root, leaf_hash(&voters[4], 1), &proof));
+
+        // overlong proofs are rejected outright
+        let proof = vec![[0; 32]; MAX_PROOF_LEN + 1];
+        assert!(!verify_proof(&root, leaf_hash(&voters[0], 1), &proof));
+    }
+}
@@ -0,0 +1,4846 @@
+//! Program state processor
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    log::sol_log_compute_units,
+    msg,
+    program::{invoke, invoke_signed, set_return_data},
+    program_error::{PrintProgramError, ProgramError},
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    anonymous::{self, MembershipProof},
+    elgamal::{self, BallotProof, Ciphertext, DecryptionProof},
+    error::VoteError,
+    instruction::{BatchPolicy, ElectionConfig, SignedVote, VoteInstruction},
+    merkle,
+    ring::{self, RingSignature, MAX_RING_SIZE},
+    signed_ballot::{self, SignedBallot},
+    state::{
+        vote_commitment, ElectionState, Eligibility, NonceRecord, NullifierRecord, VoteReceipt,
+        VoterRecord, VotingMethod, ESCROW_SEED, LEGACY_ELECTION_LEN, MAX_CANDIDATES,
+        MIN_CANDIDATES, NONCE_SEED, NULLIFIER_SEED, RECEIPT_SEED, VOTER_RECORD_SEED,
+    },
+};
+use spl_token::state::Account as TokenAccount;
+
+// Decode and dispatch an instruction
+pub fn process(
+    program_id: &Pubkey,      // Public key of program account
+    accounts: &[AccountInfo], // data accounts
+    instruction_data: &[u8],  // version-prefixed, Borsh encoded VoteInstruction
+) -> ProgramResult {
+    let instruction = VoteInstruction::unpack(instruction_data)?;
+
+    match instruction {
+        VoteInstruction::InitializeElection(config) => {
+            msg!("Instruction: InitializeElection");
+            process_initialize_election(program_id, accounts, config)
+        }
+        VoteInstruction::CastVote { candidate } => {
+            msg!("Instruction: CastVote");
+            process_cast_vote(program_id, accounts, Ballot::Single(candidate), None)
+        }
+        VoteInstruction::CloseElection => {
+            msg!("Instruction: CloseElection");
+            process_close_election(program_id, accounts)
+        }
+        VoteInstruction::MigrateLegacyElection => {
+            msg!("Instruction: MigrateLegacyElection");
+            process_migrate_legacy_election(program_id, accounts)
+        }
+        VoteInstruction::FinalizeElection => {
+            msg!("Instruction: FinalizeElection");
+            process_finalize_election(program_id, accounts)
+        }
+        VoteInstruction::RegisterVoter { voter, weight } => {
+            msg!("Instruction: RegisterVoter");
+            process_register_voter(program_id, accounts, voter, weight)
+        }
+        VoteInstruction::UnregisterVoter => {
+            msg!("Instruction: UnregisterVoter");
+            process_unregister_voter(program_id, accounts)
+        }
+        VoteInstruction::CastVoteWithProof {
+            candidate,
+            weight,
+            proof,
+        } => {
+            msg!("Instruction: CastVoteWithProof");
+            process_cast_vote(
+                program_id,
+                accounts,
+                Ballot::Single(candidate),
+                Some((weight, &proof)),
+            )
+        }
+        VoteInstruction::WithdrawTokens => {
+            msg!("Instruction: WithdrawTokens");
+            process_withdraw_tokens(program_id, accounts)
+        }
+        VoteInstruction::CastQuadraticVote { allocations } => {
+            msg!("Instruction: CastQuadraticVote");
+            process_cast_vote(program_id, accounts, Ballot::Quadratic(&allocations), None)
+        }
+        VoteInstruction::CastRankedVote { ranking } => {
+            msg!("Instruction: CastRankedVote");
+            process_cast_vote(program_id, accounts, Ballot::Ranked(&ranking), None)
+        }
+        VoteInstruction::Tabulate => {
+            msg!("Instruction: Tabulate");
+            process_tabulate(program_id, accounts)
+        }
+        VoteInstruction::CastApprovalVote { approvals } => {
+            msg!("Instruction: CastApprovalVote");
+            process_cast_vote(program_id, accounts, Ballot::Approval(approvals), None)
+        }
+        VoteInstruction::ChangeVote { candidate } => {
+            msg!("Instruction: ChangeVote");
+            process_change_vote(program_id, accounts, candidate)
+        }
+        VoteInstruction::RevokeVote => {
+            msg!("Instruction: RevokeVote");
+            process_revoke_vote(program_id, accounts)
+        }
+        VoteInstruction::CommitVote { commitment } => {
+            msg!("Instruction: CommitVote");
+            process_cast_vote(program_id, accounts, Ballot::Commitment(commitment), None)
+        }
+        VoteInstruction::RevealVote { candidate, salt } => {
+            msg!("Instruction: RevealVote");
+            process_reveal_vote(program_id, accounts, candidate, salt)
+        }
+        VoteInstruction::CastEncryptedVote { ballot, proof } => {
+            msg!("Instruction: CastEncryptedVote");
+            process_cast_vote(
+                program_id,
+                accounts,
+                Ballot::Encrypted(&ballot, &proof),
+                None,
+            )
+        }
+        VoteInstruction::DecryptTally { tallies, proofs } => {
+            msg!("Instruction: DecryptTally");
+            process_decrypt_tally(program_id, accounts, tallies, proofs)
+        }
+        VoteInstruction::CastAnonymousVote {
+            candidate,
+            nullifier_hash,
+            proof,
+        } => {
+            msg!("Instruction: CastAnonymousVote");
+            process_cast_anonymous_vote(program_id, accounts, candidate, nullifier_hash, &proof)
+        }
+        VoteInstruction::CastRingVote {
+            candidate,
+            signature,
+        } => {
+            msg!("Instruction: CastRingVote");
+            process_cast_ring_vote(program_id, accounts, candidate, &signature)
+        }
+        VoteInstruction::CastSignedVote {
+            voter,
+            candidate,
+            nonce,
+        } => {
+            msg!("Instruction: CastSignedVote");
+            let vote = SignedVote {
+                voter,
+                candidate,
+                nonce,
+            };
+            process_cast_signed_vote(program_id, accounts, vote)
+        }
+        VoteInstruction::CastSignedVotes { policy, votes } => {
+            msg!("Instruction: CastSignedVotes");
+            process_cast_signed_votes(program_id, accounts, policy, votes)
+        }
+    }
+}
+
+// The account must be owned by the program in order to modify its data
+fn check_program_account(program_id: &Pubkey, account: &AccountInfo) -> ProgramResult {
+    if account.owner != program_id {
+        msg!("Election account is not owned by the program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+// Write a new election into an uninitialized account large enough to hold it
+fn write_new_election(election_info: &AccountInfo, state: &ElectionState) -> ProgramResult {
+    let mut data = election_info.try_borrow_mut_data()?;
+
+    if ElectionState::unpack_unchecked(&data)?.is_initialized() {
+        return Err(VoteError::ElectionAlreadyInitialized.into());
+    }
+
+    // the tally region is sized by the candidate count
+    if data.len() < state.required_space() {
+        msg!("Election account data length too small for the tallies");
+        return Err(VoteError::InvalidElectionAccount.into());
+    }
+
+    state.pack(&mut data)
+}
+
+// Move all lamports out of an account and wipe its data, the runtime
+// garbage collects the emptied account at the end of the transaction
+fn drain_account(source_info: &AccountInfo, destination_info: &AccountInfo) -> ProgramResult {
+    let mut source_lamports = source_info.try_borrow_mut_lamports()?;
+    let mut destination_lamports = destination_info.try_borrow_mut_lamports()?;
+    **destination_lamports = destination_lamports
+        .checked_add(**source_lamports)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    **source_lamports = 0;
+
+    // wipe the data so the account can't be revived within this transaction
+    for byte in source_info.try_borrow_mut_data()?.iter_mut() {
+        *byte = 0;
+    }
+
+    Ok(())
+}
+
+// Create an account owned by `owner` at a program derived address
+fn create_pda_account<'a>(
+    payer_info: &AccountInfo<'a>,
+    new_account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    owner: &Pubkey,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = Rent::get()?
+        .minimum_balance(space)
+        .max(1)
+        .saturating_sub(new_account_info.lamports());
+
+    if new_account_info.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                new_account_info.key,
+                required_lamports,
+                space as u64,
+                owner,
+            ),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[signer_seeds],
+        );
+    }
+
+    // someone already funded the address, which makes create_account fail,
+    // so top it up and allocate and assign it separately
+    if required_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, new_account_info.key, required_lamports),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(new_account_info.key, space as u64),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(new_account_info.key, owner),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
+}
+
+// The authority must sign and match the one stored in the election
+fn check_authority(state: &ElectionState, authority: &AccountInfo) -> ProgramResult {
+    if !authority.is_signer {
+        msg!("Election authority must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if state.authority != *authority.key {
+        return Err(VoteError::InvalidAuthority.into());
+    }
+    Ok(())
+}
+
+// The voter record must be the election's registration of the voter
+fn check_voter_record(
+    program_id: &Pubkey,
+    election_info: &AccountInfo,
+    voter: &Pubkey,
+    record_info: &AccountInfo,
+) -> Result<VoterRecord, ProgramError> {
+    if record_info.owner != program_id {
+        msg!("Voter {} is not registered", voter);
+        return Err(VoteError::NotEligible.into());
+    }
+
+    // records are only ever written at their derived address, so matching
+    // fields are enough to tie the account to the voter
+    let record = VoterRecord::unpack(&record_info.try_borrow_data()?)?;
+    if record.election != *election_info.key || record.voter != *voter {
+        msg!("Voter record belongs to another voter");
+        return Err(VoteError::NotEligible.into());
+    }
+
+    Ok(record)
+}
+
+// The account must be an initialized SPL Token account
+fn unpack_token_account(token_account_info: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if *token_account_info.owner != spl_token::id() {
+        return Err(VoteError::InvalidTokenAccount.into());
+    }
+    TokenAccount::unpack(&token_account_info.try_borrow_data()?)
+        .map_err(|_| VoteError::InvalidTokenAccount.into())
+}
+
+fn check_token_program(token_program_info: &AccountInfo) -> ProgramResult {
+    if *token_program_info.key != spl_token::id() {
+        msg!("Not the SPL Token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+// Accounts moving the balance of a token weighted voter into escrow
+struct TokenEscrowAccounts<'a, 'b> {
+    source_info: &'a AccountInfo<'b>,
+    escrow_info: &'a AccountInfo<'b>,
+    mint_info: &'a AccountInfo<'b>,
+    token_program_info: &'a AccountInfo<'b>,
+    rent_info: &'a AccountInfo<'b>,
+}
+
+// Lock `amount` tokens of the voter in its escrow account, creating it on
+// the first vote
+fn escrow_tokens<'a>(
+    program_id: &Pubkey,
+    election_info: &AccountInfo<'a>,
+    voter_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    accounts: &TokenEscrowAccounts<'_, 'a>,
+    amount: u64,
+) -> ProgramResult {
+    let escrow_info = accounts.escrow_info;
+
+    let (escrow_key, bump_seed) = Pubkey::find_program_address(
+        &[
+            ESCROW_SEED,
+            election_info.key.as_ref(),
+            voter_info.key.as_ref(),
+        ],
+        program_id,
+    );
+    if escrow_key != *escrow_info.key {
+        return Err(VoteError::InvalidTokenAccount.into());
+    }
+
+    // the escrow owns itself, so only this program can sign for its tokens
+    if escrow_info.data_is_empty() {
+        create_pda_account(
+            voter_info,
+            escrow_info,
+            system_program_info,
+            &spl_token::id(),
+            TokenAccount::LEN,
+            &[
+                ESCROW_SEED,
+                election_info.key.as_ref(),
+                voter_info.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+        invoke(
+            &spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                escrow_info.key,
+                accounts.mint_info.key,
+                escrow_info.key,
+            )?,
+            &[
+                escrow_info.clone(),
+                accounts.mint_info.clone(),
+                escrow_info.clone(),
+                accounts.rent_info.clone(),
+                accounts.token_program_info.clone(),
+            ],
+        )?;
+    }
+
+    invoke(
+        &spl_token::instruction::transfer(
+            &spl_token::id(),
+            accounts.source_info.key,
+            escrow_info.key,
+            voter_info.key,
+            &[],
+            amount,
+        )?,
+        &[
+            accounts.source_info.clone(),
+            escrow_info.clone(),
+            voter_info.clone(),
+            accounts.token_program_info.clone(),
+        ],
+    )
+}
+
+// The escrow account must be the voter's, returns the bump seed of its
+// address
+fn check_escrow_account(
+    program_id: &Pubkey,
+    election_info: &AccountInfo,
+    voter_info: &AccountInfo,
+    escrow_info: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (escrow_key, bump_seed) = Pubkey::find_program_address(
+        &[
+            ESCROW_SEED,
+            election_info.key.as_ref(),
+            voter_info.key.as_ref(),
+        ],
+        program_id,
+    );
+    if escrow_key != *escrow_info.key {
+        return Err(VoteError::InvalidTokenAccount.into());
+    }
+    Ok(bump_seed)
+}
+
+// Move every token of the voter's escrow account to `destination_info`,
+// returns the amount moved
+fn release_escrow<'a>(
+    election_info: &AccountInfo<'a>,
+    voter_info: &AccountInfo<'a>,
+    escrow_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    bump_seed: u8,
+) -> Result<u64, ProgramError> {
+    let amount = unpack_token_account(escrow_info)?.amount;
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            &spl_token::id(),
+            escrow_info.key,
+            destination_info.key,
+            escrow_info.key,
+            &[],
+            amount,
+        )?,
+        &[
+            escrow_info.clone(),
+            destination_info.clone(),
+            escrow_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[&[
+            ESCROW_SEED,
+            election_info.key.as_ref(),
+            voter_info.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )?;
+    Ok(amount)
+}
+
+// The receipt must record the ballot the signing voter cast in the election
+fn check_vote_receipt(
+    program_id: &Pubkey,
+    election_info: &AccountInfo,
+    voter_info: &AccountInfo,
+    receipt_info: &AccountInfo,
+) -> Result<VoteReceipt, ProgramError> {
+    if !voter_info.is_signer {
+        msg!("Voter must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_program_account(program_id, receipt_info)?;
+
+    // receipts are only ever written at their derived address, so matching
+    // fields are enough to tie the account to the voter
+    let receipt = VoteReceipt::unpack(&receipt_info.try_borrow_data()?)?;
+    if receipt.election != *election_info.key || receipt.voter != *voter_info.key {
+        msg!("Vote receipt belongs to another voter");
+        return Err(VoteError::InvalidReceiptAccount.into());
+    }
+
+    Ok(receipt)
+}
+
+fn process_initialize_election(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: ElectionConfig,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    if !authority_info.is_signer {
+        msg!("Election authority must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let candidate_count = config.candidate_labels.len();
+    if candidate_count < MIN_CANDIDATES as usize || candidate_count > MAX_CANDIDATES as usize {
+        msg!("Candidate count {} out of range", candidate_count);
+        return Err(VoteError::InvalidCandidateCount.into());
+    }
+
+    if config.start_time >= config.end_time {
+        return Err(VoteError::InvalidVotingWindow.into());
+    }
+    if let VotingMethod::CommitReveal { reveal_end_time } = config.voting_method {
+        if config.end_time >= reveal_end_time {
+            return Err(VoteError::InvalidVotingWindow.into());
+        }
+    }
+
+    // only single choice ballots carry a Merkle or membership proof or a
+    // ring signature
+    if config.voting_method != VotingMethod::Plurality {
+        if let Eligibility::MerkleRoot(_)
+        | Eligibility::AnonymousGroup(_)
+        | Eligibility::AnonymousRegistry = config.eligibility
+        {
+            return Err(VoteError::WrongVotingMethod.into());
+        }
+    }
+    if let Eligibility::AnonymousGroup(_) = config.eligibility {
+        if !anonymous::is_valid_verifying_key(&config.verifying_key) {
+            msg!("Verifying key does not fit the membership circuit");
+            return Err(VoteError::InvalidVerifyingKey.into());
+        }
+    } else if !config.verifying_key.is_empty() {
+        return Err(VoteError::WrongEligibilityMode.into());
+    }
+    if let VotingMethod::Encrypted { public_key, .. } = config.voting_method {
+        if !elgamal::is_valid_public_key(&public_key) {
+            msg!("Trustee key is not a valid Ristretto point");
+            return Err(VoteError::InvalidCiphertext.into());
+        }
+        // each candidate adds a ciphertext and a proof to every ballot
+        if candidate_count > elgamal::MAX_ENCRYPTED_CANDIDATES as usize {
+            msg!(
+                "Encrypted ballots fit at most {} candidates",
+                elgamal::MAX_ENCRYPTED_CANDIDATES
+            );
+            return Err(VoteError::InvalidCandidateCount.into());
+        }
+        // weighted ballots could push a tally past what the trustee can
+        // find by search, ballots weighing 1 keep it below the voter count
+        if config.eligibility != Eligibility::Open {
+            msg!("Encrypted elections are only open to every voter, unweighted");
+            return Err(VoteError::WrongEligibilityMode.into());
+        }
+    }
+
+    let mut state = ElectionState::new(*authority_info.key, candidate_count as u8);
+    state.metadata_hash = config.metadata_hash;
+    state.candidate_labels = config.candidate_labels;
+    state.start_time = config.start_time;
+    state.end_time = config.end_time;
+    state.eligibility = config.eligibility;
+    state.voting_method = config.voting_method;
+    state.verifying_key = config.verifying_key;
+    if let VotingMethod::Encrypted { .. } = state.voting_method {
+        state.encrypted_tallies = vec![Ciphertext::default(); candidate_count];
+    }
+
+    write_new_election(election_info, &state)?;
+
+    msg!("Election initialized");
+
+    Ok(())
+}
+
+// Choices carried by a vote instruction
+enum Ballot<'a> {
+    // all of the voter's weight on one candidate
+    Single(u8),
+    // votes to buy per candidate with voice credits
+    Quadratic(&'a [(u8, u32)]),
+    // candidates in order of preference
+    Ranked(&'a [u8]),
+    // bitset of approved candidates
+    Approval(u64),
+    // hash of a single candidate and a salt, revealed later
+    Commitment([u8; 32]),
+    // one ciphertext per candidate and the proof that they encrypt one vote
+    Encrypted(&'a [Ciphertext], &'a BallotProof),
+}
+
+fn process_cast_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    ballot: Ballot,
+    proof: Option<(u64, &[[u8; 32]])>, // weight claimed in the voter's leaf and its Merkle proof
+) -> ProgramResult {
+    // Iterating accounts is safer then indexing
+    let accounts_iter = &mut accounts.iter();
+
+    // Get the account that holds the vote count
+    let election_info = next_account_info(accounts_iter)?;
+    let voter_info = next_account_info(accounts_iter)?;
+    let receipt_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    if !voter_info.is_signer {
+        msg!("Voter must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    let candidates = match (&state.voting_method, &ballot) {
+        (VotingMethod::Plurality, Ballot::Single(candidate)) => vec![*candidate],
+        (VotingMethod::Quadratic { .. }, Ballot::Quadratic(allocations)) => allocations
+            .iter()
+            .map(|(candidate, _)| *candidate)
+            .collect(),
+        (VotingMethod::RankedChoice, Ballot::Ranked(ranking)) => {
+            let mut ranked = [false; 256];
+            for candidate in ranking.iter() {
+                if std::mem::replace(&mut ranked[*candidate as usize], true) {
+                    msg!("Candidate {} ranked twice", candidate);
+                    return Err(VoteError::InvalidRanking.into());
+                }
+            }
+            if ranking.is_empty() {
+                return Err(VoteError::InvalidRanking.into());
+            }
+            ranking.to_vec()
+        }
+        (VotingMethod::Approval, Ballot::Approval(approvals)) => {
+            if *approvals == 0 {
+                msg!("Ballot approves no candidate");
+                return Err(VoteError::InvalidCandidate.into());
+            }
+            (0..64)
+                .filter(|candidate| approvals & (1 << candidate) != 0)
+                .collect()
+        }
+        // the candidate is only checked once revealed
+        (VotingMethod::CommitReveal { .. }, Ballot::Commitment(_)) => vec![],
+        (VotingMethod::Encrypted { public_key, .. }, Ballot::Encrypted(ballot, proof)) => {
+            if ballot.len() != state.candidate_count as usize {
+                msg!("Ballot must hold one ciphertext per candidate");
+                return Err(VoteError::InvalidCandidateCount.into());
+            }
+            // a ballot encrypting anything but a single vote would skew the
+            // tallies unnoticed
+            let context = elgamal::ballot_context(election_info.key, voter_info.key);
+            if !proof.verify(public_key, ballot, &context) {
+                msg!("Ballot validity proof does not verify");
+                return Err(VoteError::InvalidProof.into());
+            }
+            vec![]
+        }
+        _ => return Err(VoteError::WrongVotingMethod.into()),
+    };
+    if let Some(candidate) = candidates
+        .iter()
+        .find(|candidate| **candidate >= state.candidate_count)
+    {
+        msg!("Unknown candidate {}", candidate);
+        return Err(VoteError::InvalidCandidate.into());
+    }
+
+    // one receipt per (election, voter), its existence is what blocks a second vote
+    let (receipt_key, bump_seed) = Pubkey::find_program_address(
+        &[
+            RECEIPT_SEED,
+            election_info.key.as_ref(),
+            voter_info.key.as_ref(),
+        ],
+        program_id,
+    );
+    if receipt_key != *receipt_info.key {
+        return Err(VoteError::InvalidReceiptAccount.into());
+    }
+
+    if receipt_info.data_is_empty() {
+        let space = match state.voting_method {
+            VotingMethod::Plurality
+            | VotingMethod::Approval
+            | VotingMethod::CommitReveal { .. }
+            | VotingMethod::Encrypted { .. } => VoteReceipt::LEN,
+            VotingMethod::Quadratic { .. } => VoteReceipt::quadratic_space(state.candidate_count),
+            VotingMethod::RankedChoice => VoteReceipt::ranked_space(state.candidate_count),
+        };
+        create_pda_account(
+            voter_info,
+            receipt_info,
+            system_program_info,
+            program_id,
+            space,
+            &[
+                RECEIPT_SEED,
+                election_info.key.as_ref(),
+                voter_info.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+    }
+
+    check_program_account(program_id, receipt_info)?;
+
+    let mut receipt_data = receipt_info.try_borrow_mut_data()?;
+    let mut receipt = VoteReceipt::unpack_unchecked(&receipt_data)?;
+    let mut token_escrow = None;
+
+    if receipt.is_initialized() {
+        // quadratic voters keep spending the credits granted by their first ballot
+        if let Ballot::Single(_)
+        | Ballot::Ranked(_)
+        | Ballot::Approval(_)
+        | Ballot::Commitment(_)
+        | Ballot::Encrypted(..) = ballot
+        {
+            msg!("Voter {} already voted", voter_info.key);
+            return Err(VoteError::AlreadyVoted.into());
+        }
+    } else {
+        // every ballot in an open election weighs the same
+        let weight = match (&state.eligibility, proof) {
+            (Eligibility::Open, None) => 1,
+            (Eligibility::Registry, None) => {
+                let record_info = next_account_info(accounts_iter)?;
+                check_voter_record(program_id, election_info, voter_info.key, record_info)?.weight
+            }
+            (Eligibility::MerkleRoot(root), Some((weight, proof))) => {
+                let leaf = merkle::leaf_hash(voter_info.key, weight);
+                if weight == 0 || !merkle::verify_proof(root, leaf, proof) {
+                    msg!("Voter {} is not in the eligibility tree", voter_info.key);
+                    return Err(VoteError::NotEligible.into());
+                }
+                weight
+            }
+            (Eligibility::TokenBalance(mint), None) => {
+                let accounts = TokenEscrowAccounts {
+                    source_info: next_account_info(accounts_iter)?,
+                    escrow_info: next_account_info(accounts_iter)?,
+                    mint_info: next_account_info(accounts_iter)?,
+                    token_program_info: next_account_info(accounts_iter)?,
+                    rent_info: next_account_info(accounts_iter)?,
+                };
+                check_token_program(accounts.token_program_info)?;
+
+                let source = unpack_token_account(accounts.source_info)?;
+                if source.owner != *voter_info.key || source.mint != *mint {
+                    msg!("Token account is not the voter's account of the election mint");
+                    return Err(VoteError::InvalidTokenAccount.into());
+                }
+                if source.amount == 0 {
+                    return Err(VoteError::InvalidWeight.into());
+                }
+
+                token_escrow = Some(accounts);
+                source.amount
+            }
+            (Eligibility::MerkleRoot(_), None) => {
+                msg!("A Merkle proof of eligibility is required");
+                return Err(VoteError::NotEligible.into());
+            }
+            (Eligibility::AnonymousGroup(_), None) | (Eligibility::AnonymousRegistry, None) => {
+                msg!("Anonymous elections only take anonymous ballots");
+                return Err(VoteError::WrongEligibilityMode.into());
+            }
+            (_, Some(_)) => return Err(VoteError::WrongEligibilityMode.into()),
+        };
+
+        receipt = match state.voting_method {
+            VotingMethod::Plurality
+            | VotingMethod::RankedChoice
+            | VotingMethod::Approval
+            | VotingMethod::CommitReveal { .. }
+            | VotingMethod::Encrypted { .. } => {
+                VoteReceipt::new(*election_info.key, *voter_info.key, 0, weight, bump_seed)
+            }
+            VotingMethod::Quadratic { credits } => VoteReceipt::new_quadratic(
+                *election_info.key,
+                *voter_info.key,
+                weight,
+                bump_seed,
+                credits.saturating_mul(weight),
+                state.candidate_count,
+            ),
+        };
+    }
+
+    match ballot {
+        Ballot::Single(candidate) => {
+            receipt.candidate = candidate;
+            state.add_votes(candidate, receipt.weight)?;
+            msg!("Voted for {} with weight {}", candidate, receipt.weight);
+        }
+        Ballot::Quadratic(allocations) => {
+            for (candidate, votes) in allocations {
+                receipt.add_quadratic_votes(*candidate, *votes)?;
+                state.add_votes(*candidate, *votes as u64)?;
+            }
+            msg!(
+                "Cast quadratic votes, {} credits left",
+                receipt.credits_remaining
+            );
+        }
+        Ballot::Ranked(ranking) => {
+            receipt.ranking = ranking.to_vec();
+            state.add_votes(ranking[0], receipt.weight)?;
+            state.tabulation.ballot_count = state
+                .tabulation
+                .ballot_count
+                .checked_add(1)
+                .ok_or(VoteError::TallyOverflow)?;
+            msg!("Ranked {} candidates", ranking.len());
+        }
+        Ballot::Approval(approvals) => {
+            receipt.approvals = approvals;
+            for candidate in candidates {
+                state.add_votes(candidate, receipt.weight)?;
+            }
+            msg!("Approved {} candidates", approvals.count_ones());
+        }
+        Ballot::Commitment(commitment) => {
+            receipt.commitment = commitment;
+            state.unrevealed_commits = state
+                .unrevealed_commits
+                .checked_add(1)
+                .ok_or(VoteError::TallyOverflow)?;
+            msg!("Ballot committed");
+        }
+        Ballot::Encrypted(ballot, _) => {
+            // only open elections, whose ballots all weigh 1, are encrypted
+            for (tally, ciphertext) in state.encrypted_tallies.iter_mut().zip(ballot) {
+                *tally = tally.checked_add(ciphertext)?;
+            }
+            msg!("Encrypted ballot added");
+        }
+    }
+
+    receipt.pack(&mut receipt_data)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    // moving the tokens out keeps them from being voted with again
+    if let Some(accounts) = token_escrow {
+        escrow_tokens(
+            program_id,
+            election_info,
+            voter_info,
+            system_program_info,
+            &accounts,
+            receipt.weight,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn process_cast_anonymous_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    candidate: u8,
+    nullifier_hash: [u8; 32],
+    proof: &MembershipProof,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let payer_info = next_account_info(accounts_iter)?;
+    let nullifier_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    if !payer_info.is_signer {
+        msg!("Payer must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    let root = match state.eligibility {
+        Eligibility::AnonymousGroup(root) => root,
+        _ => return Err(VoteError::WrongEligibilityMode.into()),
+    };
+    if candidate >= state.candidate_count {
+        msg!("Unknown candidate {}", candidate);
+        return Err(VoteError::InvalidCandidate.into());
+    }
+
+    // one record per (election, nullifier hash), its existence is what
+    // blocks a second vote of the same member
+    let (nullifier_key, bump_seed) = Pubkey::find_program_address(
+        &[NULLIFIER_SEED, election_info.key.as_ref(), &nullifier_hash],
+        program_id,
+    );
+    if nullifier_key != *nullifier_info.key {
+        return Err(VoteError::InvalidReceiptAccount.into());
+    }
+
+    // checked before paying for the record, the proof also rejects
+    // non-canonical encodings of a spent nullifier hash
+    if !anonymous::verify_membership(
+        &state.verifying_key,
+        &root,
+        &nullifier_hash,
+        candidate,
+        election_info.key,
+        proof,
+    ) {
+        msg!("Membership proof does not verify");
+        return Err(VoteError::InvalidProof.into());
+    }
+
+    if nullifier_info.data_is_empty() {
+        create_pda_account(
+            payer_info,
+            nullifier_info,
+            system_program_info,
+            program_id,
+            NullifierRecord::LEN,
+            &[
+                NULLIFIER_SEED,
+                election_info.key.as_ref(),
+                &nullifier_hash,
+                &[bump_seed],
+            ],
+        )?;
+    }
+
+    check_program_account(program_id, nullifier_info)?;
+
+    let mut nullifier_data = nullifier_info.try_borrow_mut_data()?;
+    if NullifierRecord::unpack_unchecked(&nullifier_data)?.is_initialized() {
+        msg!("Nullifier hash already spent");
+        return Err(VoteError::AlreadyVoted.into());
+    }
+
+    state.add_votes(candidate, 1)?;
+
+    NullifierRecord::new(*election_info.key, nullifier_hash, candidate, bump_seed)
+        .pack(&mut nullifier_data)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!("Anonymous vote for {}", candidate);
+
+    Ok(())
+}
+
+fn process_cast_ring_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    candidate: u8,
+    signature: &RingSignature,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let payer_info = next_account_info(accounts_iter)?;
+    let nullifier_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    if !payer_info.is_signer {
+        msg!("Payer must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    if state.eligibility != Eligibility::AnonymousRegistry {
+        return Err(VoteError::WrongEligibilityMode.into());
+    }
+    if candidate >= state.candidate_count {
+        msg!("Unknown candidate {}", candidate);
+        return Err(VoteError::InvalidCandidate.into());
+    }
+
+    // keys and responses past the largest ring would not fit a transaction
+    if accounts_iter.len() > MAX_RING_SIZE {
+        msg!("Rings hold at most {} members", MAX_RING_SIZE);
+        return Err(VoteError::InvalidRingSignature.into());
+    }
+
+    // the remaining accounts register the ring members, a shared weight
+    // keeps the ballot from pointing at the signer
+    let mut ring = vec![];
+    let mut weight = None;
+    for record_info in accounts_iter {
+        if record_info.owner != program_id {
+            msg!("Ring member is not registered");
+            return Err(VoteError::NotEligible.into());
+        }
+        let record = VoterRecord::unpack(&record_info.try_borrow_data()?)?;
+        if record.election != *election_info.key {
+            msg!("Voter record belongs to another election");
+            return Err(VoteError::NotEligible.into());
+        }
+        if *weight.get_or_insert(record.weight) != record.weight {
+            msg!("Ring members must carry the same weight");
+            return Err(VoteError::InvalidWeight.into());
+        }
+        ring.push(record.voter);
+    }
+    let weight = weight.ok_or(VoteError::NotEligible)?;
+
+    // one record per (election, key image), its existence is what blocks a
+    // second vote of the same voter
+    let (nullifier_key, bump_seed) = Pubkey::find_program_address(
+        &[
+            NULLIFIER_SEED,
+            election_info.key.as_ref(),
+            &signature.key_image,
+        ],
+        program_id,
+    );
+    if nullifier_key != *nullifier_info.key {
+        return Err(VoteError::InvalidReceiptAccount.into());
+    }
+
+    // verification cost grows with the ring, log it for profiling
+    sol_log_compute_units();
+    if !signature.verify(&ring, election_info.key.as_ref(), &[candidate]) {
+        msg!("Ring signature does not verify");
+        return Err(VoteError::InvalidRingSignature.into());
+    }
+    sol_log_compute_units();
+
+    if nullifier_info.data_is_empty() {
+        create_pda_account(
+            payer_info,
+            nullifier_info,
+            system_program_info,
+            program_id,
+            NullifierRecord::LEN,
+            &[
+                NULLIFIER_SEED,
+                election_info.key.as_ref(),
+                &signature.key_image,
+                &[bump_seed],
+            ],
+        )?;
+    }
+
+    check_program_account(program_id, nullifier_info)?;
+
+    let mut nullifier_data = nullifier_info.try_borrow_mut_data()?;
+    if NullifierRecord::unpack_unchecked(&nullifier_data)?.is_initialized() {
+        msg!("Key image already spent");
+        return Err(VoteError::AlreadyVoted.into());
+    }
+
+    state.add_votes(candidate, weight)?;
+
+    NullifierRecord::new(
+        *election_info.key,
+        signature.key_image,
+        candidate,
+        bump_seed,
+    )
+    .pack(&mut nullifier_data)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!(
+        "Ring vote for {} with weight {}, ring of {}",
+        candidate,
+        weight,
+        ring.len()
+    );
+
+    Ok(())
+}
+
+// Accounts casting one signed ballot
+struct SignedBallotAccounts<'a, 'b> {
+    payer_info: &'a AccountInfo<'b>,
+    system_program_info: &'a AccountInfo<'b>,
+    instructions_info: &'a AccountInfo<'b>,
+    receipt_info: &'a AccountInfo<'b>,
+    nonce_info: &'a AccountInfo<'b>,
+    // only read in registry elections
+    record_info: Option<&'a AccountInfo<'b>>,
+}
+
+// Checks the election takes signed ballots, returning its state
+fn unpack_signed_ballot_election(
+    program_id: &Pubkey,
+    election_info: &AccountInfo,
+    payer_info: &AccountInfo,
+) -> Result<ElectionState, ProgramError> {
+    check_program_account(program_id, election_info)?;
+
+    if !payer_info.is_signer {
+        msg!("Payer must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    if state.voting_method != VotingMethod::Plurality {
+        return Err(VoteError::WrongVotingMethod.into());
+    }
+    // proofs and escrows need the voter on the transaction
+    match state.eligibility {
+        Eligibility::Open | Eligibility::Registry => Ok(state),
+        _ => {
+            msg!("Signed ballots are only taken in open and registry elections");
+            Err(VoteError::WrongEligibilityMode.into())
+        }
+    }
+}
+
+// Counts a signed ballot in `state`, which the caller packs. Every check,
+// the tally overflow included, runs before any account is created or
+// written, and `state` only changes once the receipt and nonce record are
+// written, so a rejected ballot leaves no trace and the ballots batched
+// with it can still be cast. Failing to create an account aborts the
+// whole transaction.
+fn cast_signed_ballot<'a>(
+    program_id: &Pubkey,
+    election_info: &AccountInfo<'a>,
+    state: &mut ElectionState,
+    accounts: &SignedBallotAccounts<'_, 'a>,
+    vote: &SignedVote,
+) -> ProgramResult {
+    let SignedVote {
+        voter,
+        candidate,
+        nonce,
+    } = *vote;
+    let nonce_info = accounts.nonce_info;
+    let receipt_info = accounts.receipt_info;
+
+    if candidate >= state.candidate_count {
+        msg!("Unknown candidate {}", candidate);
+        return Err(VoteError::InvalidCandidate.into());
+    }
+
+    // the voter's signature stands in for the voter signing the transaction
+    let ballot = SignedBallot {
+        election: *election_info.key,
+        candidate,
+        nonce,
+    };
+    signed_ballot::check_ed25519_signature(accounts.instructions_info, &voter, &ballot.message())?;
+
+    let weight = match state.eligibility {
+        Eligibility::Registry => {
+            let record_info = accounts
+                .record_info
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            check_voter_record(program_id, election_info, &voter, record_info)?.weight
+        }
+        _ => 1,
+    };
+
+    // the nonce record outlives revoked receipts, keeping their ballots from
+    // being cast again
+    let (nonce_key, nonce_bump_seed) = Pubkey::find_program_address(
+        &[NONCE_SEED, election_info.key.as_ref(), voter.as_ref()],
+        program_id,
+    );
+    let (receipt_key, receipt_bump_seed) = Pubkey::find_program_address(
+        &[RECEIPT_SEED, election_info.key.as_ref(), voter.as_ref()],
+        program_id,
+    );
+    if nonce_key != *nonce_info.key || receipt_key != *receipt_info.key {
+        return Err(VoteError::InvalidReceiptAccount.into());
+    }
+
+    if !nonce_info.data_is_empty() {
+        check_program_account(program_id, nonce_info)?;
+        let nonce_record = NonceRecord::unpack_unchecked(&nonce_info.try_borrow_data()?)?;
+        if nonce_record.is_initialized() && nonce <= nonce_record.nonce {
+            msg!(
+                "Nonce {} is not above the last nonce {}",
+                nonce,
+                nonce_record.nonce
+            );
+            return Err(VoteError::StaleNonce.into());
+        }
+    }
+    if !receipt_info.data_is_empty() {
+        check_program_account(program_id, receipt_info)?;
+        if VoteReceipt::unpack_unchecked(&receipt_info.try_borrow_data()?)?.is_initialized() {
+            msg!("Voter {} already voted", voter);
+            return Err(VoteError::AlreadyVoted.into());
+        }
+    }
+
+    let mut tallied = state.clone();
+    tallied.add_votes(candidate, weight)?;
+
+    if nonce_info.data_is_empty() {
+        create_pda_account(
+            accounts.payer_info,
+            nonce_info,
+            accounts.system_program_info,
+            program_id,
+            NonceRecord::LEN,
+            &[
+                NONCE_SEED,
+                election_info.key.as_ref(),
+                voter.as_ref(),
+                &[nonce_bump_seed],
+            ],
+        )?;
+    }
+    if receipt_info.data_is_empty() {
+        create_pda_account(
+            accounts.payer_info,
+            receipt_info,
+            accounts.system_program_info,
+            program_id,
+            VoteReceipt::LEN,
+            &[
+                RECEIPT_SEED,
+                election_info.key.as_ref(),
+                voter.as_ref(),
+                &[receipt_bump_seed],
+            ],
+        )?;
+    }
+
+    VoteReceipt::new(
+        *election_info.key,
+        voter,
+        candidate,
+        weight,
+        receipt_bump_seed,
+    )
+    .pack(&mut receipt_info.try_borrow_mut_data()?)?;
+    NonceRecord::new(*election_info.key, voter, nonce, nonce_bump_seed)
+        .pack(&mut nonce_info.try_borrow_mut_data()?)?;
+    *state = tallied;
+
+    msg!(
+        "Signed vote for {} with weight {}, nonce {}",
+        candidate,
+        weight,
+        nonce
+    );
+
+    Ok(())
+}
+
+fn process_cast_signed_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    vote: SignedVote,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let payer_info = next_account_info(accounts_iter)?;
+    let receipt_info = next_account_info(accounts_iter)?;
+    let nonce_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+    let instructions_info = next_account_info(accounts_iter)?;
+    let ballot_accounts = SignedBallotAccounts {
+        payer_info,
+        system_program_info,
+        instructions_info,
+        receipt_info,
+        nonce_info,
+        record_info: accounts_iter.next(),
+    };
+
+    let mut state = unpack_signed_ballot_election(program_id, election_info, payer_info)?;
+
+    cast_signed_ballot(
+        program_id,
+        election_info,
+        &mut state,
+        &ballot_accounts,
+        &vote,
+    )?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
+
+fn process_cast_signed_votes(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    policy: BatchPolicy,
+    votes: Vec<SignedVote>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let payer_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+    let instructions_info = next_account_info(accounts_iter)?;
+
+    if votes.is_empty() {
+        msg!("Empty batch");
+        return Err(VoteError::InvalidInstruction.into());
+    }
+
+    let mut state = unpack_signed_ballot_election(program_id, election_info, payer_info)?;
+
+    // one byte per ballot, 1 if it was cast and 0 if it was skipped
+    let mut cast = Vec::with_capacity(votes.len());
+    for (index, vote) in votes.iter().enumerate() {
+        let ballot_accounts = SignedBallotAccounts {
+            payer_info,
+            system_program_info,
+            instructions_info,
+            receipt_info: next_account_info(accounts_iter)?,
+            nonce_info: next_account_info(accounts_iter)?,
+            record_info: Some(next_account_info(accounts_iter)?),
+        };
+
+        match cast_signed_ballot(
+            program_id,
+            election_info,
+            &mut state,
+            &ballot_accounts,
+            vote,
+        ) {
+            Ok(()) => cast.push(1),
+            Err(error) => {
+                msg!("Ballot {} of {} rejected", index, vote.voter);
+                error.print::<VoteError>();
+                if policy == BatchPolicy::AllOrNothing {
+                    return Err(error);
+                }
+                cast.push(0);
+            }
+        }
+    }
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!(
+        "Cast {} of {} signed ballots",
+        cast.iter().filter(|cast| **cast == 1).count(),
+        votes.len()
+    );
+    set_return_data(&cast);
+
+    Ok(())
+}
+
+fn process_change_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    candidate: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let voter_info = next_account_info(accounts_iter)?;
+    let receipt_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+    let mut receipt = check_vote_receipt(program_id, election_info, voter_info, receipt_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    if state.voting_method != VotingMethod::Plurality {
+        return Err(VoteError::WrongVotingMethod.into());
+    }
+
+    let previous = receipt.candidate;
+    state.remove_votes(previous, receipt.weight)?;
+    state.add_votes(candidate, receipt.weight)?;
+    receipt.candidate = candidate;
+
+    receipt.pack(&mut receipt_info.try_borrow_mut_data()?)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!("Vote changed from {} to {}", previous, candidate);
+
+    Ok(())
+}
+
+fn process_revoke_vote(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let voter_info = next_account_info(accounts_iter)?;
+    let receipt_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+    let receipt = check_vote_receipt(program_id, election_info, voter_info, receipt_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    state.check_voting_open(Clock::get()?.unix_timestamp)?;
+
+    // the escrowed tokens go back for the voter to vote with again, the
+    // empty escrow account stays until WithdrawTokens
+    let token_refund = match state.eligibility {
+        Eligibility::TokenBalance(_) => {
+            let escrow_info = next_account_info(accounts_iter)?;
+            let destination_info = next_account_info(accounts_iter)?;
+            let token_program_info = next_account_info(accounts_iter)?;
+            check_token_program(token_program_info)?;
+            let bump_seed =
+                check_escrow_account(program_id, election_info, voter_info, escrow_info)?;
+            Some((escrow_info, destination_info, token_program_info, bump_seed))
+        }
+        _ => None,
+    };
+
+    state.remove_ballot(&receipt)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    if let Some((escrow_info, destination_info, token_program_info, bump_seed)) = token_refund {
+        let amount = release_escrow(
+            election_info,
+            voter_info,
+            escrow_info,
+            destination_info,
+            token_program_info,
+            bump_seed,
+        )?;
+        msg!("Returned {} escrowed tokens", amount);
+    }
+
+    // without a receipt the voter is free to vote again
+    drain_account(receipt_info, voter_info)?;
+
+    msg!("Vote revoked");
+
+    Ok(())
+}
+
+fn process_reveal_vote(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    candidate: u8,
+    salt: [u8; 32],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let voter_info = next_account_info(accounts_iter)?;
+    let receipt_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+    let mut receipt = check_vote_receipt(program_id, election_info, voter_info, receipt_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    state.check_reveal_open(Clock::get()?.unix_timestamp)?;
+
+    if receipt.is_revealed {
+        msg!("Voter {} already revealed", voter_info.key);
+        return Err(VoteError::AlreadyVoted.into());
+    }
+    if vote_commitment(candidate, &salt) != receipt.commitment {
+        return Err(VoteError::InvalidReveal.into());
+    }
+
+    state.unrevealed_commits = state
+        .unrevealed_commits
+        .checked_sub(1)
+        .ok_or(VoteError::TallyUnderflow)?;
+    state.add_votes(candidate, receipt.weight)?;
+    receipt.candidate = candidate;
+    receipt.is_revealed = true;
+
+    receipt.pack(&mut receipt_info.try_borrow_mut_data()?)?;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!(
+        "Revealed vote for {} with weight {}",
+        candidate,
+        receipt.weight
+    );
+
+    Ok(())
+}
+
+fn process_decrypt_tally(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tallies: Vec<u64>,
+    proofs: Vec<DecryptionProof>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let trustee_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    let public_key = match state.voting_method {
+        VotingMethod::Encrypted {
+            trustee,
+            public_key,
+        } => {
+            if !trustee_info.is_signer {
+                msg!("Election trustee must sign");
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if trustee != *trustee_info.key {
+                return Err(VoteError::InvalidAuthority.into());
+            }
+            public_key
+        }
+        _ => return Err(VoteError::WrongVotingMethod.into()),
+    };
+
+    // publishing again is harmless, the proofs pin every tally
+    if !state.is_finalized && Clock::get()?.unix_timestamp < state.end_time {
+        return Err(VoteError::VotingInProgress.into());
+    }
+
+    let candidate_count = state.candidate_count as usize;
+    if tallies.len() != candidate_count || proofs.len() != candidate_count {
+        msg!("One tally and proof per candidate expected");
+        return Err(VoteError::InvalidCandidateCount.into());
+    }
+
+    for (candidate, ((ciphertext, tally), proof)) in state
+        .encrypted_tallies
+        .iter()
+        .zip(tallies.iter())
+        .zip(proofs.iter())
+        .enumerate()
+    {
+        if !proof.verify(&public_key, ciphertext, *tally) {
+            msg!(
+                "Decryption proof of candidate {} does not verify",
+                candidate
+            );
+            return Err(VoteError::InvalidProof.into());
+        }
+    }
+
+    state.tallies = tallies;
+    state.is_finalized = true;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!("Tallies decrypted, election finalized");
+
+    Ok(())
+}
+
+fn process_close_election(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+    let destination_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    let state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    check_authority(&state, authority_info)?;
+
+    // the tallies have to be frozen before the account goes away
+    if !state.is_finalized {
+        return Err(VoteError::ElectionNotFinalized.into());
+    }
+
+    drain_account(election_info, destination_info)?;
+
+    msg!("Election closed");
+
+    Ok(())
+}
+
+fn process_finalize_election(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    check_authority(&state, authority_info)?;
+
+    if state.is_finalized {
+        return Err(VoteError::ElectionFinalized.into());
+    }
+
+    state.is_finalized = true;
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    msg!("Election finalized");
+
+    Ok(())
+}
+
+fn process_register_voter(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    voter: Pubkey,
+    weight: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+    let record_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    let state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    check_authority(&state, authority_info)?;
+
+    match state.eligibility {
+        Eligibility::Registry => {}
+        Eligibility::AnonymousRegistry => {
+            if !ring::is_valid_ring_key(&voter) {
+                msg!("Voter {} can't sign ring signatures", voter);
+                return Err(VoteError::NotEligible.into());
+            }
+        }
+        _ => return Err(VoteError::WrongEligibilityMode.into()),
+    }
+    if state.is_finalized {
+        return Err(VoteError::ElectionFinalized.into());
+    }
+    if weight == 0 {
+        return Err(VoteError::InvalidWeight.into());
+    }
+
+    let (record_key, bump_seed) = Pubkey::find_program_address(
+        &[
+            VOTER_RECORD_SEED,
+            election_info.key.as_ref(),
+            voter.as_ref(),
+        ],
+        program_id,
+    );
+    if record_key != *record_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if record_info.data_is_empty() {
+        create_pda_account(
+            authority_info,
+            record_info,
+            system_program_info,
+            program_id,
+            VoterRecord::LEN,
+            &[
+                VOTER_RECORD_SEED,
+                election_info.key.as_ref(),
+                voter.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+    }
+
+    check_program_account(program_id, record_info)?;
+
+    // registering twice is a no-op for the voter, so report it
+    let mut record_data = record_info.try_borrow_mut_data()?;
+    if VoterRecord::unpack_unchecked(&record_data)?.is_initialized() {
+        msg!("Voter {} is already registered", voter);
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    VoterRecord::new(*election_info.key, voter, weight, bump_seed).pack(&mut record_data)?;
+
+    msg!("Registered voter {} with weight {}", voter, weight);
+
+    Ok(())
+}
+
+fn process_unregister_voter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+    let record_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+    check_program_account(program_id, record_info)?;
+
+    let state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+    check_authority(&state, authority_info)?;
+
+    let record = VoterRecord::unpack(&record_info.try_borrow_data()?)?;
+    if record.election != *election_info.key {
+        return Err(VoteError::NotEligible.into());
+    }
+
+    drain_account(record_info, authority_info)?;
+
+    msg!("Unregistered voter {}", record.voter);
+
+    Ok(())
+}
+
+fn process_withdraw_tokens(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+    let voter_info = next_account_info(accounts_iter)?;
+    let escrow_info = next_account_info(accounts_iter)?;
+    let destination_info = next_account_info(accounts_iter)?;
+    let token_program_info = next_account_info(accounts_iter)?;
+
+    if !voter_info.is_signer {
+        msg!("Voter must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_token_program(token_program_info)?;
+
+    let bump_seed = check_escrow_account(program_id, election_info, voter_info, escrow_info)?;
+
+    // an election emptied by CloseElection no longer holds the tokens back
+    if election_info.owner == program_id && election_info.lamports() > 0 {
+        let state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+        if !state.is_finalized && Clock::get()?.unix_timestamp < state.end_time {
+            return Err(VoteError::TokensLocked.into());
+        }
+    }
+
+    let amount = release_escrow(
+        election_info,
+        voter_info,
+        escrow_info,
+        destination_info,
+        token_program_info,
+        bump_seed,
+    )?;
+    let signer_seeds: &[&[u8]] = &[
+        ESCROW_SEED,
+        election_info.key.as_ref(),
+        voter_info.key.as_ref(),
+        &[bump_seed],
+    ];
+    invoke_signed(
+        &spl_token::instruction::close_account(
+            &spl_token::id(),
+            escrow_info.key,
+            voter_info.key,
+            escrow_info.key,
+            &[],
+        )?,
+        &[
+            escrow_info.clone(),
+            voter_info.clone(),
+            escrow_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    msg!("Withdrew {} tokens", amount);
+
+    Ok(())
+}
+
+fn process_tabulate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let election_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, election_info)?;
+
+    let mut state = ElectionState::unpack(&election_info.try_borrow_data()?)?;
+
+    if state.voting_method != VotingMethod::RankedChoice {
+        return Err(VoteError::WrongVotingMethod.into());
+    }
+    if !state.is_finalized && Clock::get()?.unix_timestamp < state.end_time {
+        return Err(VoteError::VotingInProgress.into());
+    }
+    if state.tabulation.winner.is_some() {
+        return Err(VoteError::AlreadyTabulated.into());
+    }
+
+    if state.tabulation.rounds.is_empty() {
+        state.start_tabulation();
+    }
+
+    for receipt_info in accounts_iter {
+        if state.tabulation.winner.is_some() {
+            break;
+        }
+
+        check_program_account(program_id, receipt_info)?;
+        let mut receipt_data = receipt_info.try_borrow_mut_data()?;
+        let mut receipt = VoteReceipt::unpack(&receipt_data)?;
+        if receipt.election != *election_info.key {
+            msg!("Receipt belongs to another election");
+            return Err(VoteError::InvalidReceiptAccount.into());
+        }
+
+        if state.count_ranked_ballot(&mut receipt)? {
+            receipt.pack(&mut receipt_data)?;
+            state.advance_tabulation();
+        }
+    }
+
+    state.pack(&mut election_info.try_borrow_mut_data()?)?;
+
+    let tabulation = &state.tabulation;
+    match tabulation.winner {
+        Some(winner) => msg!("Candidate {} won", winner),
+        None => msg!(
+            "Round {}: {} of {} ballots counted",
+            tabulation.rounds.len(),
+            tabulation.ballots_counted,
+            tabulation.ballot_count
+        ),
+    }
+
+    Ok(())
+}
+
+fn process_migrate_legacy_election(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let legacy_info = next_account_info(accounts_iter)?;
+    let election_info = next_account_info(accounts_iter)?;
+    let authority_info = next_account_info(accounts_iter)?;
+
+    check_program_account(program_id, legacy_info)?;
+    check_program_account(program_id, election_info)?;
+
+    // legacy accounts carry no authority, holding their keypair is the proof of ownership
+    if !legacy_info.is_signer || !authority_info.is_signer {
+        msg!("Legacy vote account and new authority must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if legacy_info.try_data_len()? != LEGACY_ELECTION_LEN {
+        msg!("Not a legacy vote account");
+        return Err(VoteError::InvalidElectionAccount.into());
+    }
+
+    let state = ElectionState::from_legacy(*authority_info.key, &legacy_info.try_borrow_data()?)?;
+    write_new_election(election_info, &state)?;
+
+    // the legacy rent deposit tops up the new account
+    drain_account(legacy_info, election_info)?;
+
+    msg!("Legacy election migrated");
+
+    Ok(())
+}
+
+// tests
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        instruction::INSTRUCTION_VERSION,
+        state::{find_escrow_address, find_receipt_address, find_voter_record_address},
+    };
+    use solana_program::{
+        clock::{Epoch, UnixTimestamp},
+        entrypoint::SUCCESS,
+        instruction::Instruction,
+        program_stubs, system_program,
+        sysvar::{
+            self,
+            instructions::{construct_instructions_data, BorrowedAccountMeta, BorrowedInstruction},
+        },
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        sync::Once,
+    };
+
+    thread_local! {
+        // unix timestamp reported by the mocked clock sysvar, per test thread
+        static NOW: Cell<UnixTimestamp> = const { Cell::new(0) };
+        // data last set with set_return_data, per test thread
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct TestSyscallStubs;
+
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe {
+                *(var_addr as *mut Rent) = Rent::default();
+            }
+            SUCCESS
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: NOW.with(|now| now.get()),
+                ..Clock::default()
+            };
+            unsafe {
+                *(var_addr as *mut Clock) = clock;
+            }
+            SUCCESS
+        }
+
+        // SPL Token instructions run on the accounts passed along, taking
+        // their signers as given, so token balances move as they would on
+        // chain. Other programs do nothing.
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            if instruction.program_id != spl_token::id() {
+                return Ok(());
+            }
+            let accounts: Vec<AccountInfo> = instruction
+                .accounts
+                .iter()
+                .map(|meta| {
+                    let mut info = account_infos
+                        .iter()
+                        .find(|info| *info.key == meta.pubkey)
+                        .unwrap()
+                        .clone();
+                    info.is_signer = meta.is_signer;
+                    info
+                })
+                .collect();
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &accounts,
+                &instruction.data,
+            )
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+        }
+    }
+
+    fn install_syscall_stubs() {
+        static INSTALL_STUBS: Once = Once::new();
+        INSTALL_STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs));
+        });
+    }
+
+    fn set_clock(unix_timestamp: UnixTimestamp) {
+        install_syscall_stubs();
+        NOW.with(|now| now.set(unix_timestamp));
+    }
+
+    fn return_data() -> Vec<u8> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    // mock account backing an AccountInfo
+    struct TestAccount {
+        key: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, lamports: u64, space: usize, owner: Pubkey) -> Self {
+            Self {
+                key,
+                lamports,
+                data: vec![0; space],
+                owner,
+            }
+        }
+
+        fn info(&mut self, is_signer: bool) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,          // account pubkey
+                is_signer,          // is_signer
+                true,               // is_writable
+                &mut self.lamports, // balance in lamports
+                &mut self.data,     // storage
+                &self.owner,        // owner pubkey
+                false,              // is_executable
+                Epoch::default(),   // rent_epoch
+            )
+        }
+    }
+
+    // mock the receipt account the system program would have created
+    fn receipt_account(program_id: &Pubkey, election: &Pubkey, voter: &Pubkey) -> TestAccount {
+        let (receipt_key, _) = find_receipt_address(program_id, election, voter);
+        TestAccount::new(receipt_key, 1, VoteReceipt::LEN, *program_id)
+    }
+
+    // mock a voter record as RegisterVoter would have written it
+    fn voter_record_account(
+        program_id: &Pubkey,
+        election: &Pubkey,
+        voter: &Pubkey,
+        weight: u64,
+    ) -> TestAccount {
+        let (record_key, bump_seed) = find_voter_record_address(program_id, election, voter);
+        let mut record = TestAccount::new(record_key, 1, VoterRecord::LEN, *program_id);
+        VoterRecord::new(*election, *voter, weight, bump_seed)
+            .pack(&mut record.data)
+            .unwrap();
+        record
+    }
+
+    // mock the Instructions sysvar of a transaction made of `instructions`
+    fn instructions_sysvar(instructions: &[Instruction]) -> TestAccount {
+        let instructions: Vec<_> = instructions
+            .iter()
+            .map(|instruction| BorrowedInstruction {
+                program_id: &instruction.program_id,
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|meta| BorrowedAccountMeta {
+                        pubkey: &meta.pubkey,
+                        is_signer: meta.is_signer,
+                        is_writable: meta.is_writable,
+                    })
+                    .collect(),
+                data: &instruction.data,
+            })
+            .collect();
+        let mut account = TestAccount::new(sysvar::instructions::id(), 1, 0, sysvar::id());
+        account.data = construct_instructions_data(&instructions);
+        account
+    }
+
+    // mock an SPL Token account holding `amount` tokens of `mint`
+    fn token_account(key: Pubkey, mint: &Pubkey, owner: &Pubkey, amount: u64) -> TestAccount {
+        let mut account = TestAccount::new(key, 1, TokenAccount::LEN, spl_token::id());
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+        .pack_into_slice(&mut account.data);
+        account
+    }
+
+    // run a vote instruction signed by `voter`, followed by any mode specific accounts
+    fn cast_vote_with(
+        program_id: &Pubkey,
+        election: &mut TestAccount,
+        voter: &Pubkey,
+        receipt: &mut TestAccount,
+        extra_accounts: &mut [TestAccount],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        install_syscall_stubs();
+        let mut voter = TestAccount::new(*voter, 0, 0, system_program::id());
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let mut accounts = vec![
+            election.info(false),
+            voter.info(true),
+            receipt.info(false),
+            system.info(false),
+        ];
+        accounts.extend(extra_accounts.iter_mut().map(|account| account.info(false)));
+        process(program_id, &accounts, instruction_data)
+    }
+
+    fn cast_vote(
+        program_id: &Pubkey,
+        election: &mut TestAccount,
+        voter: &Pubkey,
+        receipt: &mut TestAccount,
+        candidate: u8,
+    ) -> ProgramResult {
+        let instruction_data = VoteInstruction::CastVote { candidate }.pack();
+        cast_vote_with(
+            program_id,
+            election,
+            voter,
+            receipt,
+            &mut [],
+            &instruction_data,
+        )
+    }
+
+    fn initialize_election_data(candidate_count: u8) -> Vec<u8> {
+        VoteInstruction::InitializeElection(ElectionConfig {
+            metadata_hash: [1; 32],
+            candidate_labels: (0..candidate_count).map(|i| [i; 32]).collect(),
+            start_time: 0,
+            end_time: 100,
+            eligibility: Eligibility::Open,
+            voting_method: VotingMethod::Plurality,
+            verifying_key: vec![],
+        })
+        .pack()
+    }
+
+    fn tallies(election: &TestAccount) -> Vec<u64> {
+        ElectionState::unpack(&election.data).unwrap().tallies
+    }
+
+    #[test]
+    fn test_sanity() {
+        // mock program id
+
+        let program_id = Pubkey::new_unique();
+
+        // mock accounts...
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+
+        let voters: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mut receipts: Vec<TestAccount> = voters
+            .iter()
+            .map(|voter| receipt_account(&program_id, &election.key, voter))
+            .collect();
+
+        // votes are rejected until the election is initialized
+
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voters[0], &mut receipts[0], 0),
+            Err(VoteError::ElectionNotInitialized.into())
+        );
+
+        // the account only has room for three tallies
+
+        let accounts = vec![election.info(false), authority.info(true)];
+
+        let instruction_data = initialize_election_data(4);
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(VoteError::InvalidElectionAccount.into())
+        );
+
+        let instruction_data = initialize_election_data(3);
+        process(&program_id, &accounts, &instruction_data).unwrap();
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(VoteError::ElectionAlreadyInitialized.into())
+        );
+        drop(accounts);
+
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        // vote for the first candidate
+
+        cast_vote(&program_id, &mut election, &voters[0], &mut receipts[0], 0).unwrap();
+        assert_eq!(tallies(&election), vec![1, 0, 0]);
+
+        // vote for the last candidate
+
+        cast_vote(&program_id, &mut election, &voters[1], &mut receipts[1], 2).unwrap();
+        assert_eq!(tallies(&election), vec![1, 0, 1]);
+
+        let receipt = VoteReceipt::unpack_unchecked(&receipts[1].data).unwrap();
+        assert_eq!(receipt.election, election.key);
+        assert_eq!(receipt.voter, voters[1]);
+        assert_eq!(receipt.candidate, 2);
+
+        // unknown candidates and malformed payloads are rejected
+
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voters[2], &mut receipts[2], 3),
+            Err(VoteError::InvalidCandidate.into())
+        );
+        let accounts = vec![election.info(false)];
+        assert_eq!(
+            process(&program_id, &accounts, &[]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            process(&program_id, &accounts, &[INSTRUCTION_VERSION, 1]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+        drop(accounts);
+        assert_eq!(tallies(&election), vec![1, 0, 1]);
+    }
+
+    #[test]
+    fn test_one_vote_per_voter() {
+        let program_id = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        ElectionState::new(Pubkey::new_unique(), 2)
+            .pack(&mut election.data)
+            .unwrap();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+
+        cast_vote(&program_id, &mut election, &voter, &mut receipt, 0).unwrap();
+
+        // the same voter can't vote again, for any candidate
+        for candidate in 0..2 {
+            assert_eq!(
+                cast_vote(&program_id, &mut election, &voter, &mut receipt, candidate),
+                Err(VoteError::AlreadyVoted.into())
+            );
+        }
+        assert_eq!(tallies(&election), vec![1, 0]);
+
+        // nor swap in a receipt derived for somebody else
+        let mut other_receipt = receipt_account(&program_id, &election.key, &Pubkey::new_unique());
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut other_receipt, 0),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+
+        // and the voter must sign
+        let mut voter = TestAccount::new(voter, 0, 0, system_program::id());
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let accounts = vec![
+            election.info(false),
+            voter.info(false),
+            receipt.info(false),
+            system.info(false),
+        ];
+        let instruction_data = VoteInstruction::CastVote { candidate: 0 }.pack();
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_election_account_checks() {
+        let program_id = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            0,
+            ElectionState::space(MAX_CANDIDATES),
+            other_owner,
+        );
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, other_owner);
+
+        let mut accounts = vec![election.info(false), authority.info(false)];
+
+        let instruction_data = initialize_election_data(MIN_CANDIDATES);
+
+        // the election account must belong to the program
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(ProgramError::IncorrectProgramId)
+        );
+
+        // the authority must sign
+        accounts[0].owner = &program_id;
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // the candidate count must be in range
+        accounts[1].is_signer = true;
+        for candidate_count in [0, MIN_CANDIDATES - 1, MAX_CANDIDATES + 1].iter() {
+            let instruction_data = initialize_election_data(*candidate_count);
+            assert_eq!(
+                process(&program_id, &accounts, &instruction_data),
+                Err(VoteError::InvalidCandidateCount.into())
+            );
+        }
+
+        // the voting window must not be empty
+        let window_data = VoteInstruction::InitializeElection(ElectionConfig {
+            metadata_hash: [1; 32],
+            candidate_labels: vec![[0; 32]; MIN_CANDIDATES as usize],
+            start_time: 100,
+            end_time: 100,
+            eligibility: Eligibility::Open,
+            voting_method: VotingMethod::Plurality,
+            verifying_key: vec![],
+        })
+        .pack();
+        assert_eq!(
+            process(&program_id, &accounts, &window_data),
+            Err(VoteError::InvalidVotingWindow.into())
+        );
+
+        process(&program_id, &accounts, &instruction_data).unwrap();
+
+        let state = ElectionState::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(state.authority, *accounts[1].key);
+        assert_eq!(state.metadata_hash, [1; 32]);
+        assert_eq!(state.candidate_labels, vec![[0; 32], [1; 32]]);
+        assert_eq!((state.start_time, state.end_time), (0, 100));
+        assert_eq!(state.tallies, vec![0; MIN_CANDIDATES as usize]);
+
+        // and only once, even by the same authority
+        assert_eq!(
+            process(&program_id, &accounts, &instruction_data),
+            Err(VoteError::ElectionAlreadyInitialized.into())
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy_election() {
+        let program_id = Pubkey::new_unique();
+
+        let mut legacy =
+            TestAccount::new(Pubkey::new_unique(), 10, LEGACY_ELECTION_LEN, program_id);
+        legacy.data[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        legacy.data[4..8].copy_from_slice(&3u32.to_le_bytes());
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            100,
+            ElectionState::space(2),
+            program_id,
+        );
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+
+        let accounts = vec![
+            legacy.info(true),
+            election.info(false),
+            authority.info(true),
+        ];
+
+        let instruction_data = VoteInstruction::MigrateLegacyElection.pack();
+        process(&program_id, &accounts, &instruction_data).unwrap();
+        drop(accounts);
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.authority, authority.key);
+        assert_eq!(state.tallies, vec![u32::MAX as u64, 3]);
+        assert_eq!(legacy.lamports, 0);
+        assert_eq!(election.lamports, 110);
+        assert_eq!(legacy.data, vec![0; LEGACY_ELECTION_LEN]);
+
+        // the migrated tally keeps counting past u32::MAX
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        cast_vote(&program_id, &mut election, &voter, &mut receipt, 0).unwrap();
+        state.tallies[0] += 1;
+        assert_eq!(ElectionState::unpack(&election.data).unwrap(), state);
+
+        // an overflowing tally is reported rather than wrapped
+        state.tallies[1] = u64::MAX;
+        state.pack(&mut election.data).unwrap();
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 1),
+            Err(VoteError::TallyOverflow.into())
+        );
+    }
+
+    #[test]
+    fn test_voting_window() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 2);
+        state.start_time = 1_000;
+        state.end_time = 2_000;
+        state.pack(&mut election.data).unwrap();
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+
+        set_clock(999);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::VotingNotOpen.into())
+        );
+
+        set_clock(2_000);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::ElectionClosed.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 0]);
+
+        set_clock(1_000);
+        cast_vote(&program_id, &mut election, &voter, &mut receipt, 0).unwrap();
+        assert_eq!(tallies(&election), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_finalize_and_close_election() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            500,
+            ElectionState::space(2),
+            program_id,
+        );
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let mut impostor = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let mut destination = TestAccount::new(Pubkey::new_unique(), 20, 0, system_program::id());
+        ElectionState::new(authority.key, 2)
+            .pack(&mut election.data)
+            .unwrap();
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        cast_vote(&program_id, &mut election, &voter, &mut receipt, 1).unwrap();
+
+        let finalize_data = VoteInstruction::FinalizeElection.pack();
+        let close_data = VoteInstruction::CloseElection.pack();
+
+        // only the authority may finalize or close
+        let accounts = vec![
+            election.info(false),
+            impostor.info(true),
+            destination.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &finalize_data),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            process(&program_id, &accounts, &close_data),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        drop(accounts);
+
+        // an election that is still running can't be closed
+
+        let accounts = vec![
+            election.info(false),
+            authority.info(true),
+            destination.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &close_data),
+            Err(VoteError::ElectionNotFinalized.into())
+        );
+
+        process(&program_id, &accounts, &finalize_data).unwrap();
+        assert_eq!(
+            process(&program_id, &accounts, &finalize_data),
+            Err(VoteError::ElectionFinalized.into())
+        );
+        drop(accounts);
+
+        // the tallies are frozen
+
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert!(state.is_finalized);
+        assert_eq!(state.tallies, vec![0, 1]);
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::ElectionFinalized.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 1]);
+
+        // closing returns the rent deposit and wipes the account
+
+        let accounts = vec![
+            election.info(false),
+            authority.info(true),
+            destination.info(false),
+        ];
+        process(&program_id, &accounts, &close_data).unwrap();
+        drop(accounts);
+
+        assert_eq!(election.lamports, 0);
+        assert_eq!(destination.lamports, 520);
+        assert!(election.data.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_voter_registry() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let mut state = ElectionState::new(authority.key, 2);
+        state.eligibility = Eligibility::Registry;
+        state.pack(&mut election.data).unwrap();
+
+        // register a resident, the system program already allocated the record
+
+        let resident = Pubkey::new_unique();
+        let (record_key, _) = find_voter_record_address(&program_id, &election.key, &resident);
+        let mut record = TestAccount::new(record_key, 1, VoterRecord::LEN, program_id);
+
+        let register_data = VoteInstruction::RegisterVoter {
+            voter: resident,
+            weight: 1,
+        }
+        .pack();
+        let accounts = vec![
+            election.info(false),
+            authority.info(true),
+            record.info(false),
+            system.info(false),
+        ];
+        install_syscall_stubs();
+        process(&program_id, &accounts, &register_data).unwrap();
+        assert_eq!(
+            process(&program_id, &accounts, &register_data),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        drop(accounts);
+
+        // the resident can vote
+
+        let mut receipt = receipt_account(&program_id, &election.key, &resident);
+        let vote_data = VoteInstruction::CastVote { candidate: 0 }.pack();
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &resident,
+            &mut receipt,
+            std::slice::from_mut(&mut record),
+            &vote_data,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![1, 0]);
+
+        // an outsider can neither vote without a record nor borrow the resident's
+
+        let outsider = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &outsider);
+        let (outsider_record_key, _) =
+            find_voter_record_address(&program_id, &election.key, &outsider);
+        let mut outsider_record = TestAccount::new(outsider_record_key, 0, 0, system_program::id());
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &outsider,
+                &mut receipt,
+                std::slice::from_mut(&mut outsider_record),
+                &vote_data,
+            ),
+            Err(VoteError::NotEligible.into())
+        );
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &outsider,
+                &mut receipt,
+                std::slice::from_mut(&mut record),
+                &vote_data,
+            ),
+            Err(VoteError::NotEligible.into())
+        );
+        assert_eq!(tallies(&election), vec![1, 0]);
+
+        // unregistering returns the rent and revokes eligibility
+
+        let accounts = vec![
+            election.info(false),
+            authority.info(true),
+            record.info(false),
+        ];
+        let unregister_data = VoteInstruction::UnregisterVoter.pack();
+        process(&program_id, &accounts, &unregister_data).unwrap();
+        drop(accounts);
+        assert_eq!(authority.lamports, 1);
+        assert_eq!(
+            VoterRecord::unpack(&record.data),
+            Err(VoteError::NotEligible.into())
+        );
+
+        // open elections have no registry
+
+        let mut open =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        ElectionState::new(authority.key, 2)
+            .pack(&mut open.data)
+            .unwrap();
+        let mut record = voter_record_account(&program_id, &open.key, &resident, 1);
+        record.data = vec![0; VoterRecord::LEN];
+        let accounts = vec![
+            open.info(false),
+            authority.info(true),
+            record.info(false),
+            system.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &register_data),
+            Err(VoteError::WrongEligibilityMode.into())
+        );
+    }
+
+    #[test]
+    fn test_merkle_eligibility() {
+        let program_id = Pubkey::new_unique();
+
+        let residents: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let tree = merkle::MerkleTree::new(
+            residents
+                .iter()
+                .map(|resident| merkle::leaf_hash(resident, 1))
+                .collect(),
+        );
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 2);
+        state.eligibility = Eligibility::MerkleRoot(tree.root());
+        state.pack(&mut election.data).unwrap();
+
+        let vote_data = |candidate, proof| {
+            VoteInstruction::CastVoteWithProof {
+                candidate,
+                weight: 1,
+                proof,
+            }
+            .pack()
+        };
+
+        // a resident proves membership and votes
+
+        let mut receipt = receipt_account(&program_id, &election.key, &residents[3]);
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &residents[3],
+            &mut receipt,
+            &mut [],
+            &vote_data(1, tree.proof(3)),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 1]);
+
+        // without a proof, with somebody else's proof, or as an outsider it fails
+
+        let mut receipt = receipt_account(&program_id, &election.key, &residents[0]);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &residents[0], &mut receipt, 0),
+            Err(VoteError::NotEligible.into())
+        );
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &residents[0],
+                &mut receipt,
+                &mut [],
+                &vote_data(0, tree.proof(1)),
+            ),
+            Err(VoteError::NotEligible.into())
+        );
+
+        let outsider = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &outsider);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &outsider,
+                &mut receipt,
+                &mut [],
+                &vote_data(0, tree.proof(0)),
+            ),
+            Err(VoteError::NotEligible.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 1]);
+
+        // proofs mean nothing to an open election
+
+        let mut open =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        ElectionState::new(Pubkey::new_unique(), 2)
+            .pack(&mut open.data)
+            .unwrap();
+        let mut receipt = receipt_account(&program_id, &open.key, &residents[0]);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut open,
+                &residents[0],
+                &mut receipt,
+                &mut [],
+                &vote_data(0, tree.proof(0)),
+            ),
+            Err(VoteError::WrongEligibilityMode.into())
+        );
+    }
+
+    #[test]
+    fn test_weighted_voting() {
+        let program_id = Pubkey::new_unique();
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+
+        // registry weights come from the voter records
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(authority.key, 2);
+        state.eligibility = Eligibility::Registry;
+        state.pack(&mut election.data).unwrap();
+
+        let vote_data = VoteInstruction::CastVote { candidate: 1 }.pack();
+        for shares in [3, 5].iter() {
+            let member = Pubkey::new_unique();
+            let mut record = voter_record_account(&program_id, &election.key, &member, *shares);
+            let mut receipt = receipt_account(&program_id, &election.key, &member);
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &member,
+                &mut receipt,
+                std::slice::from_mut(&mut record),
+                &vote_data,
+            )
+            .unwrap();
+            assert_eq!(
+                VoteReceipt::unpack_unchecked(&receipt.data).unwrap().weight,
+                *shares
+            );
+        }
+        assert_eq!(tallies(&election), vec![0, 8]);
+
+        // a weight that would overflow the tally is rejected without side effects
+
+        let whale = Pubkey::new_unique();
+        let mut record = voter_record_account(&program_id, &election.key, &whale, u64::MAX);
+        let mut receipt = receipt_account(&program_id, &election.key, &whale);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &whale,
+                &mut receipt,
+                std::slice::from_mut(&mut record),
+                &vote_data,
+            ),
+            Err(VoteError::TallyOverflow.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 8]);
+
+        // voters can't be registered without weight
+
+        let (record_key, _) = find_voter_record_address(&program_id, &election.key, &whale);
+        let mut record = TestAccount::new(record_key, 1, VoterRecord::LEN, program_id);
+        let accounts = vec![
+            election.info(false),
+            authority.info(true),
+            record.info(false),
+            system.info(false),
+        ];
+        let register_data = VoteInstruction::RegisterVoter {
+            voter: whale,
+            weight: 0,
+        }
+        .pack();
+        assert_eq!(
+            process(&program_id, &accounts, &register_data),
+            Err(VoteError::InvalidWeight.into())
+        );
+        drop(accounts);
+
+        // Merkle weights are committed to in the leaves
+
+        let members = [(Pubkey::new_unique(), 10), (Pubkey::new_unique(), 4)];
+        let tree = merkle::MerkleTree::new(
+            members
+                .iter()
+                .map(|(member, shares)| merkle::leaf_hash(member, *shares))
+                .collect(),
+        );
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(authority.key, 2);
+        state.eligibility = Eligibility::MerkleRoot(tree.root());
+        state.pack(&mut election.data).unwrap();
+
+        let vote_data = |weight, proof| {
+            VoteInstruction::CastVoteWithProof {
+                candidate: 0,
+                weight,
+                proof,
+            }
+            .pack()
+        };
+
+        let (member, shares) = members[1];
+        let mut receipt = receipt_account(&program_id, &election.key, &member);
+        for claimed in [shares + 1, 0].iter() {
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &member,
+                    &mut receipt,
+                    &mut [],
+                    &vote_data(*claimed, tree.proof(1)),
+                ),
+                Err(VoteError::NotEligible.into())
+            );
+        }
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &member,
+            &mut receipt,
+            &mut [],
+            &vote_data(shares, tree.proof(1)),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![4, 0]);
+    }
+
+    #[test]
+    fn test_token_weighted_voting() {
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 1, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 2);
+        state.eligibility = Eligibility::TokenBalance(mint);
+        state.end_time = 100;
+        state.pack(&mut election.data).unwrap();
+
+        let holder = Pubkey::new_unique();
+        let (escrow_key, _) = find_escrow_address(&program_id, &election.key, &holder);
+        let token_accounts = |source: TestAccount| {
+            vec![
+                source,
+                token_account(escrow_key, &mint, &escrow_key, 0),
+                TestAccount::new(mint, 1, 0, spl_token::id()),
+                TestAccount::new(spl_token::id(), 0, 0, Pubkey::default()),
+                TestAccount::new(sysvar::rent::id(), 0, 0, Pubkey::default()),
+            ]
+        };
+        let vote_data = VoteInstruction::CastVote { candidate: 0 }.pack();
+        let mut receipt = receipt_account(&program_id, &election.key, &holder);
+
+        // the source must be the voter's non empty account of the election mint
+
+        let other_mint = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+        let invalid_sources = [
+            (other_mint, holder, 50, VoteError::InvalidTokenAccount),
+            (mint, other_owner, 50, VoteError::InvalidTokenAccount),
+            (mint, holder, 0, VoteError::InvalidWeight),
+        ];
+        for (source_mint, owner, amount, error) in invalid_sources.iter() {
+            let source = token_account(Pubkey::new_unique(), source_mint, owner, *amount);
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &holder,
+                    &mut receipt,
+                    &mut token_accounts(source),
+                    &vote_data,
+                ),
+                Err(error.clone().into())
+            );
+        }
+
+        let mut fake = token_account(Pubkey::new_unique(), &mint, &holder, 50);
+        fake.owner = Pubkey::new_unique();
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &holder,
+                &mut receipt,
+                &mut token_accounts(fake),
+                &vote_data,
+            ),
+            Err(VoteError::InvalidTokenAccount.into())
+        );
+
+        // the whole balance counts, and is escrowed by the vote
+
+        let source = token_account(Pubkey::new_unique(), &mint, &holder, 50);
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &holder,
+            &mut receipt,
+            &mut token_accounts(source),
+            &vote_data,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![50, 0]);
+        assert_eq!(
+            VoteReceipt::unpack_unchecked(&receipt.data).unwrap().weight,
+            50
+        );
+
+        // the escrow stays locked while voting is open
+
+        let mut voter = TestAccount::new(holder, 0, 0, system_program::id());
+        let mut escrow = token_account(escrow_key, &mint, &escrow_key, 50);
+        let mut destination = token_account(Pubkey::new_unique(), &mint, &holder, 0);
+        let mut token_program = TestAccount::new(spl_token::id(), 0, 0, Pubkey::default());
+        let withdraw_data = VoteInstruction::WithdrawTokens.pack();
+
+        set_clock(99);
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            escrow.info(false),
+            destination.info(false),
+            token_program.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &withdraw_data),
+            Err(VoteError::TokensLocked.into())
+        );
+
+        // and opens once voting ends, for the voter's own escrow only
+
+        set_clock(100);
+        process(&program_id, &accounts, &withdraw_data).unwrap();
+        drop(accounts);
+        assert_eq!(TokenAccount::unpack(&destination.data).unwrap().amount, 50);
+        assert_eq!(escrow.owner, system_program::id());
+
+        let mut other_escrow = token_account(Pubkey::new_unique(), &mint, &holder, 50);
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            other_escrow.info(false),
+            destination.info(false),
+            token_program.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &withdraw_data),
+            Err(VoteError::InvalidTokenAccount.into())
+        );
+        drop(accounts);
+
+        // an election account that doesn't parse keeps the escrow locked
+
+        set_clock(0);
+        election.data = vec![0xff; election.data.len()];
+        escrow = token_account(escrow_key, &mint, &escrow_key, 50);
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            escrow.info(false),
+            destination.info(false),
+            token_program.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &withdraw_data),
+            Err(VoteError::InvalidElectionAccount.into())
+        );
+        drop(accounts);
+
+        // closing the election releases the escrow too
+
+        election.data = vec![0; election.data.len()];
+        election.lamports = 0;
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            escrow.info(false),
+            destination.info(false),
+            token_program.info(false),
+        ];
+        process(&program_id, &accounts, &withdraw_data).unwrap();
+    }
+
+    #[test]
+    fn test_revoke_token_weighted_vote() {
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(2), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 2);
+        state.eligibility = Eligibility::TokenBalance(mint);
+        state.end_time = 100;
+        state.pack(&mut election.data).unwrap();
+
+        let holder = Pubkey::new_unique();
+        let (escrow_key, _) = find_escrow_address(&program_id, &election.key, &holder);
+        let source_key = Pubkey::new_unique();
+        // source, escrow, mint, token program and rent sysvar
+        let mut token_accounts = vec![
+            token_account(source_key, &mint, &holder, 50),
+            token_account(escrow_key, &mint, &escrow_key, 0),
+            TestAccount::new(mint, 1, 0, spl_token::id()),
+            TestAccount::new(spl_token::id(), 0, 0, Pubkey::default()),
+            TestAccount::new(sysvar::rent::id(), 0, 0, Pubkey::default()),
+        ];
+        let balances = |token_accounts: &[TestAccount]| {
+            token_accounts[..2]
+                .iter()
+                .map(|account| TokenAccount::unpack(&account.data).unwrap().amount)
+                .collect::<Vec<_>>()
+        };
+        let mut receipt = receipt_account(&program_id, &election.key, &holder);
+        let mut voter = TestAccount::new(holder, 0, 0, system_program::id());
+
+        set_clock(0);
+        let vote_data = VoteInstruction::CastVote { candidate: 0 }.pack();
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &holder,
+            &mut receipt,
+            &mut token_accounts,
+            &vote_data,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![50, 0]);
+        assert_eq!(balances(&token_accounts), vec![0, 50]);
+
+        // revoking needs the voter's own escrow to return the tokens from
+
+        let revoke_data = VoteInstruction::RevokeVote.pack();
+        let accounts = vec![election.info(false), voter.info(true), receipt.info(false)];
+        assert_eq!(
+            process(&program_id, &accounts, &revoke_data),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        drop(accounts);
+
+        let mut other_escrow = token_account(Pubkey::new_unique(), &mint, &holder, 50);
+        let (source, rest) = token_accounts.split_at_mut(1);
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            receipt.info(false),
+            other_escrow.info(false),
+            source[0].info(false),
+            rest[2].info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &revoke_data),
+            Err(VoteError::InvalidTokenAccount.into())
+        );
+        drop(accounts);
+
+        // the tokens go back, so the voter can vote with them again
+
+        let (source, rest) = token_accounts.split_at_mut(1);
+        let (escrow, rest) = rest.split_at_mut(1);
+        let accounts = vec![
+            election.info(false),
+            voter.info(true),
+            receipt.info(false),
+            escrow[0].info(false),
+            source[0].info(false),
+            rest[1].info(false),
+        ];
+        process(&program_id, &accounts, &revoke_data).unwrap();
+        drop(accounts);
+        assert_eq!(tallies(&election), vec![0, 0]);
+        assert_eq!(balances(&token_accounts), vec![50, 0]);
+
+        let vote_data = VoteInstruction::CastVote { candidate: 1 }.pack();
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &holder,
+            &mut receipt,
+            &mut token_accounts,
+            &vote_data,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 50]);
+        assert_eq!(balances(&token_accounts), vec![0, 50]);
+    }
+
+    #[test]
+    fn test_quadratic_voting() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.voting_method = VotingMethod::Quadratic { credits: 100 };
+        state.pack(&mut election.data).unwrap();
+
+        let election_key = election.key;
+        let quadratic_receipt = |voter: &Pubkey| {
+            let (receipt_key, _) = find_receipt_address(&program_id, &election_key, voter);
+            TestAccount::new(receipt_key, 1, VoteReceipt::quadratic_space(3), program_id)
+        };
+        let vote_data = |allocations: &[(u8, u32)]| {
+            VoteInstruction::CastQuadraticVote {
+                allocations: allocations.to_vec(),
+            }
+            .pack()
+        };
+
+        // 6 votes and 8 votes use up the whole budget of 36 + 64 credits
+
+        let alice = Pubkey::new_unique();
+        let mut alice_receipt = quadratic_receipt(&alice);
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &alice,
+            &mut alice_receipt,
+            &mut [],
+            &vote_data(&[(0, 6), (1, 8)]),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![6, 8, 0]);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &alice,
+                &mut alice_receipt,
+                &mut [],
+                &vote_data(&[(2, 1)]),
+            ),
+            Err(VoteError::InsufficientCredits.into())
+        );
+
+        // credits left over can be spent later, at the marginal cost
+
+        let bob = Pubkey::new_unique();
+        let mut bob_receipt = quadratic_receipt(&bob);
+        for _ in 0..2 {
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &bob,
+                &mut bob_receipt,
+                &mut [],
+                &vote_data(&[(2, 5)]),
+            )
+            .unwrap();
+        }
+        let receipt = VoteReceipt::unpack_unchecked(&bob_receipt.data).unwrap();
+        assert_eq!(receipt.votes, vec![0, 0, 10]);
+        assert_eq!(receipt.credits_remaining, 0);
+        assert_eq!(tallies(&election), vec![6, 8, 10]);
+
+        // a ballot fails as a whole, unknown candidates included
+
+        let carol = Pubkey::new_unique();
+        let mut carol_receipt = quadratic_receipt(&carol);
+        for (allocations, error) in [
+            (vec![(0, 10), (1, 1)], VoteError::InsufficientCredits),
+            (vec![(0, 1), (3, 1)], VoteError::InvalidCandidate),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &carol,
+                    &mut carol_receipt,
+                    &mut [],
+                    &vote_data(allocations),
+                ),
+                Err(error.clone().into())
+            );
+        }
+        assert_eq!(tallies(&election), vec![6, 8, 10]);
+
+        // ballots must match the voting method
+
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &carol, &mut carol_receipt, 0),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+
+        let mut plurality =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        ElectionState::new(Pubkey::new_unique(), 3)
+            .pack(&mut plurality.data)
+            .unwrap();
+        let mut receipt = receipt_account(&program_id, &plurality.key, &carol);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut plurality,
+                &carol,
+                &mut receipt,
+                &mut [],
+                &vote_data(&[(0, 1)]),
+            ),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+    }
+
+    #[test]
+    fn test_ranked_choice_voting() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            0,
+            ElectionState::ranked_space(3),
+            program_id,
+        );
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.voting_method = VotingMethod::RankedChoice;
+        state.end_time = 100;
+        state.pack(&mut election.data).unwrap();
+
+        let election_key = election.key;
+        let ranked_receipt = |voter: &Pubkey| {
+            let (receipt_key, _) = find_receipt_address(&program_id, &election_key, voter);
+            TestAccount::new(receipt_key, 1, VoteReceipt::ranked_space(3), program_id)
+        };
+        let vote_data = |ranking: &[u8]| {
+            VoteInstruction::CastRankedVote {
+                ranking: ranking.to_vec(),
+            }
+            .pack()
+        };
+
+        // rankings must be non empty and name each candidate at most once
+
+        set_clock(0);
+        let voter = Pubkey::new_unique();
+        let mut receipt = ranked_receipt(&voter);
+        for (ranking, error) in [
+            (vec![], VoteError::InvalidRanking),
+            (vec![1, 0, 1], VoteError::InvalidRanking),
+            (vec![0, 3], VoteError::InvalidCandidate),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &voter,
+                    &mut receipt,
+                    &mut [],
+                    &vote_data(ranking),
+                ),
+                Err(error.clone().into())
+            );
+        }
+
+        // 2/1/2 first preferences, no majority
+
+        let mut receipts = vec![];
+        for ranking in [vec![0, 1], vec![0, 2], vec![1, 0], vec![2, 1], vec![2, 1]].iter() {
+            let voter = Pubkey::new_unique();
+            let mut receipt = ranked_receipt(&voter);
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &voter,
+                &mut receipt,
+                &mut [],
+                &vote_data(ranking),
+            )
+            .unwrap();
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &voter,
+                    &mut receipt,
+                    &mut [],
+                    &vote_data(ranking),
+                ),
+                Err(VoteError::AlreadyVoted.into())
+            );
+            receipts.push(receipt);
+        }
+        assert_eq!(tallies(&election), vec![2, 1, 2]);
+
+        // nothing is counted before voting ends
+
+        let tabulate_data = VoteInstruction::Tabulate.pack();
+        let tabulate = |election: &mut TestAccount, receipts: &mut [TestAccount]| {
+            let mut accounts = vec![election.info(false)];
+            accounts.extend(receipts.iter_mut().map(|receipt| receipt.info(false)));
+            process(&program_id, &accounts, &tabulate_data)
+        };
+        assert_eq!(
+            tabulate(&mut election, &mut []),
+            Err(VoteError::VotingInProgress.into())
+        );
+
+        // 1 is eliminated, then the ballots are counted again in two batches,
+        // the second overlapping the first, and its voter elects 0 by 3 to 2
+
+        set_clock(100);
+        tabulate(&mut election, &mut receipts[..3]).unwrap();
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.tabulation.rounds, vec![vec![2, 1, 2], vec![3, 0, 0]]);
+        assert_eq!(state.tabulation.winner, None);
+
+        tabulate(&mut election, &mut receipts[2..]).unwrap();
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.tabulation.rounds[1], vec![3, 0, 2]);
+        assert_eq!(state.tabulation.winner, Some(0));
+
+        assert_eq!(
+            tabulate(&mut election, &mut []),
+            Err(VoteError::AlreadyTabulated.into())
+        );
+
+        // receipts of other elections are rejected
+
+        let mut other = TestAccount::new(
+            Pubkey::new_unique(),
+            0,
+            ElectionState::ranked_space(3),
+            program_id,
+        );
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.voting_method = VotingMethod::RankedChoice;
+        state.end_time = 100;
+        state.tallies = vec![1, 1, 0];
+        state.tabulation.ballot_count = 2;
+        state.pack(&mut other.data).unwrap();
+        assert_eq!(
+            tabulate(&mut other, &mut receipts[..1]),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+    }
+
+    #[test]
+    fn test_approval_voting() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.voting_method = VotingMethod::Approval;
+        state.pack(&mut election.data).unwrap();
+
+        let vote_data = |approvals| VoteInstruction::CastApprovalVote { approvals }.pack();
+
+        // each approved candidate gets the vote, once
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &voter,
+            &mut receipt,
+            &mut [],
+            &vote_data(0b101),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![1, 0, 1]);
+        assert_eq!(VoteReceipt::unpack(&receipt.data).unwrap().approvals, 0b101);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &voter,
+                &mut receipt,
+                &mut [],
+                &vote_data(0b010),
+            ),
+            Err(VoteError::AlreadyVoted.into())
+        );
+
+        // bits past the last candidate, or no bit at all, are rejected
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        for approvals in [0b1011, 1 << 63, 0].iter() {
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &voter,
+                    &mut receipt,
+                    &mut [],
+                    &vote_data(*approvals),
+                ),
+                Err(VoteError::InvalidCandidate.into())
+            );
+        }
+        cast_vote_with(
+            &program_id,
+            &mut election,
+            &voter,
+            &mut receipt,
+            &mut [],
+            &vote_data(0b111),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![2, 1, 2]);
+
+        // approval ballots only fit approval elections
+
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+    }
+
+    #[test]
+    fn test_change_and_revoke_vote() {
+        let program_id = Pubkey::new_unique();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.end_time = 100;
+        state.pack(&mut election.data).unwrap();
+
+        let voter_key = Pubkey::new_unique();
+        let mut voter = TestAccount::new(voter_key, 0, 0, system_program::id());
+        let mut receipt = receipt_account(&program_id, &election.key, &voter_key);
+        set_clock(0);
+        cast_vote(&program_id, &mut election, &voter_key, &mut receipt, 0).unwrap();
+
+        let run = |election: &mut TestAccount,
+                   voter: &mut TestAccount,
+                   receipt: &mut TestAccount,
+                   instruction: VoteInstruction| {
+            let accounts = vec![election.info(false), voter.info(true), receipt.info(false)];
+            process(&program_id, &accounts, &instruction.pack())
+        };
+
+        // the vote moves between candidates
+
+        run(
+            &mut election,
+            &mut voter,
+            &mut receipt,
+            VoteInstruction::ChangeVote { candidate: 2 },
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 0, 1]);
+        assert_eq!(VoteReceipt::unpack(&receipt.data).unwrap().candidate, 2);
+        assert_eq!(
+            run(
+                &mut election,
+                &mut voter,
+                &mut receipt,
+                VoteInstruction::ChangeVote { candidate: 3 },
+            ),
+            Err(VoteError::InvalidCandidate.into())
+        );
+
+        // nobody else can touch it
+
+        let mut other = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        assert_eq!(
+            run(
+                &mut election,
+                &mut other,
+                &mut receipt,
+                VoteInstruction::RevokeVote,
+            ),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+
+        // revoking refunds the receipt and allows voting again
+
+        run(
+            &mut election,
+            &mut voter,
+            &mut receipt,
+            VoteInstruction::RevokeVote,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+        assert_eq!(voter.lamports, 1);
+        assert_eq!(
+            run(
+                &mut election,
+                &mut voter,
+                &mut receipt,
+                VoteInstruction::RevokeVote,
+            ),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+
+        cast_vote(&program_id, &mut election, &voter_key, &mut receipt, 1).unwrap();
+        assert_eq!(tallies(&election), vec![0, 1, 0]);
+
+        // a tally that no longer holds the vote fails rather than wrapping
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        state.tallies[1] = 0;
+        state.pack(&mut election.data).unwrap();
+        for instruction in [
+            VoteInstruction::ChangeVote { candidate: 0 },
+            VoteInstruction::RevokeVote,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                run(&mut election, &mut voter, &mut receipt, instruction.clone()),
+                Err(VoteError::TallyUnderflow.into())
+            );
+        }
+        state.tallies[1] = 1;
+        state.pack(&mut election.data).unwrap();
+
+        // both end with the voting window
+
+        set_clock(100);
+        for instruction in [
+            VoteInstruction::ChangeVote { candidate: 0 },
+            VoteInstruction::RevokeVote,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                run(&mut election, &mut voter, &mut receipt, instruction.clone()),
+                Err(VoteError::ElectionClosed.into())
+            );
+        }
+        assert_eq!(tallies(&election), vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_commit_reveal_voting() {
+        let program_id = Pubkey::new_unique();
+
+        // the reveal phase must follow the voting window
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let config = |reveal_end_time| {
+            VoteInstruction::InitializeElection(ElectionConfig {
+                metadata_hash: [1; 32],
+                candidate_labels: vec![[0; 32]; 3],
+                start_time: 0,
+                end_time: 100,
+                eligibility: Eligibility::Open,
+                voting_method: VotingMethod::CommitReveal { reveal_end_time },
+                verifying_key: vec![],
+            })
+            .pack()
+        };
+        let accounts = vec![election.info(false), authority.info(true)];
+        assert_eq!(
+            process(&program_id, &accounts, &config(100)),
+            Err(VoteError::InvalidVotingWindow.into())
+        );
+        process(&program_id, &accounts, &config(200)).unwrap();
+        drop(accounts);
+
+        let commit_data = |commitment| VoteInstruction::CommitVote { commitment }.pack();
+        let reveal = |election: &mut TestAccount,
+                      voter: &Pubkey,
+                      receipt: &mut TestAccount,
+                      candidate,
+                      salt| {
+            let mut voter = TestAccount::new(*voter, 0, 0, system_program::id());
+            let accounts = vec![election.info(false), voter.info(true), receipt.info(false)];
+            process(
+                &program_id,
+                &accounts,
+                &VoteInstruction::RevealVote { candidate, salt }.pack(),
+            )
+        };
+
+        // commitments are counted, the tallies stay hidden
+
+        set_clock(0);
+        let voters: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mut receipts: Vec<TestAccount> = voters
+            .iter()
+            .map(|voter| receipt_account(&program_id, &election.key, voter))
+            .collect();
+        let choices = [(2, [7; 32]), (2, [8; 32]), (0, [9; 32])];
+        for ((voter, receipt), (candidate, salt)) in
+            voters.iter().zip(receipts.iter_mut()).zip(choices.iter())
+        {
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                voter,
+                receipt,
+                &mut [],
+                &commit_data(vote_commitment(*candidate, salt)),
+            )
+            .unwrap();
+        }
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.unrevealed_commits, 3);
+
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &voters[0],
+                &mut receipts[0],
+                &mut [],
+                &commit_data([0; 32]),
+            ),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voters[0], &mut receipts[0], 2),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+
+        // nothing is revealed while voting is open
+
+        assert_eq!(
+            reveal(&mut election, &voters[0], &mut receipts[0], 2, [7; 32]),
+            Err(VoteError::VotingInProgress.into())
+        );
+
+        // reveals must match the commitment and count once
+
+        set_clock(100);
+        let late_voter = Pubkey::new_unique();
+        let mut late_receipt = receipt_account(&program_id, &election.key, &late_voter);
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &late_voter,
+                &mut late_receipt,
+                &mut [],
+                &commit_data([0; 32]),
+            ),
+            Err(VoteError::ElectionClosed.into())
+        );
+
+        for (candidate, salt) in [(1, [7; 32]), (2, [8; 32])].iter() {
+            assert_eq!(
+                reveal(
+                    &mut election,
+                    &voters[0],
+                    &mut receipts[0],
+                    *candidate,
+                    *salt
+                ),
+                Err(VoteError::InvalidReveal.into())
+            );
+        }
+        reveal(&mut election, &voters[0], &mut receipts[0], 2, [7; 32]).unwrap();
+        assert_eq!(tallies(&election), vec![0, 0, 1]);
+        assert_eq!(
+            reveal(&mut election, &voters[0], &mut receipts[0], 2, [7; 32]),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        let receipt = VoteReceipt::unpack(&receipts[0].data).unwrap();
+        assert!(receipt.is_revealed);
+        assert_eq!(receipt.candidate, 2);
+
+        // a commitment count that no longer holds the ballot fails rather
+        // than wrapping
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        state.unrevealed_commits = 0;
+        state.pack(&mut election.data).unwrap();
+        assert_eq!(
+            reveal(&mut election, &voters[1], &mut receipts[1], 2, [8; 32]),
+            Err(VoteError::TallyUnderflow.into())
+        );
+        state.unrevealed_commits = 2;
+        state.pack(&mut election.data).unwrap();
+
+        reveal(&mut election, &voters[1], &mut receipts[1], 2, [8; 32]).unwrap();
+        assert_eq!(tallies(&election), vec![0, 0, 2]);
+
+        // ballots not revealed in time are reported and never counted
+
+        set_clock(200);
+        assert_eq!(
+            reveal(&mut election, &voters[2], &mut receipts[2], 0, [9; 32]),
+            Err(VoteError::ElectionClosed.into())
+        );
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.tallies, vec![0, 0, 2]);
+        assert_eq!(state.unrevealed_commits, 1);
+    }
+
+    #[test]
+    fn test_encrypted_voting() {
+        use crate::elgamal::ElGamalSecretKey;
+        use curve25519_dalek::scalar::Scalar;
+
+        let program_id = Pubkey::new_unique();
+        let trustee_secret = ElGamalSecretKey::new(Scalar::from(0x5ec7e7u64));
+        let public_key = trustee_secret.public_key();
+        let trustee_key = Pubkey::new_unique();
+
+        // the trustee key must be a usable point
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            0,
+            ElectionState::encrypted_space(3),
+            program_id,
+        );
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let config = |public_key, eligibility| {
+            VoteInstruction::InitializeElection(ElectionConfig {
+                metadata_hash: [1; 32],
+                candidate_labels: vec![[0; 32]; 3],
+                start_time: 0,
+                end_time: 100,
+                eligibility,
+                voting_method: VotingMethod::Encrypted {
+                    trustee: trustee_key,
+                    public_key,
+                },
+                verifying_key: vec![],
+            })
+            .pack()
+        };
+        let accounts = vec![election.info(false), authority.info(true)];
+        for invalid_key in [[0; 32], [0xff; 32]].iter() {
+            assert_eq!(
+                process(
+                    &program_id,
+                    &accounts,
+                    &config(*invalid_key, Eligibility::Open)
+                ),
+                Err(VoteError::InvalidCiphertext.into())
+            );
+        }
+
+        // a ballot and its proofs have to fit a transaction
+
+        let oversized = VoteInstruction::InitializeElection(ElectionConfig {
+            metadata_hash: [1; 32],
+            candidate_labels: vec![[0; 32]; elgamal::MAX_ENCRYPTED_CANDIDATES as usize + 1],
+            start_time: 0,
+            end_time: 100,
+            eligibility: Eligibility::Open,
+            voting_method: VotingMethod::Encrypted {
+                trustee: trustee_key,
+                public_key,
+            },
+            verifying_key: vec![],
+        })
+        .pack();
+        assert_eq!(
+            process(&program_id, &accounts, &oversized),
+            Err(VoteError::InvalidCandidateCount.into())
+        );
+
+        // every ballot weighs 1, so the trustee can find the tallies by
+        // searching up to the number of voters
+
+        for weighted in [
+            Eligibility::Registry,
+            Eligibility::TokenBalance(Pubkey::new_unique()),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                process(
+                    &program_id,
+                    &accounts,
+                    &config(public_key, weighted.clone())
+                ),
+                Err(VoteError::WrongEligibilityMode.into())
+            );
+        }
+        process(
+            &program_id,
+            &accounts,
+            &config(public_key, Eligibility::Open),
+        )
+        .unwrap();
+        drop(accounts);
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.encrypted_tallies, vec![Ciphertext::default(); 3]);
+
+        let election_key = election.key;
+        let encrypt_ballot = |voter: &Pubkey, choice: u8, seed: u64| {
+            let randomness: Vec<Scalar> = (0..3)
+                .map(|candidate| Scalar::from(seed * 3 + candidate + 1))
+                .collect();
+            let context = elgamal::ballot_context(&election_key, voter);
+            elgamal::encrypt_ballot(&public_key, choice, &randomness, &context).unwrap()
+        };
+        let vote_data =
+            |(ballot, proof)| VoteInstruction::CastEncryptedVote { ballot, proof }.pack();
+
+        // ballots are summed in the encrypted tallies while the plaintext
+        // tallies stay empty
+
+        set_clock(0);
+        let voters: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mut receipts: Vec<TestAccount> = voters
+            .iter()
+            .map(|voter| receipt_account(&program_id, &election.key, voter))
+            .collect();
+        for (index, choice) in [1, 1, 0].iter().enumerate() {
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &voters[index],
+                &mut receipts[index],
+                &mut [],
+                &vote_data(encrypt_ballot(&voters[index], *choice, index as u64)),
+            )
+            .unwrap();
+        }
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+        let state = ElectionState::unpack(&election.data).unwrap();
+        let decrypted: Vec<Option<u64>> = state
+            .encrypted_tallies
+            .iter()
+            .map(|tally| trustee_secret.decrypt(tally, 10))
+            .collect();
+        assert_eq!(decrypted, vec![Some(1), Some(2), Some(0)]);
+
+        assert_eq!(
+            cast_vote_with(
+                &program_id,
+                &mut election,
+                &voters[0],
+                &mut receipts[0],
+                &mut [],
+                &vote_data(encrypt_ballot(&voters[0], 0, 9)),
+            ),
+            Err(VoteError::AlreadyVoted.into())
+        );
+
+        // ballots must hold one ciphertext per candidate and prove they
+        // encrypt a single vote of the voter casting them
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+
+        let mut short = encrypt_ballot(&voter, 0, 4);
+        short.0.pop();
+        let mut invalid = encrypt_ballot(&voter, 0, 5);
+        invalid.0[2].masked = [0xff; 32];
+        let copied = encrypt_ballot(&voters[1], 1, 6);
+
+        // a proof of a single vote doesn't carry over to a ballot piling
+        // 1000 votes on a candidate
+        let (mut stuffed, proof) = encrypt_ballot(&voter, 0, 7);
+        stuffed[0] = Ciphertext::encrypt(&public_key, 1_000, &Scalar::from(22u64)).unwrap();
+
+        for (ballot, error) in [
+            (short, VoteError::InvalidCandidateCount),
+            (invalid, VoteError::InvalidProof),
+            (copied, VoteError::InvalidProof),
+            ((stuffed, proof), VoteError::InvalidProof),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                cast_vote_with(
+                    &program_id,
+                    &mut election,
+                    &voter,
+                    &mut receipt,
+                    &mut [],
+                    &vote_data(ballot.clone()),
+                ),
+                Err(error.clone().into())
+            );
+        }
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+
+        // encrypted ballots can't be taken back
+
+        let mut voter_account = TestAccount::new(voters[0], 0, 0, system_program::id());
+        let accounts = vec![
+            election.info(false),
+            voter_account.info(true),
+            receipts[0].info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &VoteInstruction::RevokeVote.pack()),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+        drop(accounts);
+
+        // the trustee publishes the proven decryption once voting ended
+
+        let encrypted_tallies = ElectionState::unpack(&election.data)
+            .unwrap()
+            .encrypted_tallies;
+        let prove = |values: &[u64]| -> Vec<DecryptionProof> {
+            encrypted_tallies
+                .iter()
+                .zip(values)
+                .map(|(tally, value)| trustee_secret.prove_decryption(tally, *value).unwrap())
+                .collect()
+        };
+        let decrypt = |election: &mut TestAccount, trustee: Pubkey, is_signer, tallies: &[u64]| {
+            let mut trustee = TestAccount::new(trustee, 0, 0, system_program::id());
+            let accounts = vec![election.info(false), trustee.info(is_signer)];
+            let instruction = VoteInstruction::DecryptTally {
+                tallies: tallies.to_vec(),
+                proofs: prove(tallies),
+            };
+            process(&program_id, &accounts, &instruction.pack())
+        };
+
+        assert_eq!(
+            decrypt(&mut election, trustee_key, true, &[1, 2, 0]),
+            Err(VoteError::VotingInProgress.into())
+        );
+
+        set_clock(100);
+        assert_eq!(
+            decrypt(&mut election, trustee_key, false, &[1, 2, 0]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            decrypt(&mut election, authority.key, true, &[1, 2, 0]),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            decrypt(&mut election, trustee_key, true, &[1, 2]),
+            Err(VoteError::InvalidCandidateCount.into())
+        );
+        for wrong in [[0, 3, 0], [1, 2, 1], [2, 1, 0]].iter() {
+            assert_eq!(
+                decrypt(&mut election, trustee_key, true, wrong),
+                Err(VoteError::InvalidProof.into())
+            );
+        }
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        decrypt(&mut election, trustee_key, true, &[1, 2, 0]).unwrap();
+        let state = ElectionState::unpack(&election.data).unwrap();
+        assert_eq!(state.tallies, vec![1, 2, 0]);
+        assert!(state.is_finalized);
+
+        // the proofs pin the result, publishing again changes nothing
+        decrypt(&mut election, trustee_key, true, &[1, 2, 0]).unwrap();
+        assert_eq!(
+            decrypt(&mut election, trustee_key, true, &[0, 3, 0]),
+            Err(VoteError::InvalidProof.into())
+        );
+        assert_eq!(tallies(&election), vec![1, 2, 0]);
+
+        // decrypting only fits encrypted elections
+
+        let mut plurality =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        ElectionState::new(trustee_key, 3)
+            .pack(&mut plurality.data)
+            .unwrap();
+        assert_eq!(
+            decrypt(&mut plurality, trustee_key, true, &[0, 0, 0]),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+    }
+
+    #[test]
+    fn test_anonymous_voting() {
+        use crate::{
+            anonymous::{field_to_bytes, prove_membership, setup, Group, Identity},
+            state::find_nullifier_address,
+        };
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let program_id = Pubkey::new_unique();
+        let rng = &mut StdRng::seed_from_u64(0);
+        let members: Vec<Identity> = (0..3).map(|_| Identity::random(rng)).collect();
+        let group = Group::new(3, members.iter().map(Identity::commitment).collect()).unwrap();
+        let root = field_to_bytes(&group.root());
+        let (proving_key, verifying_key) = setup(group.depth(), rng).unwrap();
+
+        // the verifying key must fit the circuit, which only carries a
+        // single choice
+
+        let mut election = TestAccount::new(
+            Pubkey::new_unique(),
+            0,
+            ElectionState::anonymous_space(3),
+            program_id,
+        );
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let config = |voting_method, verifying_key: &[u8]| {
+            VoteInstruction::InitializeElection(ElectionConfig {
+                metadata_hash: [1; 32],
+                candidate_labels: vec![[0; 32]; 3],
+                start_time: 0,
+                end_time: 100,
+                eligibility: Eligibility::AnonymousGroup(root),
+                voting_method,
+                verifying_key: verifying_key.to_vec(),
+            })
+            .pack()
+        };
+        let accounts = vec![election.info(false), authority.info(true)];
+        for invalid_key in [&verifying_key[1..], &[]].iter() {
+            assert_eq!(
+                process(
+                    &program_id,
+                    &accounts,
+                    &config(VotingMethod::Plurality, invalid_key)
+                ),
+                Err(VoteError::InvalidVerifyingKey.into())
+            );
+        }
+        assert_eq!(
+            process(
+                &program_id,
+                &accounts,
+                &config(VotingMethod::Approval, &verifying_key)
+            ),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+        process(
+            &program_id,
+            &accounts,
+            &config(VotingMethod::Plurality, &verifying_key),
+        )
+        .unwrap();
+        drop(accounts);
+
+        let election_key = election.key;
+        let mut ballot = |index: usize, candidate: u8| {
+            let member = &members[index];
+            let nullifier_hash = field_to_bytes(&member.nullifier_hash(&election_key));
+            let proof = prove_membership(
+                &proving_key,
+                member,
+                &group,
+                index,
+                &election_key,
+                candidate,
+                rng,
+            )
+            .unwrap();
+            (nullifier_hash, proof)
+        };
+        let nullifier_account = |nullifier_hash: &[u8; 32]| {
+            let (key, _) = find_nullifier_address(&program_id, &election_key, nullifier_hash);
+            TestAccount::new(key, 1, NullifierRecord::LEN, program_id)
+        };
+        let cast = |election: &mut TestAccount,
+                    nullifier: &mut TestAccount,
+                    candidate,
+                    (nullifier_hash, proof): ([u8; 32], MembershipProof)| {
+            // any payer will do, it is not tied to the member
+            let mut payer = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+            let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+            let accounts = vec![
+                election.info(false),
+                payer.info(true),
+                nullifier.info(false),
+                system.info(false),
+            ];
+            let instruction = VoteInstruction::CastAnonymousVote {
+                candidate,
+                nullifier_hash,
+                proof,
+            };
+            process(&program_id, &accounts, &instruction.pack())
+        };
+
+        // a proof only counts for the member's own nullifier hash and the
+        // candidate it was made for
+
+        set_clock(0);
+        let (nullifier_hash, proof) = ballot(2, 0);
+        let mut nullifier = nullifier_account(&nullifier_hash);
+        assert_eq!(
+            cast(&mut election, &mut nullifier, 2, (nullifier_hash, proof)),
+            Err(VoteError::InvalidProof.into())
+        );
+        let other_hash = field_to_bytes(&members[1].nullifier_hash(&election_key));
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier_account(&other_hash),
+                0,
+                (other_hash, proof)
+            ),
+            Err(VoteError::InvalidProof.into())
+        );
+        assert_eq!(
+            cast(&mut election, &mut nullifier, 3, (nullifier_hash, proof)),
+            Err(VoteError::InvalidCandidate.into())
+        );
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier_account(&[0; 32]),
+                0,
+                (nullifier_hash, proof)
+            ),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        // members vote once each without revealing who they are
+
+        cast(&mut election, &mut nullifier, 0, (nullifier_hash, proof)).unwrap();
+        let record = NullifierRecord::unpack_unchecked(&nullifier.data).unwrap();
+        assert_eq!(record.election, election_key);
+        assert_eq!(record.nullifier_hash, nullifier_hash);
+        assert_eq!(record.candidate, 0);
+
+        let mut nullifiers = vec![];
+        for (index, candidate) in [1, 1].iter().enumerate() {
+            let ballot = ballot(index, *candidate);
+            let mut nullifier = nullifier_account(&ballot.0);
+            cast(&mut election, &mut nullifier, *candidate, ballot).unwrap();
+            nullifiers.push(nullifier);
+        }
+        assert_eq!(tallies(&election), vec![1, 2, 0]);
+
+        // a member's nullifier hash is the same for every ballot in the
+        // election
+
+        let second = ballot(0, 2);
+        assert_eq!(
+            cast(&mut election, &mut nullifiers[0], 2, second),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        assert_eq!(tallies(&election), vec![1, 2, 0]);
+
+        // ballots naming a voter are refused, as are late ones
+
+        let voter = Pubkey::new_unique();
+        let mut receipt = receipt_account(&program_id, &election.key, &voter);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voter, &mut receipt, 0),
+            Err(VoteError::WrongEligibilityMode.into())
+        );
+
+        set_clock(100);
+        let late = ballot(2, 0);
+        assert_eq!(
+            cast(&mut election, &mut nullifier, 0, late),
+            Err(VoteError::ElectionClosed.into())
+        );
+    }
+
+    #[test]
+    fn test_ring_voting() {
+        use crate::{
+            ring::{secret_scalar, sign},
+            state::find_nullifier_address,
+        };
+        use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+
+        let program_id = Pubkey::new_unique();
+        let secrets: Vec<_> = (1..=4u8).map(|seed| secret_scalar(&[seed; 32])).collect();
+        let voters: Vec<_> = secrets
+            .iter()
+            .map(|secret| {
+                Pubkey::new_from_array((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+            })
+            .collect();
+
+        // ring signatures only sign a single choice
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        let mut authority = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let config = |voting_method| {
+            VoteInstruction::InitializeElection(ElectionConfig {
+                metadata_hash: [1; 32],
+                candidate_labels: vec![[0; 32]; 3],
+                start_time: 0,
+                end_time: 100,
+                eligibility: Eligibility::AnonymousRegistry,
+                voting_method,
+                verifying_key: vec![],
+            })
+            .pack()
+        };
+        let accounts = vec![election.info(false), authority.info(true)];
+        assert_eq!(
+            process(&program_id, &accounts, &config(VotingMethod::Approval)),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+        process(&program_id, &accounts, &config(VotingMethod::Plurality)).unwrap();
+        drop(accounts);
+
+        // registered keys must be usable as ring members
+
+        let mut register = |election: &mut TestAccount, voter: &Pubkey| {
+            let mut record = voter_record_account(&program_id, &election.key, voter, 1);
+            record.data = vec![0; VoterRecord::LEN];
+            let accounts = vec![
+                election.info(false),
+                authority.info(true),
+                record.info(false),
+                system.info(false),
+            ];
+            let instruction = VoteInstruction::RegisterVoter {
+                voter: *voter,
+                weight: 1,
+            };
+            process(&program_id, &accounts, &instruction.pack())
+        };
+        assert_eq!(
+            register(&mut election, &Pubkey::default()),
+            Err(VoteError::NotEligible.into())
+        );
+        for voter in voters.iter() {
+            register(&mut election, voter).unwrap();
+        }
+
+        let election_key = election.key;
+        let ring_records = |ring: &[Pubkey], weight| -> Vec<TestAccount> {
+            ring.iter()
+                .map(|voter| voter_record_account(&program_id, &election_key, voter, weight))
+                .collect()
+        };
+        let nullifier_account = |signature: &RingSignature| {
+            let (key, _) = find_nullifier_address(&program_id, &election_key, &signature.key_image);
+            TestAccount::new(key, 1, NullifierRecord::LEN, program_id)
+        };
+        let cast = |election: &mut TestAccount,
+                    nullifier: &mut TestAccount,
+                    records: &mut [TestAccount],
+                    candidate,
+                    signature: &RingSignature| {
+            // any payer will do, it is not tied to the voter
+            let mut payer = TestAccount::new(Pubkey::new_unique(), 0, 0, system_program::id());
+            let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+            let mut accounts = vec![
+                election.info(false),
+                payer.info(true),
+                nullifier.info(false),
+                system.info(false),
+            ];
+            accounts.extend(records.iter_mut().map(|record| record.info(false)));
+            let instruction = VoteInstruction::CastRingVote {
+                candidate,
+                signature: signature.clone(),
+            };
+            process(&program_id, &accounts, &instruction.pack())
+        };
+
+        // a signature only counts for its ring, its candidate and registered
+        // members of equal weight
+
+        set_clock(0);
+        let ring = &voters[..3];
+        let signature = sign(ring, &secrets[1], election_key.as_ref(), &[2]).unwrap();
+        let mut nullifier = nullifier_account(&signature);
+        let mut records = ring_records(ring, 1);
+        assert_eq!(
+            cast(&mut election, &mut nullifier, &mut records, 1, &signature),
+            Err(VoteError::InvalidRingSignature.into())
+        );
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier,
+                &mut records[..2],
+                2,
+                &signature
+            ),
+            Err(VoteError::InvalidRingSignature.into())
+        );
+        let mut tampered = signature.clone();
+        tampered.responses[0] = tampered.responses[1];
+        assert_eq!(
+            cast(&mut election, &mut nullifier, &mut records, 2, &tampered),
+            Err(VoteError::InvalidRingSignature.into())
+        );
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier_account(&tampered),
+                &mut records,
+                3,
+                &signature
+            ),
+            Err(VoteError::InvalidCandidate.into())
+        );
+        assert_eq!(
+            cast(&mut election, &mut nullifier, &mut [], 2, &signature),
+            Err(VoteError::NotEligible.into())
+        );
+        let mut other_key_image = signature.clone();
+        other_key_image.key_image = [1; 32];
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier,
+                &mut records,
+                2,
+                &other_key_image
+            ),
+            Err(VoteError::InvalidReceiptAccount.into())
+        );
+
+        let mut mixed = ring_records(ring, 1);
+        mixed[2] = voter_record_account(&program_id, &election_key, &ring[2], 2);
+        assert_eq!(
+            cast(&mut election, &mut nullifier, &mut mixed, 2, &signature),
+            Err(VoteError::InvalidWeight.into())
+        );
+        let mut foreign = ring_records(ring, 1);
+        foreign[0] = voter_record_account(&program_id, &Pubkey::new_unique(), &ring[0], 1);
+        assert_eq!(
+            cast(&mut election, &mut nullifier, &mut foreign, 2, &signature),
+            Err(VoteError::NotEligible.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        // registered voters vote with the weight their ring shares
+
+        cast(&mut election, &mut nullifier, &mut records, 2, &signature).unwrap();
+        let record = NullifierRecord::unpack_unchecked(&nullifier.data).unwrap();
+        assert_eq!(record.election, election_key);
+        assert_eq!(record.nullifier_hash, signature.key_image);
+        assert_eq!(record.candidate, 2);
+
+        let signature = sign(&voters, &secrets[3], election_key.as_ref(), &[0]).unwrap();
+        let mut records = ring_records(&voters, 3);
+        cast(
+            &mut election,
+            &mut nullifier_account(&signature),
+            &mut records,
+            0,
+            &signature,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![3, 0, 1]);
+
+        // the key image gives away a second ballot, whatever the ring
+
+        let ring = &voters[1..];
+        let second = sign(ring, &secrets[1], election_key.as_ref(), &[0]).unwrap();
+        assert_eq!(second.key_image, record.nullifier_hash);
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier,
+                &mut ring_records(ring, 1),
+                0,
+                &second
+            ),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        assert_eq!(tallies(&election), vec![3, 0, 1]);
+
+        // rings stay within what a transaction fits
+
+        let secrets: Vec<_> = (1..=MAX_RING_SIZE as u8 + 1)
+            .map(|seed| secret_scalar(&[seed; 32]))
+            .collect();
+        let large: Vec<_> = secrets
+            .iter()
+            .map(|secret| {
+                Pubkey::new_from_array((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+            })
+            .collect();
+        let signer = &secrets[MAX_RING_SIZE];
+        let oversized = sign(&large, signer, election_key.as_ref(), &[1]).unwrap();
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier_account(&oversized),
+                &mut ring_records(&large, 1),
+                1,
+                &oversized
+            ),
+            Err(VoteError::InvalidRingSignature.into())
+        );
+        let largest = sign(&large[1..], signer, election_key.as_ref(), &[1]).unwrap();
+        cast(
+            &mut election,
+            &mut nullifier_account(&largest),
+            &mut ring_records(&large[1..], 1),
+            1,
+            &largest,
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![3, 1, 1]);
+
+        // ballots naming a voter are refused, as are late ones
+
+        let mut receipt = receipt_account(&program_id, &election.key, &voters[0]);
+        assert_eq!(
+            cast_vote(&program_id, &mut election, &voters[0], &mut receipt, 0),
+            Err(VoteError::WrongEligibilityMode.into())
+        );
+
+        set_clock(100);
+        let late = sign(&voters, &secrets[0], election_key.as_ref(), &[0]).unwrap();
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut nullifier_account(&late),
+                &mut records,
+                0,
+                &late
+            ),
+            Err(VoteError::ElectionClosed.into())
+        );
+    }
+
+    #[test]
+    fn test_signed_voting() {
+        use crate::{
+            instruction::cast_signed_vote,
+            signed_ballot::new_ed25519_instruction,
+            state::{find_nonce_address, NonceRecord},
+        };
+        use solana_sdk::signature::{Keypair, Signer};
+
+        let program_id = Pubkey::new_unique();
+        let voter = Keypair::new();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        ElectionState::new(Pubkey::new_unique(), 3)
+            .pack(&mut election.data)
+            .unwrap();
+        let election_key = election.key;
+
+        let mut receipt = receipt_account(&program_id, &election_key, &voter.pubkey());
+        let (nonce_key, _) = find_nonce_address(&program_id, &election_key, &voter.pubkey());
+        let mut nonce_record = TestAccount::new(nonce_key, 1, NonceRecord::LEN, program_id);
+
+        // the relayer pays and signs, the voter only signs the ballot
+        let payer = Pubkey::new_unique();
+        let ballot = |candidate, nonce| SignedBallot {
+            election: election_key,
+            candidate,
+            nonce,
+        };
+        let sign = |signer: &Keypair, ballot: &SignedBallot| {
+            let message = ballot.message();
+            let signature = signer.sign_message(&message).into();
+            new_ed25519_instruction(&[(signer.pubkey(), signature, message)])
+        };
+        let cast = |election: &mut TestAccount,
+                    receipt: &mut TestAccount,
+                    nonce_record: &mut TestAccount,
+                    transaction: &[Instruction],
+                    extra_accounts: &mut [TestAccount]| {
+            let instruction = transaction.last().unwrap();
+            let mut payer = TestAccount::new(payer, 0, 0, system_program::id());
+            let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+            let mut sysvar = instructions_sysvar(transaction);
+            let mut accounts = vec![
+                election.info(false),
+                payer.info(true),
+                receipt.info(false),
+                nonce_record.info(false),
+                system.info(false),
+                sysvar.info(false),
+            ];
+            accounts.extend(extra_accounts.iter_mut().map(|account| account.info(false)));
+            process(&program_id, &accounts, &instruction.data)
+        };
+        let vote =
+            |ballot: &SignedBallot| cast_signed_vote(&program_id, &payer, &voter.pubkey(), ballot);
+
+        // the transaction must verify the voter's signature of that very
+        // ballot
+
+        set_clock(0);
+        let first = ballot(1, 1);
+        for transaction in [
+            vec![vote(&first)],
+            vec![sign(&voter, &ballot(2, 1)), vote(&first)],
+            vec![sign(&voter, &ballot(1, 0)), vote(&first)],
+            vec![sign(&Keypair::new(), &first), vote(&first)],
+        ]
+        .iter()
+        {
+            assert_eq!(
+                cast(
+                    &mut election,
+                    &mut receipt,
+                    &mut nonce_record,
+                    transaction,
+                    &mut []
+                ),
+                Err(VoteError::MissingBallotSignature.into())
+            );
+        }
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        // the signature is only looked up in the Instructions sysvar
+
+        let transaction = [sign(&voter, &first), vote(&first)];
+        let mut payer_account = TestAccount::new(payer, 0, 0, system_program::id());
+        let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+        let mut fake_sysvar = instructions_sysvar(&transaction);
+        fake_sysvar.key = Pubkey::new_unique();
+        let accounts = vec![
+            election.info(false),
+            payer_account.info(true),
+            receipt.info(false),
+            nonce_record.info(false),
+            system.info(false),
+            fake_sysvar.info(false),
+        ];
+        assert_eq!(
+            process(&program_id, &accounts, &transaction[1].data),
+            Err(ProgramError::InvalidArgument)
+        );
+        drop(accounts);
+
+        // the signed ballot counts for the voter
+
+        cast(
+            &mut election,
+            &mut receipt,
+            &mut nonce_record,
+            &transaction,
+            &mut [],
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 1, 0]);
+        let record = VoteReceipt::unpack(&receipt.data).unwrap();
+        assert_eq!((record.voter, record.candidate), (voter.pubkey(), 1));
+        assert_eq!(
+            NonceRecord::unpack_unchecked(&nonce_record.data)
+                .unwrap()
+                .nonce,
+            1
+        );
+
+        // a ballot is cast once, even after the voter revoked it
+
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &transaction,
+                &mut []
+            ),
+            Err(VoteError::StaleNonce.into())
+        );
+        let second = ballot(2, 2);
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &[sign(&voter, &second), vote(&second)],
+                &mut []
+            ),
+            Err(VoteError::AlreadyVoted.into())
+        );
+
+        let mut voter_account = TestAccount::new(voter.pubkey(), 0, 0, system_program::id());
+        let accounts = vec![
+            election.info(false),
+            voter_account.info(true),
+            receipt.info(false),
+        ];
+        process(&program_id, &accounts, &VoteInstruction::RevokeVote.pack()).unwrap();
+        drop(accounts);
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &transaction,
+                &mut []
+            ),
+            Err(VoteError::StaleNonce.into())
+        );
+        cast(
+            &mut election,
+            &mut receipt,
+            &mut nonce_record,
+            &[sign(&voter, &second), vote(&second)],
+            &mut [],
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![0, 0, 1]);
+
+        // registered voters vote with their weight, others not at all
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        state.eligibility = Eligibility::Registry;
+        state.tallies = vec![0; 3];
+        state.pack(&mut election.data).unwrap();
+        let mut receipt = receipt_account(&program_id, &election_key, &voter.pubkey());
+        let third = ballot(0, 3);
+        let transaction = [sign(&voter, &third), vote(&third)];
+        let mut unregistered = voter_record_account(&program_id, &election_key, &voter.pubkey(), 1);
+        unregistered.owner = system_program::id();
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &transaction,
+                std::slice::from_mut(&mut unregistered)
+            ),
+            Err(VoteError::NotEligible.into())
+        );
+        let mut record = voter_record_account(&program_id, &election_key, &voter.pubkey(), 3);
+        cast(
+            &mut election,
+            &mut receipt,
+            &mut nonce_record,
+            &transaction,
+            std::slice::from_mut(&mut record),
+        )
+        .unwrap();
+        assert_eq!(tallies(&election), vec![3, 0, 0]);
+
+        // ballots needing more than the voter's signature are refused
+
+        let mut receipt = receipt_account(&program_id, &election_key, &voter.pubkey());
+        let fourth = ballot(0, 4);
+        let transaction = [sign(&voter, &fourth), vote(&fourth)];
+        state.eligibility = Eligibility::TokenBalance(Pubkey::new_unique());
+        state.pack(&mut election.data).unwrap();
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &transaction,
+                &mut []
+            ),
+            Err(VoteError::WrongEligibilityMode.into())
+        );
+        state.eligibility = Eligibility::Open;
+        state.voting_method = VotingMethod::Approval;
+        state.pack(&mut election.data).unwrap();
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut receipt,
+                &mut nonce_record,
+                &transaction,
+                &mut []
+            ),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+    }
+
+    #[test]
+    fn test_batch_signed_voting() {
+        use crate::{
+            instruction::{cast_signed_votes, BatchPolicy, SignedVote},
+            signed_ballot::new_ed25519_instruction,
+            state::{find_nonce_address, NonceRecord},
+        };
+        use solana_sdk::signature::{Keypair, Signer};
+
+        let program_id = Pubkey::new_unique();
+        let voters: Vec<_> = (0..4).map(|_| Keypair::new()).collect();
+
+        let mut election =
+            TestAccount::new(Pubkey::new_unique(), 0, ElectionState::space(3), program_id);
+        ElectionState::new(Pubkey::new_unique(), 3)
+            .pack(&mut election.data)
+            .unwrap();
+        let election_key = election.key;
+
+        // receipt, nonce record and voter record of each voter
+        let new_ballot_accounts = || -> Vec<_> {
+            voters
+                .iter()
+                .map(|voter| {
+                    let (nonce_key, _) =
+                        find_nonce_address(&program_id, &election_key, &voter.pubkey());
+                    vec![
+                        receipt_account(&program_id, &election_key, &voter.pubkey()),
+                        TestAccount::new(nonce_key, 1, NonceRecord::LEN, program_id),
+                        voter_record_account(&program_id, &election_key, &voter.pubkey(), 1),
+                    ]
+                })
+                .collect()
+        };
+        let mut ballot_accounts = new_ballot_accounts();
+
+        let payer = Pubkey::new_unique();
+        let vote = |voter: usize, candidate, nonce| SignedVote {
+            voter: voters[voter].pubkey(),
+            candidate,
+            nonce,
+        };
+        let sign = |votes: &[SignedVote]| {
+            let signatures: Vec<_> = votes
+                .iter()
+                .map(|vote| {
+                    let voter = voters.iter().find(|voter| voter.pubkey() == vote.voter);
+                    let message = SignedBallot {
+                        election: election_key,
+                        candidate: vote.candidate,
+                        nonce: vote.nonce,
+                    }
+                    .message();
+                    let signature = voter.unwrap().sign_message(&message).into();
+                    (vote.voter, signature, message)
+                })
+                .collect();
+            new_ed25519_instruction(&signatures)
+        };
+        let cast = |election: &mut TestAccount,
+                    ballot_accounts: &mut [Vec<TestAccount>],
+                    transaction: &[Instruction],
+                    batch: &[usize]| {
+            let instruction = transaction.last().unwrap();
+            let mut payer = TestAccount::new(payer, 0, 0, system_program::id());
+            let mut system = TestAccount::new(system_program::id(), 0, 0, Pubkey::default());
+            let mut sysvar = instructions_sysvar(transaction);
+            let infos: Vec<Vec<_>> = ballot_accounts
+                .iter_mut()
+                .map(|accounts| {
+                    accounts
+                        .iter_mut()
+                        .map(|account| account.info(false))
+                        .collect()
+                })
+                .collect();
+            let mut accounts = vec![
+                election.info(false),
+                payer.info(true),
+                system.info(false),
+                sysvar.info(false),
+            ];
+            for voter in batch {
+                accounts.extend(infos[*voter].iter().cloned());
+            }
+            process(&program_id, &accounts, &instruction.data)
+        };
+        let batch = |votes: &[SignedVote], policy| {
+            cast_signed_votes(&program_id, &election_key, &payer, votes, policy)
+        };
+        let is_cast = |ballot_accounts: &[Vec<TestAccount>], voter: usize| {
+            VoteReceipt::unpack_unchecked(&ballot_accounts[voter][0].data)
+                .unwrap()
+                .is_initialized()
+        };
+
+        // an invalid ballot fails an all or nothing batch as a whole
+
+        set_clock(0);
+        let votes = [vote(0, 0, 1), vote(1, 5, 1), vote(2, 2, 1)];
+        let all_or_nothing = [sign(&votes), batch(&votes, BatchPolicy::AllOrNothing)];
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut ballot_accounts,
+                &all_or_nothing,
+                &[0, 1, 2]
+            ),
+            Err(VoteError::InvalidCandidate.into())
+        );
+        assert_eq!(tallies(&election), vec![0, 0, 0]);
+
+        // the runtime discards what the failed instruction wrote
+        let mut ballot_accounts = new_ballot_accounts();
+
+        // and is skipped by a best effort batch, which reports each ballot
+
+        let best_effort = [sign(&votes), batch(&votes, BatchPolicy::BestEffort)];
+        cast(
+            &mut election,
+            &mut ballot_accounts,
+            &best_effort,
+            &[0, 1, 2],
+        )
+        .unwrap();
+        assert_eq!(return_data(), vec![1, 0, 1]);
+        assert_eq!(tallies(&election), vec![1, 0, 1]);
+        assert!(is_cast(&ballot_accounts, 0) && is_cast(&ballot_accounts, 2));
+        assert!(!is_cast(&ballot_accounts, 1));
+        let record = VoteReceipt::unpack(&ballot_accounts[2][0].data).unwrap();
+        assert_eq!((record.voter, record.candidate), (voters[2].pubkey(), 2));
+
+        // unsigned ballots, voters who already voted and repeats within the
+        // batch are skipped too
+
+        let votes = [vote(3, 1, 1), vote(1, 1, 1), vote(0, 2, 2), vote(1, 0, 2)];
+        let mut transaction = [sign(&votes[1..]), batch(&votes, BatchPolicy::AllOrNothing)];
+        assert_eq!(
+            cast(
+                &mut election,
+                &mut ballot_accounts,
+                &transaction,
+                &[3, 1, 0, 1]
+            ),
+            Err(VoteError::MissingBallotSignature.into())
+        );
+        transaction[1] = batch(&votes, BatchPolicy::BestEffort);
+        cast(
+            &mut election,
+            &mut ballot_accounts,
+            &transaction,
+            &[3, 1, 0, 1],
+        )
+        .unwrap();
+        assert_eq!(return_data(), vec![0, 1, 0, 0]);
+        assert_eq!(tallies(&election), vec![1, 1, 1]);
+        assert!(!is_cast(&ballot_accounts, 3));
+
+        // a ballot overflowing its tally is skipped without writing its
+        // receipt or nonce record
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        state.tallies[1] = u64::MAX;
+        state.pack(&mut election.data).unwrap();
+        let votes = [vote(3, 1, 1)];
+        let transaction = [sign(&votes), batch(&votes, BatchPolicy::BestEffort)];
+        cast(&mut election, &mut ballot_accounts, &transaction, &[3]).unwrap();
+        assert_eq!(return_data(), vec![0]);
+        assert_eq!(tallies(&election), vec![1, u64::MAX, 1]);
+        assert!(!is_cast(&ballot_accounts, 3));
+        assert!(!NonceRecord::unpack_unchecked(&ballot_accounts[3][1].data)
+            .unwrap()
+            .is_initialized());
+
+        // a malformed batch fails whatever the policy
+
+        let votes = [vote(3, 1, 1)];
+        let transaction = [sign(&votes), batch(&[], BatchPolicy::BestEffort)];
+        assert_eq!(
+            cast(&mut election, &mut ballot_accounts, &transaction, &[]),
+            Err(VoteError::InvalidInstruction.into())
+        );
+        let transaction = [sign(&votes), batch(&votes, BatchPolicy::BestEffort)];
+        assert_eq!(
+            cast(&mut election, &mut ballot_accounts, &transaction, &[]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        // and so does an election not taking signed ballots
+
+        let mut state = ElectionState::unpack(&election.data).unwrap();
+        state.voting_method = VotingMethod::Approval;
+        state.pack(&mut election.data).unwrap();
+        assert_eq!(
+            cast(&mut election, &mut ballot_accounts, &transaction, &[3]),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+        assert!(!is_cast(&ballot_accounts, 3));
+    }
+}
@@ -0,0 +1,577 @@
+//! Sponsored submission of signed ballots, see `signed_ballot`
+//!
+//! Voters send the relayer their ballot message and signature as JSON:
+//!
+//! ```text
+//! {"voter": "<base58 pubkey>", "message": "<hex>", "signature": "<base58>"}
+//! ```
+//!
+//! The relayer decodes and verifies each ballot as the program would, then
+//! packs as many ballots of an election as fit into each transaction, one
+//! Ed25519 signature program instruction verifying all their signatures
+//! followed by a best effort `CastSignedVotes`, and pays for them with its
+//! sponsor keypair. The program skips the ballots it rejects and returns
+//! which ones it cast, so a rejected ballot doesn't hold back the others.
+//! Skipped ballots and those of failed transactions are retried for a
+//! bounded number of rounds, waiting longer before each.
+//!
+//! `BallotQuota` bounds what the sponsor pays for, ballots of the elections
+//! it allows and up to a number per election.
+//!
+//! The relayer talks to the cluster through `Client`, implemented for the
+//! RPC client here and for `BanksClient` in the tests.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::config::RpcTransactionConfig;
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+    transaction_context::TransactionReturnData,
+};
+use solana_transaction_status::{UiTransactionEncoding, UiTransactionReturnData};
+
+use crate::{
+    instruction::{cast_signed_votes, BatchPolicy, SignedVote},
+    signed_ballot::{new_ed25519_instruction, SignedBallot},
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    str::{self, FromStr},
+    thread,
+    time::Duration,
+};
+
+/// Rounds of submission a ballot gets by default
+pub const DEFAULT_MAX_RETRIES: usize = 10;
+
+/// Wait before the first retry round by default, doubled for each round
+/// after it
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait between two retry rounds
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+/// Ballot a voter signed, checked against its signature
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelayedBallot {
+    /// Voter who signed the ballot
+    pub voter: Pubkey,
+    /// Decoded ballot
+    pub ballot: SignedBallot,
+    /// Voter's ed25519 signature of the ballot message
+    pub signature: [u8; 64],
+}
+
+impl RelayedBallot {
+    /// Decodes a ballot request and verifies its signature
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| format!("invalid JSON: {}", e))?;
+        let field = |name| {
+            value[name]
+                .as_str()
+                .ok_or_else(|| format!("missing field {}", name))
+        };
+
+        let voter = Pubkey::from_str(field("voter")?).map_err(|_| "invalid voter")?;
+        let message = from_hex(field("message")?).ok_or("invalid message")?;
+        let signature =
+            Signature::from_str(field("signature")?).map_err(|_| "invalid signature")?;
+
+        let ballot = SignedBallot::unpack_message(&message).map_err(|_| "invalid ballot")?;
+        if !signature.verify(voter.as_ref(), &message) {
+            return Err("signature does not verify".to_string());
+        }
+
+        Ok(Self {
+            voter,
+            ballot,
+            signature: signature.into(),
+        })
+    }
+
+    /// Encodes the ballot request as a voter would send it
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "voter": self.voter.to_string(),
+            "message": to_hex(&self.ballot.message()),
+            "signature": Signature::from(self.signature).to_string(),
+        })
+        .to_string()
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] if pair.iter().all(u8::is_ascii_hexdigit) => {
+                u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok()
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Why a ballot request is refused before it is relayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Refusal {
+    /// The ballot is for an election the relayer doesn't pay for
+    UnknownElection,
+    /// The election already had as many ballots as the relayer pays for
+    QuotaExceeded,
+}
+
+/// Elections a relayer accepts ballots for and how many ballots of each
+pub struct BallotQuota {
+    elections: HashSet<Pubkey>,
+    max_ballots: usize,
+    accepted: HashMap<Pubkey, usize>,
+}
+
+impl BallotQuota {
+    /// Allows up to `max_ballots` ballots for each of `elections`
+    pub fn new(elections: impl IntoIterator<Item = Pubkey>, max_ballots: usize) -> Self {
+        Self {
+            elections: elections.into_iter().collect(),
+            max_ballots,
+            accepted: HashMap::new(),
+        }
+    }
+
+    /// Counts the ballot against its election's quota, unless the election
+    /// isn't allowed or its quota is used up. Repeats count too, so
+    /// resending a ballot can't be used to flood the relayer.
+    pub fn admit(&mut self, ballot: &RelayedBallot) -> Result<(), Refusal> {
+        let election = ballot.ballot.election;
+        if !self.elections.contains(&election) {
+            return Err(Refusal::UnknownElection);
+        }
+        let accepted = self.accepted.entry(election).or_insert(0);
+        if *accepted >= self.max_ballots {
+            return Err(Refusal::QuotaExceeded);
+        }
+        *accepted += 1;
+        Ok(())
+    }
+}
+
+/// Connection to the cluster transactions are submitted to
+pub trait Client {
+    /// Blockhash to sign new transactions with
+    fn latest_blockhash(&mut self) -> Result<Hash, String>;
+
+    /// Submits a transaction, waits until it is confirmed or rejected and
+    /// returns the data its programs returned, if any
+    fn send_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Option<TransactionReturnData>, String>;
+}
+
+impl Client for RpcClient {
+    fn latest_blockhash(&mut self) -> Result<Hash, String> {
+        self.get_latest_blockhash().map_err(|e| e.to_string())
+    }
+
+    fn send_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Option<TransactionReturnData>, String> {
+        let signature = self
+            .send_and_confirm_transaction(transaction)
+            .map_err(|e| e.to_string())?;
+
+        // confirmation doesn't carry the return data, the confirmed
+        // transaction's status does
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(self.commitment()),
+            max_supported_transaction_version: Some(0),
+        };
+        let confirmed = self
+            .get_transaction_with_config(&signature, config)
+            .map_err(|e| e.to_string())?;
+        let return_data: Option<UiTransactionReturnData> = confirmed
+            .transaction
+            .meta
+            .and_then(|meta| meta.return_data.into());
+        return_data
+            .map(|return_data| {
+                Ok(TransactionReturnData {
+                    program_id: Pubkey::from_str(&return_data.program_id)
+                        .map_err(|e| e.to_string())?,
+                    data: STANDARD
+                        .decode(&return_data.data.0)
+                        .map_err(|e| e.to_string())?,
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Outcome of relaying a set of ballots
+#[derive(Debug, Default)]
+pub struct RelayReport {
+    /// Ballots cast and the signature of the transaction that cast them
+    pub cast: Vec<(RelayedBallot, Signature)>,
+    /// Ballots given up on and the last error submitting them
+    pub failed: Vec<(RelayedBallot, String)>,
+}
+
+/// Submits signed ballots to a vote program, paying with a sponsor keypair
+pub struct Relayer<C: Client> {
+    client: C,
+    program_id: Pubkey,
+    sponsor: Keypair,
+    max_retries: usize,
+    retry_delay: Duration,
+}
+
+impl<C: Client> Relayer<C> {
+    /// Creates a relayer retrying ballots for `DEFAULT_MAX_RETRIES` rounds,
+    /// first after `DEFAULT_RETRY_DELAY`
+    pub fn new(client: C, program_id: Pubkey, sponsor: Keypair) -> Self {
+        Self {
+            client,
+            program_id,
+            sponsor,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+        }
+    }
+
+    /// Sets the rounds of submission a ballot gets
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the wait before the first retry round, doubled for each round
+    /// after it up to `MAX_RETRY_DELAY`
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Connection to the cluster
+    pub fn client_mut(&mut self) -> &mut C {
+        &mut self.client
+    }
+
+    /// Public key paying for the transactions
+    pub fn sponsor(&self) -> Pubkey {
+        self.sponsor.pubkey()
+    }
+
+    /// Builds the instructions casting a batch of ballots of one election,
+    /// the program skips those it rejects
+    pub fn instructions(&self, ballots: &[&RelayedBallot]) -> Vec<Instruction> {
+        let signatures: Vec<_> = ballots
+            .iter()
+            .map(|ballot| (ballot.voter, ballot.signature, ballot.ballot.message()))
+            .collect();
+        let votes: Vec<_> = ballots
+            .iter()
+            .map(|ballot| SignedVote {
+                voter: ballot.voter,
+                candidate: ballot.ballot.candidate,
+                nonce: ballot.ballot.nonce,
+            })
+            .collect();
+        vec![
+            new_ed25519_instruction(&signatures),
+            cast_signed_votes(
+                &self.program_id,
+                &ballots[0].ballot.election,
+                &self.sponsor.pubkey(),
+                &votes,
+                BatchPolicy::BestEffort,
+            ),
+        ]
+    }
+
+    /// Splits ballots into batches that each fit a transaction. A batch
+    /// holds the ballots of one election, since a transaction only returns
+    /// what its last program returned, which ballots one `CastSignedVotes`
+    /// cast.
+    pub fn batches<'a>(&self, ballots: &[&'a RelayedBallot]) -> Vec<Vec<&'a RelayedBallot>> {
+        let mut elections: Vec<Pubkey> = vec![];
+        for ballot in ballots {
+            if !elections.contains(&ballot.ballot.election) {
+                elections.push(ballot.ballot.election);
+            }
+        }
+
+        let mut batches = vec![];
+        for election in elections {
+            let mut batch: Vec<&RelayedBallot> = vec![];
+            for ballot in ballots
+                .iter()
+                .filter(|ballot| ballot.ballot.election == election)
+            {
+                batch.push(ballot);
+                if batch.len() > 1 && self.transaction_size(&batch) > PACKET_DATA_SIZE {
+                    batch.pop();
+                    batches.push(mem::replace(&mut batch, vec![ballot]));
+                }
+            }
+            batches.push(batch);
+        }
+        batches
+    }
+
+    // which ballots of a batch the program cast, from what the transaction
+    // returned
+    fn outcomes(
+        &self,
+        return_data: Option<TransactionReturnData>,
+        len: usize,
+    ) -> Option<Vec<bool>> {
+        let return_data = return_data.filter(|return_data| {
+            return_data.program_id == self.program_id && return_data.data.len() == len
+        })?;
+        Some(return_data.data.iter().map(|cast| *cast == 1).collect())
+    }
+
+    fn transaction_size(&self, ballots: &[&RelayedBallot]) -> usize {
+        let message = Message::new(&self.instructions(ballots), Some(&self.sponsor.pubkey()));
+        // compact array of the one sponsor signature, then the message
+        1 + 64 + message.serialize().len()
+    }
+
+    /// Casts the ballots, skipping repeats, retrying the ballots that weren't
+    /// cast for up to `max_retries` rounds with a growing wait before each
+    pub fn relay(&mut self, ballots: Vec<RelayedBallot>) -> RelayReport {
+        let mut report = RelayReport::default();
+
+        let mut seen = HashSet::new();
+        let mut pending: Vec<_> = ballots
+            .into_iter()
+            .filter(|ballot| {
+                seen.insert((ballot.voter, ballot.ballot.election, ballot.ballot.nonce))
+            })
+            .map(|ballot| (ballot, String::new()))
+            .collect();
+
+        let mut delay = self.retry_delay;
+        for round in 0..self.max_retries {
+            if pending.is_empty() {
+                break;
+            }
+            // give whatever failed the last round, e.g. a congested
+            // cluster, time to clear
+            if round > 0 {
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+
+            let blockhash = match self.client.latest_blockhash() {
+                Ok(blockhash) => blockhash,
+                Err(error) => {
+                    for (_, last_error) in pending.iter_mut() {
+                        *last_error = error.clone();
+                    }
+                    continue;
+                }
+            };
+
+            let ballots: Vec<_> = pending.iter().map(|(ballot, _)| ballot).collect();
+            let mut failed = vec![];
+            for batch in self.batches(&ballots) {
+                let transaction = Transaction::new_signed_with_payer(
+                    &self.instructions(&batch),
+                    Some(&self.sponsor.pubkey()),
+                    &[&self.sponsor],
+                    blockhash,
+                );
+                let signature = transaction.signatures[0];
+                let outcomes = match self.client.send_transaction(&transaction) {
+                    Ok(return_data) => self.outcomes(return_data, batch.len()).ok_or_else(|| {
+                        format!("transaction {} returned no ballot outcomes", signature)
+                    }),
+                    Err(error) => Err(error),
+                };
+                match outcomes {
+                    Ok(outcomes) => {
+                        for (ballot, cast) in batch.into_iter().zip(outcomes) {
+                            if cast {
+                                report.cast.push((ballot.clone(), signature));
+                            } else {
+                                let error = format!("rejected in transaction {}", signature);
+                                failed.push((ballot.clone(), error));
+                            }
+                        }
+                    }
+                    Err(error) => failed.extend(
+                        batch
+                            .into_iter()
+                            .map(|ballot| (ballot.clone(), error.clone())),
+                    ),
+                }
+            }
+            pending = failed;
+        }
+
+        report.failed.extend(pending);
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    fn signed_ballot(voter: &Keypair) -> RelayedBallot {
+        let ballot = SignedBallot {
+            election: Pubkey::new_unique(),
+            candidate: 1,
+            nonce: 2,
+        };
+        RelayedBallot {
+            voter: voter.pubkey(),
+            ballot,
+            signature: voter.sign_message(&ballot.message()).into(),
+        }
+    }
+
+    // a cluster rejecting every transaction
+    struct Congested {
+        sent: Vec<Instant>,
+    }
+
+    impl Client for Congested {
+        fn latest_blockhash(&mut self) -> Result<Hash, String> {
+            Ok(Hash::default())
+        }
+
+        fn send_transaction(
+            &mut self,
+            _transaction: &Transaction,
+        ) -> Result<Option<TransactionReturnData>, String> {
+            self.sent.push(Instant::now());
+            Err("congested".to_string())
+        }
+    }
+
+    #[test]
+    fn test_ballot_json() {
+        let relayed = signed_ballot(&Keypair::new());
+        assert_eq!(
+            RelayedBallot::from_json(&relayed.to_json()),
+            Ok(relayed.clone())
+        );
+
+        let mut forged = relayed.clone();
+        forged.ballot.candidate = 0;
+        assert_eq!(
+            RelayedBallot::from_json(&forged.to_json()),
+            Err("signature does not verify".to_string())
+        );
+
+        let json = relayed
+            .to_json()
+            .replace("\"message\":\"", "\"message\":\"00");
+        assert_eq!(
+            RelayedBallot::from_json(&json),
+            Err("invalid ballot".to_string())
+        );
+        assert!(RelayedBallot::from_json("{\"voter\": 1}").is_err());
+        assert!(RelayedBallot::from_json("ballot").is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(from_hex(&to_hex(&[0, 15, 255])), Some(vec![0, 15, 255]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("0g"), None);
+        assert_eq!(from_hex("+f"), None);
+    }
+
+    #[test]
+    fn test_ballot_quota() {
+        let allowed = signed_ballot(&Keypair::new());
+        let mut quota = BallotQuota::new(vec![allowed.ballot.election], 2);
+
+        // ballots of other elections are refused, those of an allowed one
+        // up to its quota, repeats included
+        assert_eq!(
+            quota.admit(&signed_ballot(&Keypair::new())),
+            Err(Refusal::UnknownElection)
+        );
+        assert_eq!(quota.admit(&allowed), Ok(()));
+        assert_eq!(quota.admit(&allowed), Ok(()));
+        assert_eq!(quota.admit(&allowed), Err(Refusal::QuotaExceeded));
+    }
+
+    #[test]
+    fn test_batches() {
+        let relayer = Relayer::new(
+            Congested { sent: vec![] },
+            Pubkey::new_unique(),
+            Keypair::new(),
+        );
+        let ballots: Vec<_> = (0..20).map(|_| signed_ballot(&Keypair::new())).collect();
+        let mut interleaved = vec![];
+        for index in 0..20 {
+            let mut ballot = ballots[index].clone();
+            ballot.ballot.election = ballots[index % 2].ballot.election;
+            interleaved.push(ballot);
+        }
+
+        // batches fit a transaction and keep to one election, in the order
+        // elections first appear
+        let interleaved: Vec<_> = interleaved.iter().collect();
+        let batches = relayer.batches(&interleaved);
+        assert!(batches.len() > 2);
+        let mut elections = vec![];
+        for batch in batches.iter() {
+            assert!(relayer.transaction_size(batch) <= PACKET_DATA_SIZE);
+            let election = batch[0].ballot.election;
+            assert!(batch
+                .iter()
+                .all(|ballot| ballot.ballot.election == election));
+            elections.push(election);
+        }
+        elections.dedup();
+        assert_eq!(elections.len(), 2);
+        assert_eq!(batches.concat().len(), 20);
+    }
+
+    #[test]
+    fn test_relay_retries() {
+        let relayed = signed_ballot(&Keypair::new());
+        let delay = Duration::from_millis(20);
+        let mut relayer = Relayer::new(
+            Congested { sent: vec![] },
+            Pubkey::new_unique(),
+            Keypair::new(),
+        )
+        .with_max_retries(4)
+        .with_retry_delay(delay);
+
+        // a ballot is given up on after its rounds, each retry waiting
+        // twice as long as the one before
+        let report = relayer.relay(vec![relayed.clone()]);
+        assert!(report.cast.is_empty());
+        assert_eq!(report.failed, vec![(relayed, "congested".to_string())]);
+        let sent = &relayer.client_mut().sent;
+        assert_eq!(sent.len(), 4);
+        for (round, rounds) in sent.windows(2).enumerate() {
+            assert!(rounds[1] - rounds[0] >= delay * 2u32.pow(round as u32));
+        }
+    }
+}
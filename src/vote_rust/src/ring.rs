@@ -0,0 +1,371 @@
+//! Linkable ring signatures over the keys of registered voters
+//!
+//! Ballots of an `Eligibility::AnonymousRegistry` election are signed with
+//! an LSAG signature (Liu, Wei and Wong) by a ring of registered voters,
+//! proving that one of them signed without revealing which. Ring members are
+//! the voters' ed25519 keys `P = x·G` and the signer uses the scalar `x` of
+//! its ed25519 secret key, so voters need no key besides the one they were
+//! registered with, and nothing relies on a trusted setup.
+//!
+//! Each signature carries the key image `x·Hp(scope, P)`, where `Hp` hashes
+//! to the Ristretto group. The key image of a key is the same for every
+//! signature in a scope, whatever the ring and the message, so the program
+//! stores spent key images to reject a second ballot. Ballots use the
+//! election address as scope, which keeps key images from linking a voter's
+//! ballots across elections.
+//!
+//! Verifying only takes the curve25519 syscalls, see `curve`, and SHA-256.
+//! Each ring member takes a multiscalar multiplication of two points on
+//! ed25519 and one on Ristretto, 6,122 compute units, the challenge hash and
+//! a hash to the group, which tries about four candidate points at 307 units
+//! each, so about 7,509 units per member. Each member also takes an account
+//! and 32 bytes of instruction data, which is what bounds rings to
+//! `MAX_RING_SIZE`:
+//!
+//! | ring size | syscall units | transaction bytes |
+//! |----------:|--------------:|------------------:|
+//! |         1 |         7,646 |               405 |
+//! |         2 |        15,155 |               471 |
+//! |         4 |        30,173 |               601 |
+//! |         8 |        60,209 |               861 |
+//! |        13 |        97,754 |             1,186 |
+//! |        14 |       105,263 |   1,251, too large |
+//!
+//! Units add up the syscall prices of the 1.18 runtime for the expected
+//! number of tries, the program's own instruction decoding and account
+//! handling come on top. Bytes are those of a signed `cast_ring_vote`
+//! transaction, which has to fit the 1,232 bytes of a packet, a compute
+//! budget instruction adds 40. The `ring_signature` benchmark measures both
+//! on the SBF build.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+use crate::curve::{
+    self, hash_to_scalar, ED25519_BASEPOINT, ED25519_IDENTITY, ORDER_MINUS_ONE, RISTRETTO_IDENTITY,
+};
+
+/// Most members a ring can have, the most whose keys and responses fit a
+/// transaction
+pub const MAX_RING_SIZE: usize = 13;
+
+const RING_DOMAIN: &[u8] = b"vote/ring/ring";
+const HASH_TO_POINT_DOMAIN: &[u8] = b"vote/ring/hash-to-point";
+const CHALLENGE_DOMAIN: &[u8] = b"vote/ring/challenge";
+const NONCE_DOMAIN: &[u8] = b"vote/ring/nonce";
+
+/// LSAG signature by one of the keys of a ring
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct RingSignature {
+    /// Key image of the signer, a compressed Ristretto point
+    pub key_image: [u8; 32],
+    /// Challenge of the first ring member
+    pub challenge: [u8; 32],
+    /// Response per ring member, in ring order
+    pub responses: Vec<[u8; 32]>,
+}
+
+// Hash of a ring member to the Ristretto group, no one knows its discrete
+// log. Digests are tried in turn as point encodings, with the bits no
+// canonical encoding sets cleared, and about one in four decodes.
+fn hash_to_point(scope: &[u8], key: &Pubkey) -> [u8; 32] {
+    let mut counter = 0u64;
+    loop {
+        let mut point = hashv(&[
+            HASH_TO_POINT_DOMAIN,
+            scope,
+            key.as_ref(),
+            &counter.to_le_bytes(),
+        ])
+        .to_bytes();
+        point[0] &= 0xfe;
+        point[31] &= 0x7f;
+        if curve::is_valid_ristretto(&point) {
+            return point;
+        }
+        counter += 1;
+    }
+}
+
+// Everything the challenges commit to besides the per member commitments
+fn signed_data(scope: &[u8], message: &[u8], key_image: &[u8; 32], ring: &[Pubkey]) -> [u8; 32] {
+    let mut parts = vec![RING_DOMAIN, scope, message, key_image];
+    parts.extend(ring.iter().map(|key| key.as_ref()));
+    hashv(&parts).to_bytes()
+}
+
+fn challenge(signed: &[u8; 32], left: &[u8; 32], right: &[u8; 32]) -> Scalar {
+    hash_to_scalar(&[CHALLENGE_DOMAIN, signed, left, right])
+}
+
+// `s·G + c·P` and `s·Hp(P) + c·I`, the commitments a ring member's challenge
+// and response stand for, `None` if a point or scalar is invalid
+fn commitments(
+    scope: &[u8],
+    key: &Pubkey,
+    key_image: &[u8; 32],
+    challenge: &[u8; 32],
+    response: &[u8; 32],
+) -> Option<([u8; 32], [u8; 32])> {
+    let left = curve::multiscalar_multiply_edwards(
+        &[*response, *challenge],
+        &[ED25519_BASEPOINT, key.to_bytes()],
+    )?;
+    let right = curve::multiscalar_multiply_ristretto(
+        &[*response, *challenge],
+        &[hash_to_point(scope, key), *key_image],
+    )?;
+    Some((left, right))
+}
+
+/// Checks that `key` can be a ring member, a point of the prime order
+/// subgroup other than the identity, which keys outside it could yield more
+/// than one key image. Every ed25519 key generated from a secret is one.
+pub fn is_valid_ring_key(key: &Pubkey) -> bool {
+    let key = key.to_bytes();
+    // (l - 1)·P is -P only in the prime order subgroup
+    match curve::multiply_edwards(&ORDER_MINUS_ONE, &key) {
+        Some(negated) => {
+            negated != ED25519_IDENTITY
+                && curve::add_edwards(&negated, &key) == Some(ED25519_IDENTITY)
+        }
+        None => false,
+    }
+}
+
+/// Key image of the ed25519 key with secret scalar `secret` in `scope`
+pub fn key_image(secret: &Scalar, scope: &[u8]) -> [u8; 32] {
+    let key = Pubkey::new_from_array((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes());
+    curve::multiply_ristretto(secret.as_bytes(), &hash_to_point(scope, &key))
+        .expect("hashed points are valid")
+}
+
+/// Signs `message` in `scope` as one of the members of `ring`, with the
+/// secret scalar of one of the keys. Returns `None` if the key is not in the
+/// ring or the ring holds an invalid key. Nonces are derived from the secret
+/// and the signed data.
+pub fn sign(
+    ring: &[Pubkey],
+    secret: &Scalar,
+    scope: &[u8],
+    message: &[u8],
+) -> Option<RingSignature> {
+    let public = (secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes();
+    let signer = ring.iter().position(|key| key.to_bytes() == public)?;
+    if !ring.iter().all(is_valid_ring_key) {
+        return None;
+    }
+
+    let key_image = key_image(secret, scope);
+    let signed = signed_data(scope, message, &key_image, ring);
+    let nonce = |index: usize| {
+        hash_to_scalar(&[
+            NONCE_DOMAIN,
+            secret.as_bytes(),
+            &signed,
+            &(index as u64).to_le_bytes(),
+        ])
+    };
+
+    // close the ring from the signer's commitment back around to the signer
+    let mut challenges = vec![Scalar::zero(); ring.len()];
+    let mut responses = vec![Scalar::zero(); ring.len()];
+    let alpha = nonce(signer);
+    let mut index = (signer + 1) % ring.len();
+    challenges[index] = challenge(
+        &signed,
+        &curve::multiply_edwards(alpha.as_bytes(), &ED25519_BASEPOINT)?,
+        &curve::multiply_ristretto(alpha.as_bytes(), &hash_to_point(scope, &ring[signer]))?,
+    );
+    while index != signer {
+        responses[index] = nonce(index);
+        let (left, right) = commitments(
+            scope,
+            &ring[index],
+            &key_image,
+            challenges[index].as_bytes(),
+            responses[index].as_bytes(),
+        )?;
+        let next = (index + 1) % ring.len();
+        challenges[next] = challenge(&signed, &left, &right);
+        index = next;
+    }
+    responses[signer] = alpha - challenges[signer] * secret;
+
+    Some(RingSignature {
+        key_image,
+        challenge: challenges[0].to_bytes(),
+        responses: responses.iter().map(Scalar::to_bytes).collect(),
+    })
+}
+
+impl RingSignature {
+    /// Checks that a member of `ring` signed `message` in `scope`. Ring
+    /// members must be valid ring keys, see `is_valid_ring_key`, which
+    /// registration checks once rather than every ballot.
+    pub fn verify(&self, ring: &[Pubkey], scope: &[u8], message: &[u8]) -> bool {
+        if ring.is_empty()
+            || self.responses.len() != ring.len()
+            || self.key_image == RISTRETTO_IDENTITY
+            || !curve::is_canonical_scalar(&self.challenge)
+        {
+            return false;
+        }
+        let signed = signed_data(scope, message, &self.key_image, ring);
+
+        let mut current = self.challenge;
+        for (key, response) in ring.iter().zip(&self.responses) {
+            match commitments(scope, key, &self.key_image, &current, response) {
+                Some((left, right)) => current = challenge(&signed, &left, &right).to_bytes(),
+                None => return false,
+            }
+        }
+        current == self.challenge
+    }
+}
+
+/// Secret scalar of the ed25519 key generated from `seed`, the first 32
+/// bytes of a Solana keypair
+#[cfg(any(test, feature = "prover"))]
+pub fn secret_scalar(seed: &[u8; 32]) -> Scalar {
+    use sha2::{Digest, Sha512};
+
+    let hash = Sha512::digest(seed);
+    let mut bits = [0; 32];
+    bits.copy_from_slice(&hash[..32]);
+    bits[0] &= 248;
+    bits[31] &= 127;
+    bits[31] |= 64;
+    Scalar::from_bytes_mod_order(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::{
+        constants::EIGHT_TORSION,
+        edwards::{CompressedEdwardsY, EdwardsPoint},
+    };
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn secret(seed: u8) -> Scalar {
+        secret_scalar(&[seed; 32])
+    }
+
+    fn public(secret: &Scalar) -> Pubkey {
+        Pubkey::new_from_array((secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+    }
+
+    #[test]
+    fn test_secret_scalar_matches_ed25519() {
+        let keypair = Keypair::new();
+        let secret = secret_scalar(keypair.secret().as_bytes());
+        assert_eq!(public(&secret), keypair.pubkey());
+        assert!(is_valid_ring_key(&keypair.pubkey()));
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let secrets: Vec<Scalar> = (1..=5).map(secret).collect();
+        let ring: Vec<Pubkey> = secrets.iter().map(public).collect();
+        let scope = Pubkey::new_unique();
+
+        for secret in secrets.iter() {
+            let signature = sign(&ring, secret, scope.as_ref(), &[1]).unwrap();
+            assert_eq!(signature.key_image, key_image(secret, scope.as_ref()));
+            assert!(signature.verify(&ring, scope.as_ref(), &[1]));
+            assert!(!signature.verify(&ring, scope.as_ref(), &[2]));
+            assert!(!signature.verify(&ring, Pubkey::new_unique().as_ref(), &[1]));
+
+            // the ring is signed as a whole, in order
+            let mut reordered = ring.clone();
+            reordered.swap(0, 1);
+            assert!(!signature.verify(&reordered, scope.as_ref(), &[1]));
+            assert!(!signature.verify(&ring[1..], scope.as_ref(), &[1]));
+        }
+
+        // a ring of one is an ordinary signature
+        let signature = sign(&ring[..1], &secrets[0], scope.as_ref(), &[1]).unwrap();
+        assert!(signature.verify(&ring[..1], scope.as_ref(), &[1]));
+
+        // only members can sign
+        assert!(sign(&ring, &secret(9), scope.as_ref(), &[1]).is_none());
+    }
+
+    #[test]
+    fn test_key_image_links_signatures() {
+        let secrets: Vec<Scalar> = (1..=4).map(secret).collect();
+        let ring: Vec<Pubkey> = secrets.iter().map(public).collect();
+        let scope = Pubkey::new_unique();
+
+        // the same key has the same image in any ring and for any message
+        let first = sign(&ring[..3], &secrets[0], scope.as_ref(), &[1]).unwrap();
+        let second = sign(&ring[1..], &secrets[0], scope.as_ref(), &[2]);
+        assert!(second.is_none());
+        let second = sign(&[ring[3], ring[0]], &secrets[0], scope.as_ref(), &[2]).unwrap();
+        assert_eq!(first.key_image, second.key_image);
+
+        // but not in another scope, nor for another key
+        let other_scope = sign(&ring, &secrets[0], Pubkey::new_unique().as_ref(), &[1]).unwrap();
+        assert_ne!(first.key_image, other_scope.key_image);
+        let other_key = sign(&ring, &secrets[1], scope.as_ref(), &[1]).unwrap();
+        assert_ne!(first.key_image, other_key.key_image);
+    }
+
+    #[test]
+    fn test_tampered_signatures() {
+        let secrets: Vec<Scalar> = (1..=3).map(secret).collect();
+        let ring: Vec<Pubkey> = secrets.iter().map(public).collect();
+        let scope = Pubkey::new_unique();
+        let signature = sign(&ring, &secrets[1], scope.as_ref(), &[0]).unwrap();
+
+        let mut tampered = signature.clone();
+        tampered.responses[0] = Scalar::one().to_bytes();
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+
+        let mut tampered = signature.clone();
+        tampered.challenge = Scalar::one().to_bytes();
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+
+        // non-canonical scalars are rejected rather than reduced
+        let mut tampered = signature.clone();
+        tampered.responses[2] = [0xff; 32];
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+
+        // a key image of another key doesn't close the ring
+        let mut tampered = signature.clone();
+        tampered.key_image = key_image(&secrets[0], scope.as_ref());
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+
+        let mut tampered = signature.clone();
+        tampered.key_image = [0; 32];
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+
+        let mut tampered = signature;
+        tampered.responses.pop();
+        assert!(!tampered.verify(&ring, scope.as_ref(), &[0]));
+    }
+
+    #[test]
+    fn test_ring_keys() {
+        // keys with a torsion component could sign under several key images
+        let torsion = EIGHT_TORSION[1].compress().to_bytes();
+        assert!(!is_valid_ring_key(&Pubkey::new_from_array(torsion)));
+
+        let mixed = (public(&secret(1)).to_bytes(), EIGHT_TORSION[1]);
+        let mixed = CompressedEdwardsY(mixed.0).decompress().unwrap() + mixed.1;
+        let mixed = Pubkey::new_from_array(mixed.compress().to_bytes());
+        assert!(!is_valid_ring_key(&mixed));
+
+        let identity = EdwardsPoint::default().compress().to_bytes();
+        assert!(!is_valid_ring_key(&Pubkey::new_from_array(identity)));
+
+        // and nobody signs for a ring holding one
+        let secrets: Vec<Scalar> = (1..=2).map(secret).collect();
+        let mut ring: Vec<Pubkey> = secrets.iter().map(public).collect();
+        let scope = Pubkey::new_unique();
+        ring[1] = mixed;
+        assert!(sign(&ring, &secrets[0], scope.as_ref(), &[0]).is_none());
+    }
+}
@@ -0,0 +1,260 @@
+//! Ballots signed off-chain by voters and submitted by a relayer
+//!
+//! A voter without SOL signs a `SignedBallot` with their wallet key and
+//! hands it to a relayer, which submits it with `CastSignedVote` and pays the
+//! fees and the rent of the vote receipt. The program doesn't verify the
+//! ed25519 signature itself: the transaction must also carry an instruction
+//! of the Ed25519 signature program verifying it, which the runtime checks
+//! before running the transaction, and the program finds that instruction
+//! through the Instructions sysvar.
+//!
+//! Each ballot carries a nonce above the last one the voter used in the
+//! election, so a relayer can't cast a ballot again once the voter revoked
+//! it.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    ed25519_program,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{self, instructions::load_instruction_at_checked},
+};
+
+use crate::error::VoteError;
+
+use std::convert::TryInto;
+
+// keeps a ballot signature from passing for the signature of anything else
+const BALLOT_DOMAIN: &[u8] = b"vote/signed-ballot";
+
+// layout of Ed25519 signature program instructions: a signature count and a
+// padding byte, then the offsets of each signature, public key and message
+const SIGNATURE_OFFSETS_START: usize = 2;
+const SIGNATURE_OFFSETS_LEN: usize = 14;
+const PUBLIC_KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+
+// instruction index of data in the Ed25519 instruction itself
+const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+/// Single choice ballot a voter signs off-chain
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct SignedBallot {
+    /// Election the ballot is cast in
+    pub election: Pubkey,
+    /// Index of the candidate voted for, starting at 0
+    pub candidate: u8,
+    /// Above the nonce of the voter's previous signed ballot in the election
+    pub nonce: u64,
+}
+
+impl SignedBallot {
+    /// Size of the signed message
+    pub const MESSAGE_LEN: usize = BALLOT_DOMAIN.len() + 32 + 1 + 8;
+
+    /// Message the voter signs, the domain separated Borsh encoding
+    pub fn message(&self) -> Vec<u8> {
+        let mut message = BALLOT_DOMAIN.to_vec();
+        self.serialize(&mut message).unwrap();
+        message
+    }
+
+    /// Decodes the message of a signed ballot
+    pub fn unpack_message(message: &[u8]) -> Result<Self, ProgramError> {
+        if !message.starts_with(BALLOT_DOMAIN) {
+            return Err(VoteError::InvalidInstruction.into());
+        }
+        Self::try_from_slice(&message[BALLOT_DOMAIN.len()..])
+            .map_err(|_| VoteError::InvalidInstruction.into())
+    }
+}
+
+/// Creates an Ed25519 signature program instruction verifying each
+/// signature of a message by its signer, all held in the instruction data
+pub fn new_ed25519_instruction(signatures: &[(Pubkey, [u8; 64], Vec<u8>)]) -> Instruction {
+    let mut data = vec![signatures.len() as u8, 0];
+    let mut offset = SIGNATURE_OFFSETS_START + signatures.len() * SIGNATURE_OFFSETS_LEN;
+    for (_, _, message) in signatures {
+        let public_key_offset = offset;
+        let signature_offset = public_key_offset + PUBLIC_KEY_LEN;
+        let message_offset = signature_offset + SIGNATURE_LEN;
+        offset = message_offset + message.len();
+        for field in [
+            signature_offset as u16,
+            CURRENT_INSTRUCTION,
+            public_key_offset as u16,
+            CURRENT_INSTRUCTION,
+            message_offset as u16,
+            message.len() as u16,
+            CURRENT_INSTRUCTION,
+        ]
+        .iter()
+        {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    for (signer, signature, message) in signatures {
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(signature);
+        data.extend_from_slice(message);
+    }
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+/// Returns the signer and message of each signature an Ed25519 signature
+/// program instruction verifies, `None` if it isn't one or some signature
+/// is verified against data of another instruction
+pub fn ed25519_signatures(instruction: &Instruction) -> Option<Vec<(Pubkey, &[u8])>> {
+    if instruction.program_id != ed25519_program::id() {
+        return None;
+    }
+    let data = &instruction.data[..];
+    let count = *data.first()? as usize;
+
+    (0..count)
+        .map(|index| {
+            let start = SIGNATURE_OFFSETS_START + index * SIGNATURE_OFFSETS_LEN;
+            let offsets = data.get(start..start + SIGNATURE_OFFSETS_LEN)?;
+            let field =
+                |index: usize| u16::from_le_bytes([offsets[2 * index], offsets[2 * index + 1]]);
+            // where the signature itself comes from doesn't matter, the
+            // runtime verified it against this key and message
+            if field(3) != CURRENT_INSTRUCTION || field(6) != CURRENT_INSTRUCTION {
+                return None;
+            }
+            let public_key = data.get(field(2) as usize..)?.get(..PUBLIC_KEY_LEN)?;
+            let message = data.get(field(4) as usize..)?.get(..field(5) as usize)?;
+            Some((Pubkey::new_from_array(public_key.try_into().ok()?), message))
+        })
+        .collect()
+}
+
+/// Checks that an Ed25519 signature program instruction of the transaction,
+/// read from the Instructions sysvar account, verifies a signature of
+/// `message` by `signer`
+pub fn check_ed25519_signature(
+    instructions_info: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> ProgramResult {
+    if !sysvar::instructions::check_id(instructions_info.key) {
+        msg!("Expected the Instructions sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_info) {
+        let signatures = ed25519_signatures(&instruction).unwrap_or_default();
+        if signatures
+            .iter()
+            .any(|(key, signed)| key == signer && *signed == message)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+
+    msg!(
+        "No Ed25519 instruction verifies the signature of {}",
+        signer
+    );
+    Err(VoteError::MissingBallotSignature.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::{
+        ed25519_instruction::verify,
+        feature_set::FeatureSet,
+        signature::{Keypair, Signer},
+    };
+
+    fn sign(keypair: &Keypair, message: Vec<u8>) -> (Pubkey, [u8; 64], Vec<u8>) {
+        let signature = keypair.sign_message(&message).into();
+        (keypair.pubkey(), signature, message)
+    }
+
+    #[test]
+    fn test_ballot_message() {
+        let ballot = SignedBallot {
+            election: Pubkey::new_unique(),
+            candidate: 2,
+            nonce: 7,
+        };
+        let message = ballot.message();
+        assert_eq!(message.len(), SignedBallot::MESSAGE_LEN);
+        assert_eq!(SignedBallot::unpack_message(&message), Ok(ballot));
+
+        assert!(SignedBallot::unpack_message(&message[1..]).is_err());
+        assert!(SignedBallot::unpack_message(&message[..message.len() - 1]).is_err());
+        assert!(SignedBallot::unpack_message(&[message.clone(), vec![0]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_ed25519_instruction() {
+        let voters = [Keypair::new(), Keypair::new()];
+        let signatures: Vec<_> = voters
+            .iter()
+            .enumerate()
+            .map(|(candidate, voter)| {
+                let ballot = SignedBallot {
+                    election: Pubkey::new_unique(),
+                    candidate: candidate as u8,
+                    nonce: 1,
+                };
+                sign(voter, ballot.message())
+            })
+            .collect();
+
+        // the runtime verifies the instruction and the program reads back
+        // what was verified
+
+        let instruction = new_ed25519_instruction(&signatures);
+        verify(
+            &instruction.data,
+            &[&instruction.data],
+            &FeatureSet::all_enabled(),
+        )
+        .unwrap();
+        let verified = ed25519_signatures(&instruction).unwrap();
+        assert_eq!(verified.len(), 2);
+        for ((signer, _, message), verified) in signatures.iter().zip(verified) {
+            assert_eq!((*signer, &message[..]), verified);
+        }
+
+        let mut forged = signatures[0].clone();
+        forged.2 = signatures[1].2.clone();
+        let instruction = new_ed25519_instruction(&[forged]);
+        assert!(verify(
+            &instruction.data,
+            &[&instruction.data],
+            &FeatureSet::all_enabled()
+        )
+        .is_err());
+
+        // keys and messages must be read from the instruction itself
+
+        let mut instruction = new_ed25519_instruction(&signatures[..1]);
+        instruction.data[SIGNATURE_OFFSETS_START + 6] = 0;
+        instruction.data[SIGNATURE_OFFSETS_START + 7] = 0;
+        assert_eq!(ed25519_signatures(&instruction), None);
+
+        let mut instruction = new_ed25519_instruction(&signatures[..1]);
+        instruction.data.pop();
+        assert_eq!(ed25519_signatures(&instruction), None);
+
+        let mut instruction = new_ed25519_instruction(&signatures[..1]);
+        instruction.program_id = Pubkey::new_unique();
+        assert_eq!(ed25519_signatures(&instruction), None);
+    }
+}
@@ -0,0 +1,1304 @@
+//! State transition types
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    clock::UnixTimestamp, hash::hashv, program_error::ProgramError, program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+use crate::{anonymous::VERIFYING_KEY_LEN, elgamal::Ciphertext, error::VoteError};
+
+use std::convert::TryInto;
+
+/// Tag stored in the first bytes of every election account
+pub const ELECTION_DISCRIMINATOR: [u8; 8] = *b"election";
+
+/// Current layout version of `ElectionState`
+pub const ELECTION_STATE_VERSION: u8 = 1;
+
+/// Fewest candidates an election can be created with
+pub const MIN_CANDIDATES: u8 = 2;
+
+/// Most candidates an election can be created with
+pub const MAX_CANDIDATES: u8 = 64;
+
+/// Size of the original headerless vote account, two little endian u32 counts
+pub const LEGACY_ELECTION_LEN: usize = 8;
+
+/// Tag stored in the first bytes of every vote receipt account
+pub const RECEIPT_DISCRIMINATOR: [u8; 8] = *b"receipt_";
+
+/// Current layout version of `VoteReceipt`
+pub const RECEIPT_STATE_VERSION: u8 = 1;
+
+/// Seed prefix of vote receipt addresses
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// Tag stored in the first bytes of every voter record account
+pub const VOTER_RECORD_DISCRIMINATOR: [u8; 8] = *b"voter___";
+
+/// Current layout version of `VoterRecord`
+pub const VOTER_RECORD_STATE_VERSION: u8 = 1;
+
+/// Seed prefix of voter record addresses
+pub const VOTER_RECORD_SEED: &[u8] = b"voter";
+
+/// Tag stored in the first bytes of every nullifier record account
+pub const NULLIFIER_DISCRIMINATOR: [u8; 8] = *b"nullifr_";
+
+/// Current layout version of `NullifierRecord`
+pub const NULLIFIER_STATE_VERSION: u8 = 1;
+
+/// Seed prefix of nullifier record addresses
+pub const NULLIFIER_SEED: &[u8] = b"nullifier";
+
+/// Tag stored in the first bytes of every nonce record account
+pub const NONCE_DISCRIMINATOR: [u8; 8] = *b"nonce___";
+
+/// Current layout version of `NonceRecord`
+pub const NONCE_STATE_VERSION: u8 = 1;
+
+/// Seed prefix of nonce record addresses
+pub const NONCE_SEED: &[u8] = b"nonce";
+
+/// Seed prefix of the token accounts escrowing the balance of token
+/// weighted voters
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Who may vote in an election
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum Eligibility {
+    /// Anyone with a keypair
+    Open,
+    /// Only voters the authority registered with `RegisterVoter`
+    Registry,
+    /// Only voters proving membership in the Merkle tree with this root,
+    /// see `merkle`
+    MerkleRoot([u8; 32]),
+    /// Holders of this SPL Token mint, weighted by the balance they lock in
+    /// escrow when voting
+    TokenBalance(Pubkey),
+    /// Members of the group with this root, voting anonymously with
+    /// `CastAnonymousVote`, see `anonymous`. Plurality elections only.
+    AnonymousGroup([u8; 32]),
+    /// Only voters the authority registered with `RegisterVoter`, voting
+    /// anonymously with `CastRingVote` signed by a ring of registered
+    /// voters, see `ring`. Plurality elections only.
+    AnonymousRegistry,
+}
+
+impl Eligibility {
+    /// Largest serialized size of any variant
+    pub const MAX_LEN: usize = 1 + 32;
+}
+
+/// How ballots are cast and counted
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum VotingMethod {
+    /// Each ballot adds the voter's weight to a single candidate
+    Plurality,
+    /// Voters spread votes over candidates, `k` votes on a candidate cost
+    /// `k²` voice credits. Not available with `Eligibility::MerkleRoot`.
+    Quadratic {
+        /// Voice credits granted per unit of voter weight
+        credits: u64,
+    },
+    /// Voters rank candidates and the winner is found by instant runoff
+    /// with `Tabulate`, the tallies hold the first preferences. Not
+    /// available with `Eligibility::MerkleRoot`.
+    RankedChoice,
+    /// Each ballot adds the voter's weight to every candidate it approves.
+    /// Not available with `Eligibility::MerkleRoot`.
+    Approval,
+    /// Secret plurality ballots. Until `end_time` voters only commit to
+    /// `vote_commitment(candidate, salt)`, then reveal the candidate and salt
+    /// until `reveal_end_time`, and only revealed ballots are counted. Not
+    /// available with `Eligibility::MerkleRoot`.
+    CommitReveal {
+        /// Unix timestamp the reveal phase closes at, after `end_time`
+        reveal_end_time: UnixTimestamp,
+    },
+    /// Plurality ballots encrypted to a trustee, see `elgamal`. Each ballot
+    /// encrypts 1 for the chosen candidate and 0 for the others and is
+    /// summed into the encrypted tallies, until the trustee publishes their
+    /// proven decryption. Only available with `Eligibility::Open`, so no
+    /// tally exceeds the number of voters and the trustee can decrypt it by
+    /// search.
+    Encrypted {
+        /// Signer allowed to publish the decrypted tallies
+        trustee: Pubkey,
+        /// ElGamal public key of the trustee, a compressed Ristretto point
+        public_key: [u8; 32],
+    },
+}
+
+impl VotingMethod {
+    /// Largest serialized size of any variant
+    pub const MAX_LEN: usize = 1 + 32 + 32;
+}
+
+/// Progress of the instant runoff count of a ranked choice election.
+///
+/// Round 1 is taken from the first preference tallies. While no candidate
+/// holds a majority of the ballots still ranking a continuing candidate, the
+/// weakest candidate is eliminated and every ballot is counted again, in
+/// batches, towards its highest ranked continuing candidate.
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct Tabulation {
+    /// Number of ranked ballots cast
+    pub ballot_count: u64,
+    /// Number of ballots counted in the current round
+    pub ballots_counted: u64,
+    /// Bit `i` is set once candidate `i` is eliminated
+    pub eliminated: u64,
+    /// The winner, once the count completed
+    pub winner: Option<u8>,
+    /// Counts per candidate of each round, the last one may be in progress
+    pub rounds: Vec<Vec<u64>>,
+}
+
+impl Tabulation {
+    /// Size of the fixed length fields and of the round vector prefix
+    const EMPTY_LEN: usize = 8 + 8 + 8 + 2 + 4;
+
+    fn is_eliminated(&self, candidate: u8) -> bool {
+        self.eliminated & (1 << candidate) != 0
+    }
+}
+
+/// Election data, Borsh encoded at the start of the election account
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ElectionState {
+    /// Always `ELECTION_DISCRIMINATOR` once initialized
+    pub discriminator: [u8; 8],
+    /// Layout version the account was written with
+    pub version: u8,
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Authority allowed to manage the election
+    pub authority: Pubkey,
+    /// Hash of the off-chain title and description
+    pub metadata_hash: [u8; 32],
+    /// Unix timestamp voting opens at
+    pub start_time: UnixTimestamp,
+    /// Unix timestamp voting closes at
+    pub end_time: UnixTimestamp,
+    /// Is `true` once the authority froze the tallies
+    pub is_finalized: bool,
+    /// Who may vote
+    pub eligibility: Eligibility,
+    /// How ballots are cast and counted
+    pub voting_method: VotingMethod,
+    /// Number of candidates on the ballot
+    pub candidate_count: u8,
+    /// Hash of each candidate label, indexed by candidate
+    pub candidate_labels: Vec<[u8; 32]>,
+    /// Vote count per candidate, indexed by candidate
+    pub tallies: Vec<u64>,
+    /// Committed ballots not revealed yet, only used by commit-reveal
+    /// elections
+    pub unrevealed_commits: u64,
+    /// Encrypted vote count per candidate, only used by encrypted elections
+    pub encrypted_tallies: Vec<Ciphertext>,
+    /// Groth16 verifying key of the membership circuit, only used by
+    /// anonymous elections
+    pub verifying_key: Vec<u8>,
+    /// Instant runoff count, only used by ranked choice elections
+    pub tabulation: Tabulation,
+}
+
+impl ElectionState {
+    /// Creates a freshly initialized election without metadata, open for
+    /// voting at any time
+    pub fn new(authority: Pubkey, candidate_count: u8) -> Self {
+        Self {
+            discriminator: ELECTION_DISCRIMINATOR,
+            version: ELECTION_STATE_VERSION,
+            is_initialized: true,
+            authority,
+            metadata_hash: [0; 32],
+            start_time: 0,
+            end_time: UnixTimestamp::MAX,
+            is_finalized: false,
+            eligibility: Eligibility::Open,
+            voting_method: VotingMethod::Plurality,
+            candidate_count,
+            candidate_labels: vec![[0; 32]; candidate_count as usize],
+            tallies: vec![0; candidate_count as usize],
+            unrevealed_commits: 0,
+            encrypted_tallies: vec![],
+            verifying_key: vec![],
+            tabulation: Tabulation::default(),
+        }
+    }
+
+    /// Size of the fixed length fields
+    const FIXED_LEN: usize =
+        8 + 1 + 1 + 32 + 32 + 8 + 8 + 1 + Eligibility::MAX_LEN + VotingMethod::MAX_LEN + 1 + 8;
+
+    /// Account size needed to hold an election with `candidate_count` candidates
+    pub fn space(candidate_count: u8) -> usize {
+        let candidates = candidate_count as usize;
+        // the vectors are prefixed by their u32 length
+        Self::FIXED_LEN
+            + (4 + 32 * candidates)
+            + (4 + 8 * candidates)
+            + 4
+            + 4
+            + Tabulation::EMPTY_LEN
+    }
+
+    /// Account size needed to hold a ranked choice election with
+    /// `candidate_count` candidates and the counts of all its runoff rounds
+    pub fn ranked_space(candidate_count: u8) -> usize {
+        let candidates = candidate_count as usize;
+        // each round eliminates a candidate, the last one standing wins
+        // without another round
+        let rounds = candidates.saturating_sub(1);
+        Self::space(candidate_count) + rounds * (4 + 8 * candidates)
+    }
+
+    /// Account size needed to hold an encrypted election with
+    /// `candidate_count` candidates
+    pub fn encrypted_space(candidate_count: u8) -> usize {
+        Self::space(candidate_count) + Ciphertext::LEN * candidate_count as usize
+    }
+
+    /// Account size needed to hold an anonymous election with
+    /// `candidate_count` candidates and its verifying key
+    pub fn anonymous_space(candidate_count: u8) -> usize {
+        Self::space(candidate_count) + VERIFYING_KEY_LEN
+    }
+
+    /// Account size needed to hold this election
+    pub fn required_space(&self) -> usize {
+        match (&self.voting_method, &self.eligibility) {
+            (VotingMethod::RankedChoice, _) => Self::ranked_space(self.candidate_count),
+            (VotingMethod::Encrypted { .. }, _) => Self::encrypted_space(self.candidate_count),
+            (_, Eligibility::AnonymousGroup(_)) => Self::anonymous_space(self.candidate_count),
+            _ => Self::space(self.candidate_count),
+        }
+    }
+
+    /// Builds a two candidate election from the data of a legacy vote account
+    pub fn from_legacy(authority: Pubkey, input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != LEGACY_ELECTION_LEN {
+            return Err(VoteError::InvalidElectionAccount.into());
+        }
+
+        let mut state = Self::new(authority, 2);
+        for (tally, count) in state.tallies.iter_mut().zip(input.chunks_exact(4)) {
+            *tally = u32::from_le_bytes(count.try_into().unwrap()) as u64;
+        }
+        Ok(state)
+    }
+
+    /// Checks that the election is not finalized and that `now` falls within
+    /// the voting window, start inclusive and end exclusive
+    pub fn check_voting_open(&self, now: UnixTimestamp) -> Result<(), ProgramError> {
+        if self.is_finalized {
+            return Err(VoteError::ElectionFinalized.into());
+        }
+        if now < self.start_time {
+            return Err(VoteError::VotingNotOpen.into());
+        }
+        if now >= self.end_time {
+            return Err(VoteError::ElectionClosed.into());
+        }
+        Ok(())
+    }
+
+    /// Checks that the election is not finalized and that `now` falls within
+    /// the reveal phase of a commit-reveal election, from the end of voting
+    /// inclusive to `reveal_end_time` exclusive
+    pub fn check_reveal_open(&self, now: UnixTimestamp) -> Result<(), ProgramError> {
+        let reveal_end_time = match self.voting_method {
+            VotingMethod::CommitReveal { reveal_end_time } => reveal_end_time,
+            _ => return Err(VoteError::WrongVotingMethod.into()),
+        };
+        if self.is_finalized {
+            return Err(VoteError::ElectionFinalized.into());
+        }
+        if now < self.end_time {
+            return Err(VoteError::VotingInProgress.into());
+        }
+        if now >= reveal_end_time {
+            return Err(VoteError::ElectionClosed.into());
+        }
+        Ok(())
+    }
+
+    /// Adds `weight` votes to a candidate, failing instead of wrapping
+    pub fn add_votes(&mut self, candidate: u8, weight: u64) -> Result<(), ProgramError> {
+        let tally = self
+            .tallies
+            .get_mut(candidate as usize)
+            .ok_or(VoteError::InvalidCandidate)?;
+        *tally = tally.checked_add(weight).ok_or(VoteError::TallyOverflow)?;
+        Ok(())
+    }
+
+    /// Takes `weight` votes back from a candidate, failing instead of wrapping
+    pub fn remove_votes(&mut self, candidate: u8, weight: u64) -> Result<(), ProgramError> {
+        let tally = self
+            .tallies
+            .get_mut(candidate as usize)
+            .ok_or(VoteError::InvalidCandidate)?;
+        *tally = tally.checked_sub(weight).ok_or(VoteError::TallyUnderflow)?;
+        Ok(())
+    }
+
+    /// Takes back everything the ballot recorded in `receipt` added to the
+    /// tallies
+    pub fn remove_ballot(&mut self, receipt: &VoteReceipt) -> Result<(), ProgramError> {
+        match self.voting_method {
+            VotingMethod::Plurality => self.remove_votes(receipt.candidate, receipt.weight),
+            VotingMethod::Quadratic { .. } => {
+                for (candidate, votes) in receipt.votes.iter().enumerate() {
+                    self.remove_votes(candidate as u8, *votes as u64)?;
+                }
+                Ok(())
+            }
+            VotingMethod::RankedChoice => {
+                if let Some(first) = receipt.ranking.first() {
+                    self.tabulation.ballot_count = self
+                        .tabulation
+                        .ballot_count
+                        .checked_sub(1)
+                        .ok_or(VoteError::TallyUnderflow)?;
+                    self.remove_votes(*first, receipt.weight)?;
+                }
+                Ok(())
+            }
+            VotingMethod::Approval => {
+                for candidate in 0..self.candidate_count {
+                    if receipt.approvals & (1 << candidate) != 0 {
+                        self.remove_votes(candidate, receipt.weight)?;
+                    }
+                }
+                Ok(())
+            }
+            VotingMethod::CommitReveal { .. } => {
+                if receipt.is_revealed {
+                    self.remove_votes(receipt.candidate, receipt.weight)
+                } else {
+                    self.unrevealed_commits = self
+                        .unrevealed_commits
+                        .checked_sub(1)
+                        .ok_or(VoteError::TallyUnderflow)?;
+                    Ok(())
+                }
+            }
+            // nobody but the voter knows what the ballot added
+            VotingMethod::Encrypted { .. } => Err(VoteError::WrongVotingMethod.into()),
+        }
+    }
+
+    /// Opens round 1 of the instant runoff count with the first preference
+    /// tallies, then resolves it
+    pub fn start_tabulation(&mut self) {
+        let tabulation = &mut self.tabulation;
+        tabulation.rounds = vec![self.tallies.clone()];
+        tabulation.ballots_counted = tabulation.ballot_count;
+        self.advance_tabulation();
+    }
+
+    /// Resolves the current round once every ballot was counted in it,
+    /// either electing the majority winner or eliminating the candidate with
+    /// the fewest votes, the later candidate on ties, and opening the next
+    /// round. Repeats while rounds complete without counting any ballots.
+    pub fn advance_tabulation(&mut self) {
+        let candidate_count = self.candidate_count;
+        let tabulation = &mut self.tabulation;
+
+        while tabulation.winner.is_none()
+            && !tabulation.rounds.is_empty()
+            && tabulation.ballots_counted == tabulation.ballot_count
+        {
+            let counts = tabulation.rounds.last().unwrap();
+            let continuing: Vec<u8> = (0..candidate_count)
+                .filter(|candidate| !tabulation.is_eliminated(*candidate))
+                .collect();
+            let total: u128 = continuing
+                .iter()
+                .map(|candidate| counts[*candidate as usize] as u128)
+                .sum();
+
+            // earlier candidates win ties for the lead
+            let leader = *continuing
+                .iter()
+                .rev()
+                .max_by_key(|candidate| counts[**candidate as usize])
+                .unwrap();
+            if continuing.len() == 1 || counts[leader as usize] as u128 * 2 > total {
+                tabulation.winner = Some(leader);
+                break;
+            }
+
+            // later candidates lose ties for the last place
+            let loser = *continuing
+                .iter()
+                .rev()
+                .min_by_key(|candidate| counts[**candidate as usize])
+                .unwrap();
+            tabulation.eliminated |= 1 << loser;
+
+            if continuing.len() == 2 {
+                tabulation.winner = continuing.into_iter().find(|candidate| *candidate != loser);
+                break;
+            }
+            tabulation.rounds.push(vec![0; candidate_count as usize]);
+            tabulation.ballots_counted = 0;
+        }
+    }
+
+    /// Counts a ranked ballot towards its highest ranked continuing
+    /// candidate in the current round, returns `false` without changes if
+    /// the ballot was already counted in it or no round is in progress
+    pub fn count_ranked_ballot(&mut self, receipt: &mut VoteReceipt) -> Result<bool, ProgramError> {
+        let tabulation = &mut self.tabulation;
+        let round = tabulation.rounds.len();
+        if tabulation.winner.is_some() || round < 2 || receipt.tabulated_round as usize >= round {
+            return Ok(false);
+        }
+
+        // exhausted ballots count towards nobody
+        let choice = receipt
+            .ranking
+            .iter()
+            .find(|candidate| !tabulation.is_eliminated(**candidate));
+        if let Some(candidate) = choice {
+            let count = tabulation
+                .rounds
+                .last_mut()
+                .unwrap()
+                .get_mut(*candidate as usize)
+                .ok_or(VoteError::InvalidCandidate)?;
+            *count = count
+                .checked_add(receipt.weight)
+                .ok_or(VoteError::TallyOverflow)?;
+        }
+
+        receipt.tabulated_round = round as u8;
+        tabulation.ballots_counted += 1;
+        Ok(true)
+    }
+
+    /// Deserializes the account data without checking that it holds an
+    /// initialized election, trailing bytes are ignored
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &input[..]).map_err(|_| VoteError::InvalidElectionAccount.into())
+    }
+
+    /// Deserializes the account data, checking the discriminator, layout
+    /// version and initialized flag
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let state = Self::unpack_unchecked(input)?;
+
+        if !state.is_initialized() {
+            return Err(VoteError::ElectionNotInitialized.into());
+        }
+        if state.discriminator != ELECTION_DISCRIMINATOR {
+            return Err(VoteError::InvalidElectionAccount.into());
+        }
+
+        match state.version {
+            ELECTION_STATE_VERSION => Ok(state),
+            // older layouts get migrated here as the format evolves
+            _ => Err(VoteError::InvalidElectionAccount.into()),
+        }
+    }
+
+    /// Serializes the election into the start of the account data
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        self.serialize(&mut &mut dst[..])
+            .map_err(|_| VoteError::InvalidElectionAccount.into())
+    }
+}
+
+impl IsInitialized for ElectionState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Proof that a voter cast a ballot, stored at a program derived address
+/// seeded by the election and the voter
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct VoteReceipt {
+    /// Always `RECEIPT_DISCRIMINATOR` once initialized
+    pub discriminator: [u8; 8],
+    /// Layout version the account was written with
+    pub version: u8,
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Election the vote was cast in
+    pub election: Pubkey,
+    /// Voter who cast the vote
+    pub voter: Pubkey,
+    /// Candidate voted for
+    pub candidate: u8,
+    /// Weight added to the candidate's tally
+    pub weight: u64,
+    /// Bump seed of the receipt address
+    pub bump_seed: u8,
+    /// Voice credits left to spend in a quadratic election
+    pub credits_remaining: u64,
+    /// Quadratic votes per candidate, empty in other elections
+    pub votes: Vec<u32>,
+    /// Candidates in order of preference, empty outside ranked choice
+    /// elections
+    pub ranking: Vec<u8>,
+    /// Last instant runoff round the ranking was counted in
+    pub tabulated_round: u8,
+    /// Bit `i` is set if candidate `i` is approved in an approval election
+    pub approvals: u64,
+    /// Commitment to the hidden ballot in a commit-reveal election, see
+    /// `vote_commitment`
+    pub commitment: [u8; 32],
+    /// Is `true` once the committed ballot was revealed and counted
+    pub is_revealed: bool,
+}
+
+impl VoteReceipt {
+    /// Account size of a vote receipt without quadratic votes or ranking
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 32 + 1 + 8 + 1 + 8 + 4 + 4 + 1 + 8 + 32 + 1;
+
+    /// Account size of a vote receipt in a quadratic election with
+    /// `candidate_count` candidates
+    pub fn quadratic_space(candidate_count: u8) -> usize {
+        Self::LEN + 4 * candidate_count as usize
+    }
+
+    /// Account size of a vote receipt in a ranked choice election with
+    /// `candidate_count` candidates
+    pub fn ranked_space(candidate_count: u8) -> usize {
+        Self::LEN + candidate_count as usize
+    }
+
+    /// Creates a receipt for a vote just cast
+    pub fn new(election: Pubkey, voter: Pubkey, candidate: u8, weight: u64, bump_seed: u8) -> Self {
+        Self {
+            discriminator: RECEIPT_DISCRIMINATOR,
+            version: RECEIPT_STATE_VERSION,
+            is_initialized: true,
+            election,
+            voter,
+            candidate,
+            weight,
+            bump_seed,
+            credits_remaining: 0,
+            votes: vec![],
+            ranking: vec![],
+            tabulated_round: 0,
+            approvals: 0,
+            commitment: [0; 32],
+            is_revealed: false,
+        }
+    }
+
+    /// Creates the receipt of a quadratic voter who has yet to spend any of
+    /// its `credits`
+    pub fn new_quadratic(
+        election: Pubkey,
+        voter: Pubkey,
+        weight: u64,
+        bump_seed: u8,
+        credits: u64,
+        candidate_count: u8,
+    ) -> Self {
+        Self {
+            credits_remaining: credits,
+            votes: vec![0; candidate_count as usize],
+            ..Self::new(election, voter, 0, weight, bump_seed)
+        }
+    }
+
+    /// Adds `votes` quadratic votes for a candidate, paying the difference
+    /// between the squares of its new and old vote count out of the
+    /// remaining credits
+    pub fn add_quadratic_votes(&mut self, candidate: u8, votes: u32) -> Result<(), ProgramError> {
+        let current = self
+            .votes
+            .get_mut(candidate as usize)
+            .ok_or(VoteError::InvalidCandidate)?;
+        let total = current
+            .checked_add(votes)
+            .ok_or(VoteError::InsufficientCredits)?;
+
+        let (old, new) = (*current as u64, total as u64);
+        let cost = new * new - old * old;
+        self.credits_remaining = self
+            .credits_remaining
+            .checked_sub(cost)
+            .ok_or(VoteError::InsufficientCredits)?;
+        *current = total;
+        Ok(())
+    }
+
+    /// Deserializes the account data without checking that it holds an
+    /// initialized receipt
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &input[..]).map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+
+    /// Deserializes the account data, checking the discriminator, layout
+    /// version and initialized flag
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let receipt = Self::unpack_unchecked(input)?;
+        if !receipt.is_initialized()
+            || receipt.discriminator != RECEIPT_DISCRIMINATOR
+            || receipt.version != RECEIPT_STATE_VERSION
+        {
+            return Err(VoteError::InvalidReceiptAccount.into());
+        }
+        Ok(receipt)
+    }
+
+    /// Serializes the receipt into the start of the account data
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        self.serialize(&mut &mut dst[..])
+            .map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+}
+
+impl IsInitialized for VoteReceipt {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Registration of an eligible voter, stored at a program derived address
+/// seeded by the election and the voter
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct VoterRecord {
+    /// Always `VOTER_RECORD_DISCRIMINATOR` once initialized
+    pub discriminator: [u8; 8],
+    /// Layout version the account was written with
+    pub version: u8,
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Election the voter is registered in
+    pub election: Pubkey,
+    /// The registered voter
+    pub voter: Pubkey,
+    /// Weight of the voter's ballot, e.g. its shares in a co-op
+    pub weight: u64,
+    /// Bump seed of the voter record address
+    pub bump_seed: u8,
+}
+
+impl VoterRecord {
+    /// Account size of a voter record
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 32 + 8 + 1;
+
+    /// Creates the record of a newly registered voter
+    pub fn new(election: Pubkey, voter: Pubkey, weight: u64, bump_seed: u8) -> Self {
+        Self {
+            discriminator: VOTER_RECORD_DISCRIMINATOR,
+            version: VOTER_RECORD_STATE_VERSION,
+            is_initialized: true,
+            election,
+            voter,
+            weight,
+            bump_seed,
+        }
+    }
+
+    /// Deserializes the account data without checking that it holds an
+    /// initialized voter record
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &input[..]).map_err(|_| VoteError::NotEligible.into())
+    }
+
+    /// Deserializes the account data, checking the discriminator, layout
+    /// version and initialized flag
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let record = Self::unpack_unchecked(input)?;
+        if !record.is_initialized()
+            || record.discriminator != VOTER_RECORD_DISCRIMINATOR
+            || record.version != VOTER_RECORD_STATE_VERSION
+        {
+            return Err(VoteError::NotEligible.into());
+        }
+        Ok(record)
+    }
+
+    /// Serializes the record into the start of the account data
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        self.serialize(&mut &mut dst[..])
+            .map_err(|_| VoteError::NotEligible.into())
+    }
+}
+
+impl IsInitialized for VoterRecord {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Spent nullifier of an anonymous ballot, the nullifier hash of a
+/// membership proof or the key image of a ring signature, stored at a
+/// program derived address seeded by the election and the nullifier
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct NullifierRecord {
+    /// Always `NULLIFIER_DISCRIMINATOR` once initialized
+    pub discriminator: [u8; 8],
+    /// Layout version the account was written with
+    pub version: u8,
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Election the ballot was cast in
+    pub election: Pubkey,
+    /// Nullifier hash of the ballot, see `anonymous`, or key image, see
+    /// `ring`
+    pub nullifier_hash: [u8; 32],
+    /// Candidate voted for
+    pub candidate: u8,
+    /// Bump seed of the nullifier record address
+    pub bump_seed: u8,
+}
+
+impl NullifierRecord {
+    /// Account size of a nullifier record
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 32 + 1 + 1;
+
+    /// Creates the record of a nullifier hash just spent
+    pub fn new(election: Pubkey, nullifier_hash: [u8; 32], candidate: u8, bump_seed: u8) -> Self {
+        Self {
+            discriminator: NULLIFIER_DISCRIMINATOR,
+            version: NULLIFIER_STATE_VERSION,
+            is_initialized: true,
+            election,
+            nullifier_hash,
+            candidate,
+            bump_seed,
+        }
+    }
+
+    /// Deserializes the account data without checking that it holds an
+    /// initialized nullifier record
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &input[..]).map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+
+    /// Serializes the record into the start of the account data
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        self.serialize(&mut &mut dst[..])
+            .map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+}
+
+impl IsInitialized for NullifierRecord {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Last nonce of the signed ballots a voter cast in an election, stored at
+/// a program derived address seeded by the election and the voter. The
+/// record outlives the vote receipt, so a revoked ballot can't be cast again.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct NonceRecord {
+    /// Always `NONCE_DISCRIMINATOR` once initialized
+    pub discriminator: [u8; 8],
+    /// Layout version the account was written with
+    pub version: u8,
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Election the ballots were cast in
+    pub election: Pubkey,
+    /// Voter who signed the ballots
+    pub voter: Pubkey,
+    /// Nonce of the last signed ballot, the next one must be greater
+    pub nonce: u64,
+    /// Bump seed of the nonce record address
+    pub bump_seed: u8,
+}
+
+impl NonceRecord {
+    /// Account size of a nonce record
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 32 + 8 + 1;
+
+    /// Creates the record of a voter's first signed ballot
+    pub fn new(election: Pubkey, voter: Pubkey, nonce: u64, bump_seed: u8) -> Self {
+        Self {
+            discriminator: NONCE_DISCRIMINATOR,
+            version: NONCE_STATE_VERSION,
+            is_initialized: true,
+            election,
+            voter,
+            nonce,
+            bump_seed,
+        }
+    }
+
+    /// Deserializes the account data without checking that it holds an
+    /// initialized nonce record
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &input[..]).map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+
+    /// Serializes the record into the start of the account data
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        self.serialize(&mut &mut dst[..])
+            .map_err(|_| VoteError::InvalidReceiptAccount.into())
+    }
+}
+
+impl IsInitialized for NonceRecord {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Hash a commit-reveal ballot commits to, the salt keeps the choice from
+/// being guessed by hashing every candidate
+pub fn vote_commitment(candidate: u8, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&[candidate], salt]).to_bytes()
+}
+
+/// Finds the vote receipt address of a voter in an election
+pub fn find_receipt_address(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RECEIPT_SEED, election.as_ref(), voter.as_ref()],
+        program_id,
+    )
+}
+
+/// Finds the address of the token account escrowing the balance a voter
+/// voted with, the account is its own token owner
+pub fn find_escrow_address(program_id: &Pubkey, election: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ESCROW_SEED, election.as_ref(), voter.as_ref()],
+        program_id,
+    )
+}
+
+/// Finds the nullifier record address of a nullifier hash in an election
+pub fn find_nullifier_address(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    nullifier_hash: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[NULLIFIER_SEED, election.as_ref(), nullifier_hash],
+        program_id,
+    )
+}
+
+/// Finds the nonce record address of a voter in an election
+pub fn find_nonce_address(program_id: &Pubkey, election: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NONCE_SEED, election.as_ref(), voter.as_ref()], program_id)
+}
+
+/// Finds the voter record address of a voter in an election
+pub fn find_voter_record_address(
+    program_id: &Pubkey,
+    election: &Pubkey,
+    voter: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VOTER_RECORD_SEED, election.as_ref(), voter.as_ref()],
+        program_id,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pack_unpack() {
+        for candidate_count in [MIN_CANDIDATES, 7, MAX_CANDIDATES].iter() {
+            let mut state = ElectionState::new(Pubkey::new_unique(), *candidate_count);
+            // the largest variants fill the whole account
+            state.eligibility = Eligibility::MerkleRoot([7; 32]);
+            state.voting_method = VotingMethod::Encrypted {
+                trustee: Pubkey::new_unique(),
+                public_key: [3; 32],
+            };
+            state.encrypted_tallies = vec![Ciphertext::default(); *candidate_count as usize];
+            state.tabulation.winner = Some(0);
+
+            let mut data = vec![0; ElectionState::encrypted_space(*candidate_count)];
+            state.pack(&mut data).unwrap();
+            assert_eq!(&data[0..8], b"election");
+            assert_eq!(ElectionState::unpack(&data).unwrap(), state);
+
+            // a buffer too small for the tallies is rejected
+            let mut data = vec![0; ElectionState::encrypted_space(*candidate_count) - 1];
+            assert_eq!(
+                state.pack(&mut data),
+                Err(VoteError::InvalidElectionAccount.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove_ballot() {
+        let election = Pubkey::new_unique();
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.tallies = vec![10, 5, 10];
+
+        let receipt = VoteReceipt::new(election, Pubkey::new_unique(), 1, 4, 255);
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![10, 1, 10]);
+
+        // a tally never goes below zero
+        assert_eq!(
+            state.remove_ballot(&receipt),
+            Err(VoteError::TallyUnderflow.into())
+        );
+
+        state.voting_method = VotingMethod::Approval;
+        let mut receipt = VoteReceipt::new(election, Pubkey::new_unique(), 0, 2, 255);
+        receipt.approvals = 0b101;
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![8, 1, 8]);
+
+        state.voting_method = VotingMethod::RankedChoice;
+        state.tabulation.ballot_count = 1;
+        let mut receipt = VoteReceipt::new(election, Pubkey::new_unique(), 0, 3, 255);
+        receipt.ranking = vec![2, 0];
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![8, 1, 5]);
+        assert_eq!(state.tabulation.ballot_count, 0);
+        assert_eq!(
+            state.remove_ballot(&receipt),
+            Err(VoteError::TallyUnderflow.into())
+        );
+        assert_eq!(state.tallies, vec![8, 1, 5]);
+
+        state.voting_method = VotingMethod::Quadratic { credits: 100 };
+        let mut receipt =
+            VoteReceipt::new_quadratic(election, Pubkey::new_unique(), 1, 255, 100, 3);
+        receipt.add_quadratic_votes(0, 5).unwrap();
+        receipt.add_quadratic_votes(1, 1).unwrap();
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![3, 0, 5]);
+
+        // an unrevealed commitment only ever counted as such
+        state.voting_method = VotingMethod::CommitReveal {
+            reveal_end_time: 200,
+        };
+        state.unrevealed_commits = 1;
+        let mut receipt = VoteReceipt::new(election, Pubkey::new_unique(), 0, 3, 255);
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![3, 0, 5]);
+        assert_eq!(state.unrevealed_commits, 0);
+        assert_eq!(
+            state.remove_ballot(&receipt),
+            Err(VoteError::TallyUnderflow.into())
+        );
+
+        receipt.is_revealed = true;
+        state.remove_ballot(&receipt).unwrap();
+        assert_eq!(state.tallies, vec![0, 0, 5]);
+    }
+
+    #[test]
+    fn test_ranked_space() {
+        for candidate_count in [MIN_CANDIDATES, 7, MAX_CANDIDATES].iter() {
+            let mut state = ElectionState::new(Pubkey::new_unique(), *candidate_count);
+            state.voting_method = VotingMethod::RankedChoice;
+            state.eligibility = Eligibility::TokenBalance(Pubkey::new_unique());
+            state.tabulation.winner = Some(0);
+            state.tabulation.rounds =
+                vec![vec![u64::MAX; *candidate_count as usize]; *candidate_count as usize - 1];
+
+            // every round fits
+            let mut data = vec![0; state.required_space()];
+            state.pack(&mut data).unwrap();
+            assert_eq!(ElectionState::unpack(&data).unwrap(), state);
+        }
+    }
+
+    #[test]
+    fn test_instant_runoff() {
+        let mut state = ElectionState::new(Pubkey::new_unique(), 4);
+        state.voting_method = VotingMethod::RankedChoice;
+
+        let rankings: &[&[u8]] = &[
+            &[0, 1],
+            &[0, 3],
+            &[0],
+            &[1, 0],
+            &[1, 0],
+            &[2, 1],
+            &[2, 1],
+            &[3],
+        ];
+        let mut receipts: Vec<VoteReceipt> = rankings
+            .iter()
+            .map(|ranking| {
+                let mut receipt =
+                    VoteReceipt::new(Pubkey::new_unique(), Pubkey::new_unique(), 0, 1, 255);
+                receipt.ranking = ranking.to_vec();
+                state.add_votes(ranking[0], 1).unwrap();
+                state.tabulation.ballot_count += 1;
+                receipt
+            })
+            .collect();
+
+        // 3/2/2/1 first preferences, 3 is eliminated
+        state.start_tabulation();
+        assert_eq!(state.tabulation.rounds, vec![vec![3, 2, 2, 1], vec![0; 4]]);
+        assert_eq!(state.tabulation.eliminated, 0b1000);
+
+        for receipt in receipts.iter_mut() {
+            assert!(state.count_ranked_ballot(receipt).unwrap());
+            // counting the same ballot twice in a round is a no-op
+            assert!(!state.count_ranked_ballot(receipt).unwrap());
+        }
+        state.advance_tabulation();
+
+        // the ballot ranking only 3 is exhausted, 1 and 2 tie for last place
+        // and the later candidate 2 goes, its ballots then elect 1 by 4 to 3
+        assert_eq!(state.tabulation.rounds[1], vec![3, 2, 2, 0]);
+        assert_eq!(state.tabulation.eliminated, 0b1100);
+        for receipt in receipts.iter_mut() {
+            state.count_ranked_ballot(receipt).unwrap();
+            state.advance_tabulation();
+        }
+        assert_eq!(state.tabulation.rounds[2], vec![3, 4, 0, 0]);
+        assert_eq!(state.tabulation.winner, Some(1));
+        assert!(!state.count_ranked_ballot(&mut receipts[0]).unwrap());
+
+        // a first round majority needs no further round
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.tallies = vec![1, 3, 1];
+        state.tabulation.ballot_count = 5;
+        state.start_tabulation();
+        assert_eq!(state.tabulation.winner, Some(1));
+        assert_eq!(state.tabulation.rounds.len(), 1);
+
+        // without ballots the earliest candidate is left standing
+        let mut state = ElectionState::new(Pubkey::new_unique(), 3);
+        state.start_tabulation();
+        assert_eq!(state.tabulation.winner, Some(0));
+    }
+
+    #[test]
+    fn test_from_legacy() {
+        let authority = Pubkey::new_unique();
+
+        let mut data = [0; LEGACY_ELECTION_LEN];
+        data[0..4].copy_from_slice(&7u32.to_le_bytes());
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let state = ElectionState::from_legacy(authority, &data).unwrap();
+        assert_eq!(state.authority, authority);
+        assert_eq!(state.candidate_count, 2);
+        assert_eq!(state.tallies, vec![7, u32::MAX as u64]);
+
+        assert_eq!(
+            ElectionState::from_legacy(authority, &data[0..4]),
+            Err(VoteError::InvalidElectionAccount.into())
+        );
+    }
+
+    #[test]
+    fn test_check_voting_open() {
+        let mut state = ElectionState::new(Pubkey::new_unique(), MIN_CANDIDATES);
+        state.start_time = 1_000;
+        state.end_time = 2_000;
+
+        assert_eq!(
+            state.check_voting_open(999),
+            Err(VoteError::VotingNotOpen.into())
+        );
+        assert_eq!(state.check_voting_open(1_000), Ok(()));
+        assert_eq!(state.check_voting_open(1_999), Ok(()));
+        assert_eq!(
+            state.check_voting_open(2_000),
+            Err(VoteError::ElectionClosed.into())
+        );
+
+        state.is_finalized = true;
+        assert_eq!(
+            state.check_voting_open(1_500),
+            Err(VoteError::ElectionFinalized.into())
+        );
+    }
+
+    #[test]
+    fn test_check_reveal_open() {
+        let mut state = ElectionState::new(Pubkey::new_unique(), MIN_CANDIDATES);
+        state.end_time = 2_000;
+        assert_eq!(
+            state.check_reveal_open(2_000),
+            Err(VoteError::WrongVotingMethod.into())
+        );
+
+        state.voting_method = VotingMethod::CommitReveal {
+            reveal_end_time: 3_000,
+        };
+        assert_eq!(
+            state.check_reveal_open(1_999),
+            Err(VoteError::VotingInProgress.into())
+        );
+        assert_eq!(state.check_reveal_open(2_000), Ok(()));
+        assert_eq!(state.check_reveal_open(2_999), Ok(()));
+        assert_eq!(
+            state.check_reveal_open(3_000),
+            Err(VoteError::ElectionClosed.into())
+        );
+
+        state.is_finalized = true;
+        assert_eq!(
+            state.check_reveal_open(2_500),
+            Err(VoteError::ElectionFinalized.into())
+        );
+    }
+
+    #[test]
+    fn test_vote_commitment() {
+        let salt = [9; 32];
+        assert_eq!(vote_commitment(1, &salt), vote_commitment(1, &salt));
+        assert_ne!(vote_commitment(1, &salt), vote_commitment(2, &salt));
+        assert_ne!(vote_commitment(1, &salt), vote_commitment(1, &[8; 32]));
+    }
+
+    #[test]
+    fn test_add_votes() {
+        let mut state = ElectionState::new(Pubkey::new_unique(), MIN_CANDIDATES);
+
+        // counts keep going past the old u32 limit
+        state.tallies[0] = u32::MAX as u64;
+        state.add_votes(0, 1).unwrap();
+        assert_eq!(state.tallies[0], u32::MAX as u64 + 1);
+
+        // weighted votes add up and overflow the same way
+        state.add_votes(1, 40).unwrap();
+        state.add_votes(1, 2).unwrap();
+        assert_eq!(state.tallies[1], 42);
+
+        state.tallies[1] = u64::MAX - 41;
+        assert_eq!(state.add_votes(1, 42), Err(VoteError::TallyOverflow.into()));
+        assert_eq!(state.tallies[1], u64::MAX - 41);
+
+        assert_eq!(
+            state.add_votes(MIN_CANDIDATES, 1),
+            Err(VoteError::InvalidCandidate.into())
+        );
+    }
+
+    #[test]
+    fn test_add_quadratic_votes() {
+        let mut receipt =
+            VoteReceipt::new_quadratic(Pubkey::new_unique(), Pubkey::new_unique(), 1, 255, 100, 3);
+
+        // 3 votes cost 9, topping up to 5 costs another 16
+        receipt.add_quadratic_votes(0, 3).unwrap();
+        assert_eq!(receipt.credits_remaining, 91);
+        receipt.add_quadratic_votes(0, 2).unwrap();
+        assert_eq!(receipt.credits_remaining, 75);
+
+        // a failed purchase leaves the receipt untouched
+        assert_eq!(
+            receipt.add_quadratic_votes(1, 9),
+            Err(VoteError::InsufficientCredits.into())
+        );
+        receipt.add_quadratic_votes(1, 8).unwrap();
+        assert_eq!(receipt.votes, vec![5, 8, 0]);
+        assert_eq!(receipt.credits_remaining, 11);
+
+        assert_eq!(
+            receipt.add_quadratic_votes(2, u32::MAX),
+            Err(VoteError::InsufficientCredits.into())
+        );
+        assert_eq!(
+            receipt.add_quadratic_votes(3, 1),
+            Err(VoteError::InvalidCandidate.into())
+        );
+    }
+
+    #[test]
+    fn test_receipt_pack_unpack() {
+        let receipt = VoteReceipt::new(Pubkey::new_unique(), Pubkey::new_unique(), 3, 7, 255);
+
+        let mut data = vec![0; VoteReceipt::LEN];
+        receipt.pack(&mut data).unwrap();
+        assert_eq!(VoteReceipt::unpack_unchecked(&data).unwrap(), receipt);
+
+        let empty = VoteReceipt::unpack_unchecked(&[0; VoteReceipt::LEN]).unwrap();
+        assert!(!empty.is_initialized());
+
+        let receipt = VoteReceipt::new_quadratic(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            2,
+            255,
+            50,
+            MAX_CANDIDATES,
+        );
+        let mut data = vec![0; VoteReceipt::quadratic_space(MAX_CANDIDATES)];
+        receipt.pack(&mut data).unwrap();
+        assert_eq!(VoteReceipt::unpack_unchecked(&data).unwrap(), receipt);
+    }
+
+    #[test]
+    fn test_anonymous_pack_unpack() {
+        let mut state = ElectionState::new(Pubkey::new_unique(), MAX_CANDIDATES);
+        state.eligibility = Eligibility::AnonymousGroup([7; 32]);
+        state.verifying_key = vec![9; VERIFYING_KEY_LEN];
+        assert_eq!(
+            state.required_space(),
+            ElectionState::anonymous_space(MAX_CANDIDATES)
+        );
+
+        let mut data = vec![0; state.required_space()];
+        state.pack(&mut data).unwrap();
+        assert_eq!(ElectionState::unpack(&data).unwrap(), state);
+
+        let record = NullifierRecord::new(Pubkey::new_unique(), [5; 32], 3, 255);
+        let mut data = vec![0; NullifierRecord::LEN];
+        record.pack(&mut data).unwrap();
+        assert_eq!(NullifierRecord::unpack_unchecked(&data).unwrap(), record);
+
+        let empty = NullifierRecord::unpack_unchecked(&[0; NullifierRecord::LEN]).unwrap();
+        assert!(!empty.is_initialized());
+    }
+
+    #[test]
+    fn test_nonce_pack_unpack() {
+        let record = NonceRecord::new(Pubkey::new_unique(), Pubkey::new_unique(), 7, 255);
+        let mut data = vec![0; NonceRecord::LEN];
+        record.pack(&mut data).unwrap();
+        assert_eq!(NonceRecord::unpack_unchecked(&data).unwrap(), record);
+
+        let empty = NonceRecord::unpack_unchecked(&[0; NonceRecord::LEN]).unwrap();
+        assert!(!empty.is_initialized());
+    }
+
+    #[test]
+    fn test_unpack_validates_header() {
+        let data = vec![0; ElectionState::space(MIN_CANDIDATES)];
+        assert_eq!(
+            ElectionState::unpack(&data),
+            Err(VoteError::ElectionNotInitialized.into())
+        );
+
+        let mut data = vec![0; ElectionState::space(MIN_CANDIDATES)];
+        let mut state = ElectionState::new(Pubkey::new_unique(), MIN_CANDIDATES);
+        state.version = ELECTION_STATE_VERSION + 1;
+        state.pack(&mut data).unwrap();
+        assert_eq!(
+            ElectionState::unpack(&data),
+            Err(VoteError::InvalidElectionAccount.into())
+        );
+
+        let mut state = ElectionState::new(Pubkey::new_unique(), MIN_CANDIDATES);
+        state.discriminator = *b"receipt_";
+        state.pack(&mut data).unwrap();
+        assert_eq!(
+            ElectionState::unpack(&data),
+            Err(VoteError::InvalidElectionAccount.into())
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Relays signed ballots to the program running in a local bank
+//!
+//! Runs as its own test binary since `solana-program-test` replaces the
+//! syscall stubs the processor tests rely on.
+
+use solana_bpf_simplest::{
+    processor::process,
+    relayer::{Client, RelayedBallot, Relayer},
+    signed_ballot::SignedBallot,
+    state::{find_receipt_address, ElectionState, VoteReceipt},
+};
+use solana_program_test::{processor, tokio::runtime::Runtime, BanksClient, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+    transaction_context::TransactionReturnData,
+};
+use std::{collections::HashSet, time::Duration};
+
+// blocking access to a bank, as the relayer uses an RPC node
+struct Bank {
+    runtime: Runtime,
+    client: BanksClient,
+}
+
+impl Client for Bank {
+    fn latest_blockhash(&mut self) -> Result<Hash, String> {
+        self.runtime
+            .block_on(self.client.get_latest_blockhash())
+            .map_err(|e| e.to_string())
+    }
+
+    fn send_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<Option<TransactionReturnData>, String> {
+        let processed = self
+            .runtime
+            .block_on(
+                self.client
+                    .process_transaction_with_metadata(transaction.clone()),
+            )
+            .map_err(|e| e.to_string())?;
+        processed.result.map_err(|e| e.to_string())?;
+        Ok(processed.metadata.and_then(|metadata| metadata.return_data))
+    }
+}
+
+impl Bank {
+    fn account(&mut self, address: Pubkey) -> Option<Account> {
+        self.runtime
+            .block_on(self.client.get_account(address))
+            .unwrap()
+    }
+
+    fn tallies(&mut self, election: Pubkey) -> Vec<u64> {
+        ElectionState::unpack(&self.account(election).unwrap().data)
+            .unwrap()
+            .tallies
+    }
+}
+
+fn sign_ballot(voter: &Keypair, election: Pubkey, candidate: u8, nonce: u64) -> RelayedBallot {
+    let ballot = SignedBallot {
+        election,
+        candidate,
+        nonce,
+    };
+    RelayedBallot {
+        voter: voter.pubkey(),
+        ballot,
+        signature: voter.sign_message(&ballot.message()).into(),
+    }
+}
+
+#[test]
+fn test_relay_signed_ballots() {
+    let program_id = Pubkey::new_unique();
+    let election = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new("solana_bpf_simplest", program_id, processor!(process));
+    let mut data = vec![0; ElectionState::space(3)];
+    ElectionState::new(Pubkey::new_unique(), 3)
+        .pack(&mut data)
+        .unwrap();
+    program_test.add_account(
+        election,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+
+    let runtime = Runtime::new().unwrap();
+    let (client, sponsor, _) = runtime.block_on(program_test.start());
+    // the bank answers at once, no need to wait between rounds
+    let mut relayer = Relayer::new(Bank { runtime, client }, program_id, sponsor)
+        .with_retry_delay(Duration::from_millis(1));
+
+    // ballots reach the relayer as JSON and are checked before relaying
+
+    let voters: Vec<_> = (0..7).map(|_| Keypair::new()).collect();
+    let mut ballots: Vec<_> = voters
+        .iter()
+        .enumerate()
+        .map(|(index, voter)| {
+            let json = sign_ballot(voter, election, index as u8 % 3, 1).to_json();
+            RelayedBallot::from_json(&json).unwrap()
+        })
+        .collect();
+    let mut forged = sign_ballot(&voters[0], election, 0, 2);
+    forged.ballot.candidate = 1;
+    assert!(RelayedBallot::from_json(&forged.to_json()).is_err());
+
+    // several ballots share each transaction
+
+    let first: Vec<_> = ballots.iter().collect();
+    let batches = relayer.batches(&first);
+    assert!(batches.len() > 1 && batches.len() < ballots.len());
+
+    // a ballot the program rejects is skipped without holding back the
+    // ballots batched with it, which are cast the first time, and only it
+    // is retried, repeats are dropped
+
+    ballots.insert(1, sign_ballot(&voters[0], election, 7, 2));
+    ballots.push(ballots[0].clone());
+    let unique: Vec<_> = ballots[..ballots.len() - 1].iter().collect();
+    let first_round = relayer.batches(&unique).len();
+    let report = relayer.relay(ballots.clone());
+    assert_eq!(report.cast.len(), 7);
+    let transactions: HashSet<_> = report.cast.iter().map(|(_, signature)| signature).collect();
+    assert_eq!(transactions.len(), first_round);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, ballots[1]);
+    assert!(report.failed[0].1.starts_with("rejected in transaction"));
+
+    let bank = relayer.client_mut();
+    assert_eq!(bank.tallies(election), vec![3, 2, 2]);
+    let (receipt, _) = find_receipt_address(&program_id, &election, &voters[4].pubkey());
+    let receipt = VoteReceipt::unpack(&bank.account(receipt).unwrap().data).unwrap();
+    assert_eq!((receipt.voter, receipt.candidate), (voters[4].pubkey(), 1));
+
+    // a cast ballot can't be relayed again
+
+    let report = relayer.relay(vec![ballots[2].clone()]);
+    assert!(report.cast.is_empty());
+    assert_eq!(relayer.client_mut().tallies(election), vec![3, 2, 2]);
+}